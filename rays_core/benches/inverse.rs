@@ -0,0 +1,70 @@
+//! Compares the 4x4 inverses against the cofactor expansion they replaced:
+//! `cargo bench -p rays_core --bench inverse`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use rays_core::matrices::Matrix4x4;
+use rays_core::transformations::*;
+
+// the original algorithm: one 3x3 determinant per cofactor
+fn cofactor_inverse(m: &Matrix4x4) -> Option<Matrix4x4> {
+    let det = m.determinant();
+    if det == 0. {
+        return None;
+    }
+
+    let mut inv = [[0.; 4]; 4];
+    for (r, row) in inv.iter_mut().enumerate() {
+        for (c, val) in row.iter_mut().enumerate() {
+            // transposed
+            let minor = m.submatrix(c, r).determinant();
+            *val = if (r + c) % 2 == 0 { minor } else { -minor } / det;
+        }
+    }
+
+    Some(Matrix4x4::new(inv))
+}
+
+fn bench_inverse(c: &mut Criterion) {
+    let affine = translation(1., -2., 3.5) * rotation_y(0.7) * scaling(2., 1., 0.5);
+    let general = Matrix4x4::new([
+        [-5., 2., 6., -8.],
+        [1., -5., 1., 8.],
+        [7., 7., -6., -7.],
+        [1., -3., 7., 4.],
+    ]);
+
+    let mut group = c.benchmark_group("inverse");
+    group.bench_function("cofactor", |bench| {
+        bench.iter(|| cofactor_inverse(black_box(&general)))
+    });
+    group.bench_function("closed_form", |bench| {
+        bench.iter(|| black_box(&general).inverse())
+    });
+    group.bench_function("gauss_jordan", |bench| {
+        bench.iter(|| black_box(&general).inverse_gauss_jordan())
+    });
+    group.bench_function("affine", |bench| {
+        bench.iter(|| black_box(&affine).inverse_affine())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("determinant");
+    group.bench_function("cofactor", |bench| {
+        bench.iter(|| {
+            let m = black_box(&general);
+            (0..4)
+                .map(|c| {
+                    m[0][c] * m.submatrix(0, c).determinant() * if c % 2 == 0 { 1. } else { -1. }
+                })
+                .sum::<f64>()
+        })
+    });
+    group.bench_function("closed_form", |bench| {
+        bench.iter(|| black_box(&general).determinant())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_inverse);
+criterion_main!(benches);
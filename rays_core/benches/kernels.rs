@@ -0,0 +1,116 @@
+//! Compares the dispatched kernels (SIMD with `--features simd`) against the
+//! scalar loops: `cargo bench -p rays_core --features rays_core/simd`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use rays_core::matrices::Matrix4x4;
+use rays_core::simd::{scalar, Mat4};
+use rays_core::transformations::*;
+use rays_core::tuples::{point, tuple, vector};
+use rays_core::util::Float;
+
+fn transform<T: Float>() -> Mat4<T> {
+    let m: Matrix4x4<T> = translation(T::ONE, T::from_f64(-2.), T::from_f64(3.5))
+        * rotation_y(T::from_f64(0.7))
+        * scaling(T::from_f64(2.), T::ONE, T::from_f64(0.5));
+
+    let mut out = [[T::ZERO; 4]; 4];
+    for (r, row) in out.iter_mut().enumerate() {
+        row.copy_from_slice(&m[r]);
+    }
+    out
+}
+
+fn bench_precision<T: Float>(c: &mut Criterion, name: &str) {
+    let (a, b) = (transform::<T>(), transform::<T>());
+    let v = [T::ONE, T::from_f64(2.), T::from_f64(-3.), T::ONE];
+    let w = [T::from_f64(0.5), T::from_f64(-1.), T::from_f64(4.), T::ZERO];
+    let (v3, w3) = ([v[0], v[1], v[2]], [w[0], w[1], w[2]]);
+
+    let mut group = c.benchmark_group(format!("{}/mat4_mul", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::mat4_mul(black_box(&a), black_box(&b)))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::mat4_mul(black_box(&a), black_box(&b)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/mat4_mul_vec4", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::mat4_mul_vec4(black_box(&a), black_box(v)))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::mat4_mul_vec4(black_box(&a), black_box(v)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/mat4_transform3", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::mat4_transform3(black_box(&a), black_box(v3), T::ONE))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::mat4_transform3(black_box(&a), black_box(v3), T::ONE))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/dot4", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::dot4(black_box(v), black_box(w)))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::dot4(black_box(v), black_box(w)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/cross3", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::cross3(black_box(v3), black_box(w3)))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::cross3(black_box(v3), black_box(w3)))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}/add4", name));
+    group.bench_function("scalar", |bench| {
+        bench.iter(|| scalar::add4(black_box(v), black_box(w)))
+    });
+    group.bench_function("kernel", |bench| {
+        bench.iter(|| T::add4(black_box(v), black_box(w)))
+    });
+    group.finish();
+}
+
+/// The operators as used by rendering code, through whichever backend is
+/// compiled in
+fn bench_operators(c: &mut Criterion) {
+    let m: Matrix4x4 = translation(1., -2., 3.5) * rotation_y(0.7) * scaling(2., 1., 0.5);
+    let p = point(1., 2., -3.);
+    let d = vector(0.5, -1., 4.);
+    let t = tuple(1., 2., -3., 1.);
+
+    let mut group = c.benchmark_group("operators");
+    group.bench_function("matrix * matrix", |bench| {
+        bench.iter(|| black_box(m) * black_box(m))
+    });
+    group.bench_function("matrix * tuple", |bench| {
+        bench.iter(|| black_box(m) * black_box(t))
+    });
+    group.bench_function("transform ray", |bench| {
+        bench.iter(|| (black_box(m) * black_box(p), black_box(m) * black_box(d)))
+    });
+    group.bench_function("tuple + tuple", |bench| {
+        bench.iter(|| black_box(t) + black_box(t))
+    });
+    group.finish();
+}
+
+fn kernels(c: &mut Criterion) {
+    bench_precision::<f64>(c, "f64");
+    bench_precision::<f32>(c, "f32");
+    bench_operators(c);
+}
+
+criterion_group!(benches, kernels);
+criterion_main!(benches);
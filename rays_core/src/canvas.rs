@@ -1,14 +1,76 @@
-use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::slice::{ChunksExact, ChunksExactMut};
 use std::vec::Vec;
 
 use crate::color::Color;
+use crate::image::{ExportOptions, ImageError, Transfer};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanvasError {
+    OutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanvasError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "pixel ({}, {}) is outside of the {}x{} canvas",
+                x, y, width, height
+            ),
+            CanvasError::SizeMismatch { expected, found } => write!(
+                f,
+                "expected a {}x{} canvas, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+        }
+    }
+}
+
+impl Error for CanvasError {}
 
+/// Size and channel layout of a canvas, without the pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CanvasMetadata {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: bool,
+}
+
+/// A grid of linear-light colors.
+///
+/// The origin (0, 0) is the top left pixel and y grows downwards, matching
+/// the row order of the exported images.
+///
+/// Pixels are always stored linear; transfer functions are only applied
+/// when exporting to (or importing from) 8-bit images.
+///
+/// A canvas may carry an alpha channel (coverage). Colors on such a canvas
+/// are premultiplied by their alpha.
 #[derive(Debug)]
 pub struct Canvas {
     pub width: u32,
     pub height: u32,
     pub size: u32,
-    pixels: Vec<Color>,
+    pub(crate) pixels: Vec<Color>,
+    pub(crate) alpha: Option<Vec<f64>>,
 }
 
 impl Canvas {
@@ -18,44 +80,186 @@ impl Canvas {
             height,
             size: (width * height),
             pixels: vec![initial_color.unwrap_or(Color::new_black()); (width * height) as usize],
+            alpha: None,
+        }
+    }
+
+    /// Creates a fully transparent canvas with an alpha channel
+    pub fn new_transparent(width: u32, height: u32) -> Canvas {
+        let mut canvas = Canvas::new(width, height, None);
+        canvas.alpha = Some(vec![0.0; canvas.size as usize]);
+
+        canvas
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
+    pub fn metadata(&self) -> CanvasMetadata {
+        CanvasMetadata {
+            width: self.width,
+            height: self.height,
+            alpha: self.has_alpha(),
         }
     }
 
-    fn pixel_pos(&self, x: u32, y: u32) -> u32 {
-        (y * self.width) + cmp::min(x, self.width)
+    /// A black canvas laid out as `metadata` describes, transparent when
+    /// it has alpha
+    pub fn from_metadata(metadata: CanvasMetadata) -> Canvas {
+        if metadata.alpha {
+            Canvas::new_transparent(metadata.width, metadata.height)
+        } else {
+            Canvas::new(metadata.width, metadata.height, None)
+        }
+    }
+
+    /// Adds an opaque alpha channel if the canvas has none
+    pub fn enable_alpha(&mut self) {
+        if self.alpha.is_none() {
+            self.alpha = Some(vec![1.0; self.size as usize]);
+        }
     }
 
+    fn pixel_pos(&self, x: u32, y: u32) -> Result<usize, CanvasError> {
+        if x < self.width && y < self.height {
+            Ok((y * self.width + x) as usize)
+        } else {
+            Err(CanvasError::OutOfBounds {
+                x,
+                y,
+                width: self.width,
+                height: self.height,
+            })
+        }
+    }
+
+    /// Writes a color, ignoring coordinates outside of the canvas
     pub fn write_pixel(&mut self, x: u32, y: u32, color: Color) {
-        let mut pos;
+        let _ = self.try_write_pixel(x, y, color);
+    }
+
+    pub fn try_write_pixel(&mut self, x: u32, y: u32, color: Color) -> Result<(), CanvasError> {
+        let pos = self.pixel_pos(x, y)?;
+        self.pixels[pos] = color;
+
+        if let Some(alpha) = self.alpha.as_mut() {
+            alpha[pos] = 1.0;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a color without checking the coordinates.
+    ///
+    /// # Safety
+    ///
+    /// `x` must be less than `width` and `y` less than `height`.
+    pub unsafe fn write_pixel_unchecked(&mut self, x: u32, y: u32, color: Color) {
+        debug_assert!(x < self.width && y < self.height);
+        let pos = (y * self.width + x) as usize;
+
+        *self.pixels.get_unchecked_mut(pos) = color;
+
+        if let Some(alpha) = self.alpha.as_mut() {
+            *alpha.get_unchecked_mut(pos) = 1.0;
+        }
+    }
 
-        pos = self.pixel_pos(x, self.height - y);
-        if pos > self.size {
-            pos = self.size - 1
+    /// Writes a premultiplied color with its coverage, adding an alpha
+    /// channel to the canvas if necessary.
+    pub fn write_pixel_with_alpha(&mut self, x: u32, y: u32, color: Color, alpha: f64) {
+        let pos = match self.pixel_pos(x, y) {
+            Ok(pos) => pos,
+            Err(_) => return,
         };
 
-        self.pixels[pos as usize] = color;
+        self.enable_alpha();
+        self.pixels[pos] = color;
+
+        if let Some(a) = self.alpha.as_mut() {
+            a[pos] = alpha;
+        }
     }
 
-    pub fn pixel_at(&self, x: u32, y: u32) -> Option<Color> {
-        let pos;
+    /// The coverage at (x, y); pixels of canvases without alpha are opaque
+    pub fn alpha_at(&self, x: u32, y: u32) -> Option<f64> {
+        self.pixel_pos(x, y)
+            .ok()
+            .map(|pos| self.alpha_at_index(pos))
+    }
+
+    pub(crate) fn alpha_at_index(&self, i: usize) -> f64 {
+        self.alpha.as_ref().map_or(1.0, |a| a[i])
+    }
 
-        pos = self.pixel_pos(x, y);
-        if pos > self.size {
-            return None;
+    /// The color at index `i` divided by its alpha
+    pub(crate) fn straight_color_at_index(&self, i: usize) -> Color {
+        let alpha = self.alpha_at_index(i);
+
+        if alpha <= 0.0 {
+            Color::new_black()
+        } else if alpha >= 1.0 {
+            self.pixels[i]
+        } else {
+            self.pixels[i] * (1.0 / alpha)
         }
+    }
+
+    pub fn pixel_at(&self, x: u32, y: u32) -> Option<Color> {
+        self.get(x, y).ok()
+    }
 
-        Some(self.pixels[pos as usize])
+    pub fn get(&self, x: u32, y: u32) -> Result<Color, CanvasError> {
+        let pos = self.pixel_pos(x, y)?;
+
+        Ok(self.pixels[pos])
+    }
+
+    /// Reads a color without checking the coordinates.
+    ///
+    /// # Safety
+    ///
+    /// `x` must be less than `width` and `y` less than `height`.
+    pub unsafe fn pixel_at_unchecked(&self, x: u32, y: u32) -> Color {
+        debug_assert!(x < self.width && y < self.height);
+
+        *self.pixels.get_unchecked((y * self.width + x) as usize)
+    }
+
+    /// The rows of the canvas, top to bottom
+    pub fn rows(&self) -> ChunksExact<'_, Color> {
+        self.pixels.chunks_exact(self.width.max(1) as usize)
     }
 
-    fn get_clamped_values(pixel: &Color, min: f64, max: f64) -> (u8, u8, u8) {
-        (
-            clamp(pixel.r * 256., min, max) as u8,
-            clamp(pixel.g * 256., min, max) as u8,
-            clamp(pixel.b * 256., min, max) as u8,
-        )
+    pub fn rows_mut(&mut self) -> ChunksExactMut<'_, Color> {
+        self.pixels.chunks_exact_mut(self.width.max(1) as usize)
+    }
+
+    /// Every pixel with its (x, y) coordinates, in row order
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, &Color)> {
+        let width = self.width;
+
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(i, c)| (i as u32 % width, i as u32 / width, c))
+    }
+
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (u32, u32, &mut Color)> {
+        let width = self.width;
+
+        self.pixels
+            .iter_mut()
+            .enumerate()
+            .map(move |(i, c)| (i as u32 % width, i as u32 / width, c))
     }
 
     pub fn to_ppm(&self) -> String {
+        self.to_ppm_with(&ExportOptions::default())
+    }
+
+    pub fn to_ppm_with(&self, options: &ExportOptions) -> String {
         const ROW_BREAK: usize = 70;
 
         let header = format!("P3\n{} {}\n255\n", self.width, self.height);
@@ -63,8 +267,9 @@ impl Canvas {
         let mut body = String::with_capacity(self.pixels.len() * 4 + header.len());
         let mut row_length = 0;
 
-        for (i, pixel) in self.pixels.iter().enumerate() {
-            let (r, g, b) = Self::get_clamped_values(pixel, 0.0, 255.0);
+        let pixels = self.to_rgba8(options, false);
+
+        for (i, [r, g, b, _]) in pixels.iter().enumerate() {
             let triad = &format!("{} {} {} ", r, g, b)[..];
 
             row_length += triad.len();
@@ -79,7 +284,7 @@ impl Canvas {
             }
 
             // End with newline
-            if i == self.pixels.len() - 1 {
+            if i == pixels.len() - 1 {
                 body.truncate(body.len() - 1);
                 body.push('\n');
             }
@@ -87,28 +292,183 @@ impl Canvas {
 
         header + &body
     }
+
+    /// Binary (P6) PPM, three bytes per pixel
+    pub fn to_ppm_binary(&self, options: &ExportOptions) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+
+        for [r, g, b, _] in self.to_rgba8(options, false) {
+            ppm.extend_from_slice(&[r, g, b]);
+        }
+
+        ppm
+    }
+
+    /// Reads a plain (P3) or binary (P6) PPM, decoding its samples to linear
+    /// light with `transfer`.
+    pub fn from_ppm(data: &[u8], transfer: Transfer) -> Result<Canvas, ImageError> {
+        let mut reader = PpmReader { data, pos: 0 };
+
+        let binary = match reader.token()? {
+            b"P3" => false,
+            b"P6" => true,
+            _ => return Err(ImageError::UnsupportedFormat),
+        };
+
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let max_value = reader.number()?;
+
+        if max_value == 0 || max_value > 255 {
+            return Err(ImageError::Malformed(format!(
+                "unsupported max value {}",
+                max_value
+            )));
+        }
+
+        // a single whitespace byte separates the header from binary samples
+        if binary {
+            reader.pos += 1;
+        }
+
+        // the header is checked against the data before allocating, so it
+        // can't overflow or ask for more memory than the file could fill.
+        // Binary samples take a byte each, plain ones at least a digit and
+        // a separator.
+        let too_large = || ImageError::Malformed(format!("{}x{} is too large", width, height));
+        let pixels = width.checked_mul(height).ok_or_else(too_large)?;
+        let min_len = (pixels as usize)
+            .checked_mul(if binary { 3 } else { 6 })
+            .ok_or_else(too_large)?;
+        if min_len > data.len().saturating_sub(reader.pos) {
+            return Err(ImageError::UnexpectedEof);
+        }
+
+        let mut canvas = Canvas::new(width, height, None);
+        let max_value = max_value as f64;
+
+        for pixel in canvas.pixels.iter_mut() {
+            let mut channels = [0.; 3];
+
+            for channel in channels.iter_mut() {
+                let sample = if binary {
+                    reader.byte()? as u32
+                } else {
+                    reader.number()?
+                };
+
+                if sample as f64 > max_value {
+                    return Err(ImageError::Malformed(format!(
+                        "sample {} exceeds max value",
+                        sample
+                    )));
+                }
+
+                *channel = transfer.decode_channel(sample as f64 / max_value);
+            }
+
+            *pixel = Color::new(channels[0], channels[1], channels[2]);
+        }
+
+        Ok(canvas)
+    }
+}
+
+impl Index<(u32, u32)> for Canvas {
+    type Output = Color;
+
+    fn index(&self, (x, y): (u32, u32)) -> &Color {
+        match self.pixel_pos(x, y) {
+            Ok(pos) => &self.pixels[pos],
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+/// Gives access to the stored, premultiplied color. Unlike `write_pixel`
+/// the coverage is left as is, so `canvas[(x, y)] *= 0.5` darkens a pixel
+/// without making it opaque.
+impl IndexMut<(u32, u32)> for Canvas {
+    fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut Color {
+        match self.pixel_pos(x, y) {
+            Ok(pos) => &mut self.pixels[pos],
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+struct PpmReader<'a> {
+    data: &'a [u8],
+    pos: usize,
 }
 
-fn clamp<T: PartialOrd>(input: T, min: T, max: T) -> T {
-    if input < min {
-        min
-    } else if input > max {
-        max
-    } else {
-        input
+impl<'a> PpmReader<'a> {
+    fn byte(&mut self) -> Result<u8, ImageError> {
+        let b = *self.data.get(self.pos).ok_or(ImageError::UnexpectedEof)?;
+        self.pos += 1;
+
+        Ok(b)
+    }
+
+    fn token(&mut self) -> Result<&'a [u8], ImageError> {
+        // skip whitespace and comments
+        while let Some(&b) = self.data.get(self.pos) {
+            if b == b'#' {
+                while self.pos < self.data.len() && self.data[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+
+        if start == self.pos {
+            return Err(ImageError::UnexpectedEof);
+        }
+
+        Ok(&self.data[start..self.pos])
+    }
+
+    fn number(&mut self) -> Result<u32, ImageError> {
+        let token = self.token()?;
+
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| {
+                ImageError::Malformed(format!(
+                    "expected a number, found {:?}",
+                    String::from_utf8_lossy(token)
+                ))
+            })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tonemap::{ToneMap, ToneMapOperator};
+
+    fn linear() -> ExportOptions {
+        ExportOptions {
+            transfer: Transfer::Linear,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn creating_a_canvas() {
         let c = Canvas::new(10, 20, None);
         let black = Color::new_black();
 
-        assert!(c.pixels.iter().any(|&color| color == black));
+        assert!(c.pixels.contains(&black));
     }
 
     #[test]
@@ -125,6 +485,98 @@ mod tests {
         assert_eq!(c.pixel_at(0, 19), Some(red));
     }
 
+    #[test]
+    fn out_of_bounds_writes_do_not_alias_other_pixels() {
+        let mut c = Canvas::new(10, 20, None);
+        let red = Color::new(1.0, 0.0, 0.0);
+
+        // x == width used to land on the first pixel of the next row
+        c.write_pixel(10, 0, red);
+        c.write_pixel(3, 20, red);
+
+        assert!(c.pixels.iter().all(|&p| p == Color::new_black()));
+    }
+
+    #[test]
+    fn fallible_pixel_access() {
+        let mut c = Canvas::new(4, 2, None);
+        let red = Color::new(1.0, 0.0, 0.0);
+
+        assert_eq!(c.try_write_pixel(3, 1, red), Ok(()));
+        assert_eq!(c.get(3, 1), Ok(red));
+
+        let err = CanvasError::OutOfBounds {
+            x: 4,
+            y: 1,
+            width: 4,
+            height: 2,
+        };
+        assert_eq!(c.try_write_pixel(4, 1, red), Err(err.clone()));
+        assert_eq!(c.get(4, 1), Err(err.clone()));
+        assert_eq!(err.to_string(), "pixel (4, 1) is outside of the 4x2 canvas");
+    }
+
+    #[test]
+    fn unchecked_pixel_access() {
+        let mut c = Canvas::new(4, 2, None);
+        let red = Color::new(1.0, 0.0, 0.0);
+
+        unsafe {
+            c.write_pixel_unchecked(2, 1, red);
+            assert_eq!(c.pixel_at_unchecked(2, 1), red);
+        }
+        assert_eq!(c.pixel_at(2, 1), Some(red));
+    }
+
+    #[test]
+    fn indexing_by_coordinates() {
+        let mut c = Canvas::new(4, 2, None);
+        let red = Color::new(1.0, 0.0, 0.0);
+
+        c[(1, 1)] = red;
+
+        assert_eq!(c[(1, 1)], red);
+        assert_eq!(c.pixels[5], red);
+
+        let mut c = Canvas::new_transparent(4, 2);
+        c[(1, 1)] = red;
+
+        assert_eq!(c.alpha_at(1, 1), Some(0.0));
+        c.write_pixel(1, 1, red);
+        assert_eq!(c.alpha_at(1, 1), Some(1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "pixel (4, 0) is outside of the 4x2 canvas")]
+    fn indexing_out_of_bounds_panics() {
+        let c = Canvas::new(4, 2, None);
+        let _ = c[(4, 0)];
+    }
+
+    #[test]
+    fn iterating_rows_and_pixels() {
+        let mut c = Canvas::new(3, 2, None);
+        let red = Color::new(1.0, 0.0, 0.0);
+        c.write_pixel(2, 1, red);
+
+        let rows: Vec<&[Color]> = c.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][2], red);
+
+        let (x, y, _) = c.enumerate_pixels().find(|(_, _, &p)| p == red).unwrap();
+        assert_eq!((x, y), (2, 1));
+
+        for (x, y, p) in c.enumerate_pixels_mut() {
+            *p = Color::new(x as f64, y as f64, 0.0);
+        }
+        assert_eq!(c[(1, 1)], Color::new(1.0, 1.0, 0.0));
+
+        for row in c.rows_mut() {
+            row[0] = red;
+        }
+        assert_eq!(c[(0, 1)], red);
+    }
+
     #[test]
     fn constructing_ppm_header() {
         let c = Canvas::new(5, 3, None);
@@ -147,7 +599,7 @@ mod tests {
         c.write_pixel(2, 1, c2);
         c.write_pixel(4, 2, c3);
 
-        let ppm = c.to_ppm();
+        let ppm = c.to_ppm_with(&linear());
         let ppm_test = r#"P3
 5 3
 255
@@ -158,10 +610,23 @@ mod tests {
         assert_eq!(ppm, ppm_test);
     }
 
+    #[test]
+    fn tone_mapping_ppm_pixel_data() {
+        let options = ExportOptions {
+            tone_map: ToneMap::new(ToneMapOperator::Reinhard).with_exposure(2.0),
+            ..linear()
+        };
+        let ppm = |color| Canvas::new(1, 1, Some(color)).to_ppm_with(&options);
+
+        // exposed: (4, 12, 0) and (1, 0, 0)
+        assert_eq!(ppm(Color::new(1.0, 3.0, 0.0)), "P3\n1 1\n255\n204 235 0\n");
+        assert_eq!(ppm(Color::new(0.25, 0.0, 0.0)), "P3\n1 1\n255\n128 0 0\n");
+    }
+
     #[test]
     fn splitting_long_lines_in_ppm() {
         let c = Canvas::new(10, 2, Some(Color::new(1.0, 0.8, 0.6)));
-        let ppm = c.to_ppm();
+        let ppm = c.to_ppm_with(&linear());
         let ppm_test = r#"P3
 10 2
 255
@@ -173,5 +638,82 @@ mod tests {
 
         assert_eq!(ppm, ppm_test);
     }
-}
 
+    #[test]
+    fn ppm_export_is_srgb_encoded_by_default() {
+        let c = Canvas::new(2, 1, Some(Color::new(0.5, 0.2, 0.0)));
+
+        assert_eq!(c.to_ppm(), "P3\n2 1\n255\n188 124 0 188 124 0\n");
+    }
+
+    #[test]
+    fn reading_plain_ppm() {
+        let ppm = b"P3\n# a comment\n2 1\n255\n255 0 0\n0 128 255\n";
+        let c = Canvas::from_ppm(ppm, Transfer::Linear).unwrap();
+
+        assert_eq!(c.width, 2);
+        assert_eq!(c.height, 1);
+        assert_eq!(c.pixel_at(0, 0), Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(c.pixel_at(1, 0), Some(Color::new(0.0, 128.0 / 255.0, 1.0)));
+    }
+
+    #[test]
+    fn reading_binary_ppm_decodes_to_linear() {
+        let mut ppm = b"P6\n1 1\n255\n".to_vec();
+        ppm.extend_from_slice(&[255, 188, 0]);
+
+        let c = Canvas::from_ppm(&ppm, Transfer::Srgb).unwrap();
+        let p = c.pixel_at(0, 0).unwrap();
+
+        assert_eq!(p.r, 1.0);
+        assert!((p.g - 0.5).abs() < 0.005);
+        assert_eq!(p.b, 0.0);
+    }
+
+    #[test]
+    fn ppm_round_trip_is_lossless_for_8_bit_colors() {
+        let mut c = Canvas::new(3, 2, None);
+        c.write_pixel(0, 0, Color::new(1.0, 0.5, 0.25).to_linear());
+        c.write_pixel(2, 1, Color::new(0.1, 0.9, 0.3).to_linear());
+
+        let read = Canvas::from_ppm(c.to_ppm().as_bytes(), Transfer::Srgb).unwrap();
+
+        assert_eq!(read.to_ppm(), c.to_ppm());
+    }
+
+    #[test]
+    fn binary_ppm_round_trip() {
+        let mut c = Canvas::new(3, 2, None);
+        c.write_pixel(1, 1, Color::new(1.0, 0.5, 0.0));
+
+        let ppm = c.to_ppm_binary(&linear());
+        assert_eq!(&ppm[..11], b"P6\n3 2\n255\n");
+        assert_eq!(&ppm[11 + 12..11 + 15], &[255, 128, 0]);
+
+        let read = Canvas::from_ppm(&ppm, Transfer::Linear).unwrap();
+        assert_eq!(read.to_ppm_with(&linear()), c.to_ppm_with(&linear()));
+    }
+
+    #[test]
+    fn reading_malformed_ppm() {
+        assert_eq!(
+            Canvas::from_ppm(b"P5\n1 1\n255\n", Transfer::Linear).unwrap_err(),
+            ImageError::UnsupportedFormat
+        );
+        assert_eq!(
+            Canvas::from_ppm(b"P3\n2 1\n255\n1 2 3", Transfer::Linear).unwrap_err(),
+            ImageError::UnexpectedEof
+        );
+        assert!(Canvas::from_ppm(b"P3\n1 1\n255\n1 2 300", Transfer::Linear).is_err());
+
+        // huge headers are rejected before anything is allocated
+        assert_eq!(
+            Canvas::from_ppm(b"P6\n65536 65536\n255\n", Transfer::Linear).unwrap_err(),
+            ImageError::Malformed("65536x65536 is too large".into())
+        );
+        assert_eq!(
+            Canvas::from_ppm(b"P6\n40000 40000\n255\n\0\0\0", Transfer::Linear).unwrap_err(),
+            ImageError::UnexpectedEof
+        );
+    }
+}
@@ -1,30 +1,341 @@
 use std::cmp::PartialEq;
-use std::ops::{Add, Mul, Sub};
+use std::error::Error;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Sub};
+use std::str::FromStr;
 
-use crate::util::approx_equal;
+use crate::util::{approx_equal, Float};
 
+/// An RGB color in linear light.
+///
+/// All rendering math (shading, blending, tone mapping) happens on linear
+/// values; use `to_srgb`/`to_linear` only at the boundary to 8-bit images.
+/// Hex, HSV and HSL describe sRGB encoded colors, the way color pickers show
+/// them, and convert to and from linear light.
 #[derive(Debug, Clone, Copy)]
-pub struct Color {
-    pub r: f64,
-    pub g: f64,
-    pub b: f64,
+pub struct Color<T = f64> {
+    pub r: T,
+    pub g: T,
+    pub b: T,
 }
 
-impl Color {
-    pub fn new(r: f64, g: f64, b: f64) -> Self {
+impl<T: Float> Color<T> {
+    pub fn new(r: T, g: T, b: T) -> Self {
         Color { r, g, b }
     }
 
     pub fn new_black() -> Self {
         Color {
-            r: 0.0,
-            g: 0.0,
-            b: 0.0,
+            r: T::ZERO,
+            g: T::ZERO,
+            b: T::ZERO,
         }
     }
+
+    /// Converts to another precision
+    pub fn cast<U: Float>(&self) -> Color<U> {
+        Color::new(
+            U::from_f64(self.r.to_f64()),
+            U::from_f64(self.g.to_f64()),
+            U::from_f64(self.b.to_f64()),
+        )
+    }
+
+    /// Channel-wise comparison with an explicit tolerance
+    pub fn approx_eq(&self, other: &Color<T>, epsilon: T) -> bool {
+        (self.r - other.r).abs() <= epsilon
+            && (self.g - other.g).abs() <= epsilon
+            && (self.b - other.b).abs() <= epsilon
+    }
+
+    /// Encodes a linear color with the sRGB transfer function (OETF)
+    pub fn to_srgb(&self) -> Self {
+        let encode = |v: T| T::from_f64(linear_to_srgb(v.to_f64()));
+        Color::new(encode(self.r), encode(self.g), encode(self.b))
+    }
+
+    /// Decodes an sRGB encoded color back to linear light (EOTF)
+    pub fn to_linear(&self) -> Self {
+        let decode = |v: T| T::from_f64(srgb_to_linear(v.to_f64()));
+        Color::new(decode(self.r), decode(self.g), decode(self.b))
+    }
+
+    /// Relative luminance (Rec. 709 / sRGB primaries)
+    pub fn luminance(&self) -> T {
+        T::from_f64(0.2126) * self.r + T::from_f64(0.7152) * self.g + T::from_f64(0.0722) * self.b
+    }
+
+    /// Linear interpolation, `self` at t = 0 and `other` at t = 1
+    pub fn lerp(&self, other: &Color<T>, t: T) -> Self {
+        *self + (*other - *self) * t
+    }
+
+    /// Clamps every channel to [min, max]
+    pub fn clamp(&self, min: T, max: T) -> Self {
+        Color::new(
+            self.r.clamp(min, max),
+            self.g.clamp(min, max),
+            self.b.clamp(min, max),
+        )
+    }
+
+    /// Parses `#rgb` or `#rrggbb` (the `#` is optional) as an sRGB color
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseColorError::InvalidDigit(hex.to_string()));
+        }
+
+        let channel = |s: &str| u8::from_str_radix(s, 16).unwrap() as f64;
+
+        let (r, g, b) = match digits.len() {
+            3 => (
+                channel(&digits[0..1]) * 17.,
+                channel(&digits[1..2]) * 17.,
+                channel(&digits[2..3]) * 17.,
+            ),
+            6 => (
+                channel(&digits[0..2]),
+                channel(&digits[2..4]),
+                channel(&digits[4..6]),
+            ),
+            _ => return Err(ParseColorError::InvalidLength(hex.to_string())),
+        };
+
+        Ok(Color::new(r / 255., g / 255., b / 255.).to_linear().cast())
+    }
+
+    /// Formats as `#rrggbb`, sRGB encoded and clamped to [0, 1]
+    pub fn to_hex(&self) -> String {
+        let srgb = self.cast::<f64>().clamp(0.0, 1.0).to_srgb();
+        let level = |v: f64| (v * 255.).round() as u8;
+
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            level(srgb.r),
+            level(srgb.g),
+            level(srgb.b)
+        )
+    }
+
+    /// From hue (degrees), saturation and value, each of the latter in [0, 1]
+    pub fn from_hsv(h: T, s: T, v: T) -> Self {
+        let (h, s, v) = (h.to_f64(), s.to_f64(), v.to_f64());
+        let c = v * s;
+
+        hue_to_rgb(h, c, v - c).to_linear().cast()
+    }
+
+    /// Hue (degrees in [0, 360)), saturation and value of the sRGB encoding
+    pub fn to_hsv(&self) -> (T, T, T) {
+        let (h, min, max) = hue_min_max(&self.cast::<f64>().to_srgb());
+        let s = if max > 0.0 { (max - min) / max } else { 0.0 };
+
+        cast3((h, s, max))
+    }
+
+    /// From hue (degrees), saturation and lightness, each of the latter in [0, 1]
+    pub fn from_hsl(h: T, s: T, l: T) -> Self {
+        let (h, s, l) = (h.to_f64(), s.to_f64(), l.to_f64());
+        let c = (1. - (2. * l - 1.).abs()) * s;
+
+        hue_to_rgb(h, c, l - c / 2.).to_linear().cast()
+    }
+
+    /// Hue (degrees in [0, 360)), saturation and lightness of the sRGB encoding
+    pub fn to_hsl(&self) -> (T, T, T) {
+        let (h, min, max) = hue_min_max(&self.cast::<f64>().to_srgb());
+        let l = (max + min) / 2.;
+        let s = if max - min > 0.0 {
+            (max - min) / (1. - (2. * l - 1.).abs())
+        } else {
+            0.0
+        };
+
+        cast3((h, s, l))
+    }
+
+    /// CIE 1931 XYZ (D65 white, Y = 1 for white)
+    pub fn to_xyz(&self) -> (T, T, T) {
+        cast3(rgb_to_xyz(
+            self.r.to_f64(),
+            self.g.to_f64(),
+            self.b.to_f64(),
+        ))
+    }
+
+    pub fn from_xyz(x: T, y: T, z: T) -> Self {
+        xyz_to_rgb(x.to_f64(), y.to_f64(), z.to_f64()).cast()
+    }
+
+    /// CIE L*a*b* relative to D65 white, L in [0, 100]
+    pub fn to_lab(&self) -> (T, T, T) {
+        let (x, y, z) = rgb_to_xyz(self.r.to_f64(), self.g.to_f64(), self.b.to_f64());
+        let f = |t: f64| {
+            if t > LAB_DELTA.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3. * LAB_DELTA * LAB_DELTA) + 4. / 29.
+            }
+        };
+
+        let (fx, fy, fz) = (f(x / D65_WHITE.0), f(y / D65_WHITE.1), f(z / D65_WHITE.2));
+
+        cast3((116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)))
+    }
+
+    pub fn from_lab(l: T, a: T, b: T) -> Self {
+        let f_inv = |t: f64| {
+            if t > LAB_DELTA {
+                t.powi(3)
+            } else {
+                3. * LAB_DELTA * LAB_DELTA * (t - 4. / 29.)
+            }
+        };
+
+        let fy = (l.to_f64() + 16.) / 116.;
+
+        xyz_to_rgb(
+            D65_WHITE.0 * f_inv(fy + a.to_f64() / 500.),
+            D65_WHITE.1 * f_inv(fy),
+            D65_WHITE.2 * f_inv(fy - b.to_f64() / 200.),
+        )
+        .cast()
+    }
+
+    /// Approximate color of a black body at `kelvin` (clamped to 1667K-25000K),
+    /// normalized so the brightest channel is 1
+    pub fn from_temperature(kelvin: T) -> Self {
+        // Kang et al. (2002) cubic fits of the Planckian locus in CIE xy
+        let t = kelvin.to_f64().clamp(1667., 25000.);
+        let (t1, t2, t3) = (1e3 / t, 1e6 / (t * t), 1e9 / (t * t * t));
+
+        let x = if t <= 4000. {
+            -0.266_123_9 * t3 - 0.234_358_9 * t2 + 0.877_695_6 * t1 + 0.179_910
+        } else {
+            -3.025_846_9 * t3 + 2.107_037_9 * t2 + 0.222_634_7 * t1 + 0.240_390
+        };
+
+        let y = if t <= 2222. {
+            -1.106_381_4 * x.powi(3) - 1.348_110_20 * x * x + 2.185_558_32 * x - 0.202_196_83
+        } else if t <= 4000. {
+            -0.954_947_6 * x.powi(3) - 1.374_185_93 * x * x + 2.091_370_15 * x - 0.167_488_67
+        } else {
+            3.081_758_0 * x.powi(3) - 5.873_386_70 * x * x + 3.751_129_97 * x - 0.370_014_83
+        };
+
+        let c = xyz_to_rgb(x / y, 1.0, (1. - x - y) / y).clamp(0.0, f64::INFINITY);
+        let max = c.r.max(c.g).max(c.b);
+
+        (c / max).cast()
+    }
+}
+
+fn cast3<T: Float>((a, b, c): (f64, f64, f64)) -> (T, T, T) {
+    (T::from_f64(a), T::from_f64(b), T::from_f64(c))
+}
+
+fn rgb_to_xyz(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    (
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b,
+        0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b,
+    )
+}
+
+fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    )
+}
+
+const D65_WHITE: (f64, f64, f64) = (0.950_47, 1.0, 1.088_83);
+const LAB_DELTA: f64 = 6. / 29.;
+
+/// RGB from hue (degrees), chroma and the amount added to every channel
+fn hue_to_rgb(h: f64, c: f64, m: f64) -> Color {
+    let h = h.rem_euclid(360.) / 60.;
+    let x = c * (1. - (h % 2. - 1.).abs());
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+
+    Color::new(r + m, g + m, b + m)
+}
+
+/// Hue in degrees with the smallest and largest channel
+fn hue_min_max(c: &Color) -> (f64, f64, f64) {
+    let max = c.r.max(c.g).max(c.b);
+    let min = c.r.min(c.g).min(c.b);
+    let d = max - min;
+
+    let h = if d <= 0.0 {
+        0.0
+    } else if max == c.r {
+        60. * ((c.g - c.b) / d).rem_euclid(6.)
+    } else if max == c.g {
+        60. * ((c.b - c.r) / d + 2.)
+    } else {
+        60. * ((c.r - c.g) / d + 4.)
+    };
+
+    (h, min, max)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseColorError {
+    InvalidLength(String),
+    InvalidDigit(String),
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseColorError::InvalidLength(s) => {
+                write!(f, "'{}' is not a #rgb or #rrggbb color", s)
+            }
+            ParseColorError::InvalidDigit(s) => write!(f, "'{}' has non hex digits", s),
+        }
+    }
+}
+
+impl Error for ParseColorError {}
+
+impl<T: Float> FromStr for Color<T> {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::from_hex(s.trim())
+    }
+}
+
+pub fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
 }
 
-impl PartialEq for Color {
+impl<T: Float> PartialEq for Color<T> {
     fn eq(&self, other: &Self) -> bool {
         approx_equal(self.r, other.r)
             && approx_equal(self.g, other.g)
@@ -32,7 +343,7 @@ impl PartialEq for Color {
     }
 }
 
-impl Add for Color {
+impl<T: Float> Add for Color<T> {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
@@ -40,7 +351,7 @@ impl Add for Color {
     }
 }
 
-impl Sub for Color {
+impl<T: Float> Sub for Color<T> {
     type Output = Self;
 
     fn sub(self, other: Self) -> Self {
@@ -48,25 +359,25 @@ impl Sub for Color {
     }
 }
 
-impl Mul<f64> for Color {
+impl<T: Float> Mul<T> for Color<T> {
     type Output = Self;
 
-    fn mul(self, rhs: f64) -> Self {
+    fn mul(self, rhs: T) -> Self {
         Color::new(self.r * rhs, self.g * rhs, self.b * rhs)
     }
 }
 
-impl Mul<i32> for Color {
+impl<T: Float> Mul<i32> for Color<T> {
     type Output = Self;
 
     fn mul(self, rhs: i32) -> Self {
-        let mul = rhs as f64;
+        let mul = T::from_f64(rhs as f64);
         Color::new(self.r * mul, self.g * mul, self.b * mul)
     }
 }
 
 /* Hadamard product */
-impl Mul for Color {
+impl<T: Float> Mul for Color<T> {
     type Output = Self;
 
     fn mul(self, other: Self) -> Self {
@@ -74,6 +385,52 @@ impl Mul for Color {
     }
 }
 
+impl<T: Float> Div<T> for Color<T> {
+    type Output = Self;
+
+    fn div(self, rhs: T) -> Self {
+        Color::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+impl<T: Float> Div for Color<T> {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        Color::new(self.r / other.r, self.g / other.g, self.b / other.b)
+    }
+}
+
+impl<T: Float> AddAssign for Color<T> {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl<T: Float> MulAssign<T> for Color<T> {
+    fn mul_assign(&mut self, rhs: T) {
+        *self = *self * rhs;
+    }
+}
+
+impl<T: Float> MulAssign for Color<T> {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+impl<T: Float> Sum for Color<T> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Color::new_black(), Add::add)
+    }
+}
+
+impl<'a, T: Float> Sum<&'a Color<T>> for Color<T> {
+    fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +465,165 @@ mod tests {
 
         assert_eq!(c1 * c2, Color::new(0.9, 0.2, 0.04));
     }
+
+    #[test]
+    fn comparing_colors_with_tolerance() {
+        let c1 = Color::new(0.5, 0.5, 0.5);
+        let c2 = Color::new(0.51, 0.49, 0.5);
+
+        assert_ne!(c1, c2);
+        assert!(c1.approx_eq(&c2, 0.01 + 1e-12));
+        assert!(!c1.approx_eq(&c2, 0.005));
+    }
+
+    #[test]
+    fn encoding_and_decoding_srgb() {
+        let linear = Color::new(0.0, 0.5, 1.0);
+        let encoded = linear.to_srgb();
+
+        assert_eq!(encoded, Color::new(0.0, 0.735357, 1.0));
+        assert_eq!(encoded.to_linear(), linear);
+
+        // the linear toe of the curve
+        assert!(approx_equal(linear_to_srgb(0.002), 0.02584));
+        assert!(approx_equal(srgb_to_linear(0.02584), 0.002));
+    }
+
+    #[test]
+    fn srgb_round_trips_every_8_bit_value() {
+        for i in 0..=255 {
+            let v = i as f64 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(v)) - v).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn dividing_colors() {
+        let c = Color::new(0.4, 0.6, 0.8);
+
+        assert_eq!(c / 2.0, Color::new(0.2, 0.3, 0.4));
+        assert_eq!(c / Color::new(0.4, 0.3, 0.8), Color::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn assigning_operators_and_sums() {
+        let mut c = Color::new(0.1, 0.2, 0.3);
+        c += Color::new(0.1, 0.1, 0.1);
+        c *= 2.0;
+        c *= Color::new(1.0, 0.5, 0.0);
+
+        assert_eq!(c, Color::new(0.4, 0.3, 0.0));
+
+        let colors = vec![Color::new(0.1, 0.0, 0.0), Color::new(0.0, 0.2, 0.3)];
+        assert_eq!(colors.iter().sum::<Color>(), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(colors.into_iter().sum::<Color>(), Color::new(0.1, 0.2, 0.3));
+    }
+
+    #[test]
+    fn luminance_lerp_and_clamp() {
+        assert!(approx_equal(Color::new(1.0, 1.0, 1.0).luminance(), 1.0));
+        assert!(approx_equal(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152));
+
+        let a = Color::new(0.0, 1.0, 2.0);
+        let b = Color::new(1.0, 1.0, 0.0);
+        assert_eq!(a.lerp(&b, 0.25), Color::new(0.25, 1.0, 1.5));
+        assert_eq!(a.clamp(0.0, 1.0), Color::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parsing_and_formatting_hex() {
+        assert_eq!(Color::from_hex("#ffffff"), Ok(Color::new(1.0, 1.0, 1.0)));
+        assert_eq!(Color::from_hex("f00"), Ok(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(
+            "#808080".parse::<Color>().unwrap(),
+            Color::new(0.215861, 0.215861, 0.215861)
+        );
+
+        assert_eq!(Color::new(0.5, 0.0, 2.0).to_hex(), "#bc00ff");
+
+        for hex in &["#000000", "#0a7f3c", "#ff8000", "#123456"] {
+            assert_eq!(Color::<f64>::from_hex(hex).unwrap().to_hex(), *hex);
+        }
+
+        assert_eq!(
+            Color::<f64>::from_hex("#12345"),
+            Err(ParseColorError::InvalidLength("#12345".to_string()))
+        );
+        assert_eq!(
+            Color::<f64>::from_hex("#12345g"),
+            Err(ParseColorError::InvalidDigit("#12345g".to_string()))
+        );
+    }
+
+    #[test]
+    fn converting_hsv_and_hsl() {
+        let orange: Color = Color::from_hex("#ff8000").unwrap();
+
+        let (h, s, v) = orange.to_hsv();
+        assert!((h - 30.1).abs() < 0.1);
+        assert!(approx_equal(s, 1.0) && approx_equal(v, 1.0));
+        assert_eq!(Color::from_hsv(h, s, v), orange);
+
+        let (h, s, l) = orange.to_hsl();
+        assert!((h - 30.1).abs() < 0.1);
+        assert!(approx_equal(s, 1.0) && approx_equal(l, 0.5));
+        assert_eq!(Color::from_hsl(h, s, l), orange);
+
+        assert_eq!(Color::from_hsv(120., 1., 1.), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(Color::from_hsl(-120., 1., 0.5), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(Color::new(0.2, 0.2, 0.2).to_hsv().1, 0.0);
+    }
+
+    #[test]
+    fn converting_xyz_and_lab() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let (x, y, z) = white.to_xyz();
+        assert!(approx_equal(x, 0.95047) && approx_equal(y, 1.0));
+        assert!((z - 1.08883).abs() < 1e-4);
+
+        let (l, a, b) = white.to_lab();
+        assert!((l - 100.).abs() < 1e-3 && a.abs() < 1e-2 && b.abs() < 1e-2);
+
+        let red = Color::new(1.0, 0.0, 0.0);
+        let (l, a, b) = red.to_lab();
+        assert!((l - 53.24).abs() < 0.01);
+        assert!((a - 80.09).abs() < 0.01);
+        assert!((b - 67.20).abs() < 0.01);
+
+        let c = Color::new(0.2, 0.5, 0.01);
+        let (x, y, z) = c.to_xyz();
+        assert_eq!(Color::from_xyz(x, y, z), c);
+        let (l, a, b) = c.to_lab();
+        assert_eq!(Color::from_lab(l, a, b), c);
+    }
+
+    #[test]
+    fn black_body_colors() {
+        let candle = Color::from_temperature(1900.);
+        let daylight = Color::from_temperature(6504.);
+        let sky = Color::from_temperature(12000.);
+
+        assert!(candle.r == 1.0 && candle.g < 0.3 && candle.b < 0.05);
+        // D65 is close to, but not on, the Planckian locus
+        assert!(daylight.approx_eq(&Color::new(1.0, 1.0, 1.0), 0.1));
+        assert!(sky.b == 1.0 && sky.r < sky.g);
+        assert_eq!(
+            Color::from_temperature(100.),
+            Color::from_temperature(1667.)
+        );
+    }
+
+    #[test]
+    fn single_precision_colors() {
+        let c: Color<f32> = Color::new(0.25, 0.5, 1.0);
+
+        assert_eq!(c * 2.0 - c, c);
+        assert_eq!(c.to_srgb().to_linear(), c);
+        assert_eq!(
+            Color::<f32>::from_hex("#ff8000"),
+            Ok(Color::new(1.0, 0.21586, 0.0))
+        );
+        assert_eq!(c.cast::<f64>(), Color::new(0.25, 0.5, 1.0));
+        assert!(approx_equal(c.luminance(), 0.48295));
+    }
 }
@@ -0,0 +1,137 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+/// Porter-Duff operators on premultiplied colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CompositeOp {
+    /// Source over destination
+    Over,
+    /// Source, where the destination is covered
+    In,
+    /// Source, where the destination is not covered
+    Out,
+}
+
+impl CompositeOp {
+    /// Blends premultiplied `src` onto premultiplied `dst`
+    pub fn blend(&self, src: (Color, f64), dst: (Color, f64)) -> (Color, f64) {
+        let (cs, a_s) = src;
+        let (cd, a_d) = dst;
+
+        match self {
+            CompositeOp::Over => (cs + cd * (1.0 - a_s), a_s + a_d * (1.0 - a_s)),
+            CompositeOp::In => (cs * a_d, a_s * a_d),
+            CompositeOp::Out => (cs * (1.0 - a_d), a_s * (1.0 - a_d)),
+        }
+    }
+}
+
+impl Canvas {
+    /// Composites this canvas (the source) onto `dst` and returns the result,
+    /// which has the size of `dst` and an alpha channel. Source and destination
+    /// are aligned at their top left corners; where the source does not reach
+    /// it is treated as transparent.
+    pub fn composite(&self, dst: &Canvas, op: CompositeOp) -> Canvas {
+        let mut out = Canvas::new_transparent(dst.width, dst.height);
+
+        for y in 0..dst.height {
+            for x in 0..dst.width {
+                let di = (y * dst.width + x) as usize;
+                let src = if x < self.width && y < self.height {
+                    let si = (y * self.width + x) as usize;
+                    (self.pixels[si], self.alpha_at_index(si))
+                } else {
+                    (Color::new_black(), 0.0)
+                };
+
+                let (color, alpha) = op.blend(src, (dst.pixels[di], dst.alpha_at_index(di)));
+
+                out.pixels[di] = color;
+                if let Some(a) = out.alpha.as_mut() {
+                    a[di] = alpha;
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn over(&self, dst: &Canvas) -> Canvas {
+        self.composite(dst, CompositeOp::Over)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::approx_equal;
+
+    fn half_red() -> Canvas {
+        let mut c = Canvas::new_transparent(2, 1);
+        c.write_pixel_with_alpha(0, 0, Color::new(0.5, 0.0, 0.0), 0.5);
+        c
+    }
+
+    #[test]
+    fn transparent_canvas_has_no_coverage() {
+        let c = Canvas::new_transparent(2, 2);
+
+        assert!(c.has_alpha());
+        assert_eq!(c.alpha_at(1, 1), Some(0.0));
+        assert_eq!(Canvas::new(2, 2, None).alpha_at(1, 1), Some(1.0));
+    }
+
+    #[test]
+    fn compositing_over_opaque_background() {
+        let bg = Canvas::new(2, 1, Some(Color::new(0.0, 0.0, 1.0)));
+        let out = half_red().over(&bg);
+
+        assert_eq!(out.pixel_at(0, 0), Some(Color::new(0.5, 0.0, 0.5)));
+        assert_eq!(out.alpha_at(0, 0), Some(1.0));
+        // transparent source pixels leave the background untouched
+        assert_eq!(out.pixel_at(1, 0), Some(Color::new(0.0, 0.0, 1.0)));
+        assert_eq!(out.alpha_at(1, 0), Some(1.0));
+    }
+
+    #[test]
+    fn compositing_over_transparent_background() {
+        let bg = Canvas::new_transparent(2, 1);
+        let out = half_red().over(&bg);
+
+        assert_eq!(out.pixel_at(0, 0), Some(Color::new(0.5, 0.0, 0.0)));
+        assert_eq!(out.alpha_at(0, 0), Some(0.5));
+        assert_eq!(out.alpha_at(1, 0), Some(0.0));
+    }
+
+    #[test]
+    fn compositing_in_and_out() {
+        let mut mask = Canvas::new_transparent(2, 1);
+        mask.write_pixel_with_alpha(0, 0, Color::new_black(), 0.5);
+
+        let src = Canvas::new(2, 1, Some(Color::new(1.0, 1.0, 1.0)));
+
+        let inside = src.composite(&mask, CompositeOp::In);
+        assert_eq!(inside.pixel_at(0, 0), Some(Color::new(0.5, 0.5, 0.5)));
+        assert_eq!(inside.alpha_at(0, 0), Some(0.5));
+        assert_eq!(inside.alpha_at(1, 0), Some(0.0));
+
+        let outside = src.composite(&mask, CompositeOp::Out);
+        assert_eq!(outside.alpha_at(0, 0), Some(0.5));
+        assert_eq!(outside.pixel_at(1, 0), Some(Color::new(1.0, 1.0, 1.0)));
+        assert_eq!(outside.alpha_at(1, 0), Some(1.0));
+    }
+
+    #[test]
+    fn smaller_source_is_aligned_top_left() {
+        let bg = Canvas::new(3, 2, Some(Color::new(0.0, 1.0, 0.0)));
+        let fg = Canvas::new(1, 1, Some(Color::new(1.0, 0.0, 0.0)));
+        let out = fg.over(&bg);
+
+        assert_eq!(out.width, 3);
+        assert_eq!(out.pixel_at(0, 0), Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(out.pixel_at(2, 1), Some(Color::new(0.0, 1.0, 0.0)));
+        assert!(approx_equal(out.alpha_at(2, 1).unwrap(), 1.0));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::canvas::{Canvas, CanvasError};
+use crate::color::{linear_to_srgb, Color};
+
+// SSIM window size and stabilizing constants for a dynamic range of 1
+const SSIM_WINDOW: u32 = 7;
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+/// Per-pixel and summary differences between two canvases of equal size
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    pub width: u32,
+    pub height: u32,
+    /// Largest absolute channel difference of every pixel, in row order
+    pub errors: Vec<f64>,
+    /// Root mean square error over all channels (including alpha)
+    pub rmse: f64,
+    /// Peak signal to noise ratio in dB for a peak value of 1.0
+    pub psnr: f64,
+    pub max_error: f64,
+    pub mean_error: f64,
+    /// Mean structural similarity of the sRGB encoded luma, 1.0 when equal
+    pub ssim: f64,
+}
+
+impl ImageDiff {
+    pub fn error_at(&self, x: u32, y: u32) -> Option<f64> {
+        if x < self.width && y < self.height {
+            Some(self.errors[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Number of pixels whose error exceeds `threshold`
+    pub fn pixels_above(&self, threshold: f64) -> usize {
+        self.errors.iter().filter(|&&e| e > threshold).count()
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.max_error == 0.0
+    }
+
+    /// Visualizes the per-pixel error from black (none) through blue, green
+    /// and yellow to red (at `scale`, or the largest error when `None`)
+    pub fn heatmap(&self, scale: Option<f64>) -> Canvas {
+        let scale = scale.unwrap_or(self.max_error);
+        let mut canvas = Canvas::new(self.width, self.height, None);
+
+        for (pixel, &error) in canvas.pixels.iter_mut().zip(self.errors.iter()) {
+            let t = if scale > 0.0 { error / scale } else { 0.0 };
+            *pixel = heat(t);
+        }
+
+        canvas
+    }
+}
+
+fn heat(t: f64) -> Color {
+    let stops = [
+        Color::new_black(),
+        Color::new(0.0, 0.0, 1.0),
+        Color::new(0.0, 1.0, 0.0),
+        Color::new(1.0, 1.0, 0.0),
+        Color::new(1.0, 0.0, 0.0),
+    ];
+
+    let t = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let i = (t.floor() as usize).min(stops.len() - 2);
+    let f = t - i as f64;
+
+    stops[i] * (1.0 - f) + stops[i + 1] * f
+}
+
+impl Canvas {
+    /// Compares this canvas against `other` (the reference)
+    pub fn diff(&self, other: &Canvas) -> Result<ImageDiff, CanvasError> {
+        if self.width != other.width || self.height != other.height {
+            return Err(CanvasError::SizeMismatch {
+                expected: (other.width, other.height),
+                found: (self.width, self.height),
+            });
+        }
+
+        let with_alpha = self.has_alpha() || other.has_alpha();
+        let channels = if with_alpha { 4.0 } else { 3.0 };
+
+        let mut errors = Vec::with_capacity(self.pixels.len());
+        let mut squared = 0.0;
+
+        for (i, (a, b)) in self.pixels.iter().zip(other.pixels.iter()).enumerate() {
+            let mut d = [a.r - b.r, a.g - b.g, a.b - b.b, 0.0];
+            if with_alpha {
+                d[3] = self.alpha_at_index(i) - other.alpha_at_index(i);
+            }
+
+            squared += d.iter().map(|v| v * v).sum::<f64>();
+            errors.push(d.iter().fold(0.0, |max: f64, v| max.max(v.abs())));
+        }
+
+        let count = errors.len().max(1) as f64;
+        let rmse = (squared / (count * channels)).sqrt();
+
+        Ok(ImageDiff {
+            width: self.width,
+            height: self.height,
+            rmse,
+            psnr: if rmse > 0.0 {
+                -20.0 * rmse.log10()
+            } else {
+                f64::INFINITY
+            },
+            max_error: errors.iter().cloned().fold(0.0, f64::max),
+            mean_error: errors.iter().sum::<f64>() / count,
+            ssim: ssim(&self.luma(), &other.luma(), self.width, self.height),
+            errors,
+        })
+    }
+
+    /// Perceptual (sRGB encoded) luma of every pixel, clamped to [0, 1]
+    fn luma(&self) -> Vec<f64> {
+        self.pixels
+            .iter()
+            .map(|c| linear_to_srgb(c.luminance().clamp(0.0, 1.0)))
+            .collect()
+    }
+}
+
+/// Mean SSIM over every (clipped) window centered on a pixel
+fn ssim(a: &[f64], b: &[f64], width: u32, height: u32) -> f64 {
+    if a.is_empty() {
+        return 1.0;
+    }
+
+    let half = (SSIM_WINDOW / 2) as i64;
+    let mut total = 0.0;
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab, mut n) = (0., 0., 0., 0., 0., 0.);
+
+            for wy in (y - half).max(0)..(y + half + 1).min(height as i64) {
+                for wx in (x - half).max(0)..(x + half + 1).min(width as i64) {
+                    let i = (wy * width as i64 + wx) as usize;
+                    let (va, vb) = (a[i], b[i]);
+
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                    n += 1.0;
+                }
+            }
+
+            let (mean_a, mean_b) = (sa / n, sb / n);
+            let var_a = saa / n - mean_a * mean_a;
+            let var_b = sbb / n - mean_b * mean_b;
+            let covariance = sab / n - mean_a * mean_b;
+
+            total += ((2. * mean_a * mean_b + SSIM_C1) * (2. * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+        }
+    }
+
+    total / a.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::approx_equal;
+
+    fn gradient() -> Canvas {
+        Canvas::from_fn(16, 16, |x, y| {
+            Color::new(x as f64 / 16.0, y as f64 / 16.0, 0.5)
+        })
+    }
+
+    #[test]
+    fn identical_canvases_have_no_difference() {
+        let d = gradient().diff(&gradient()).unwrap();
+
+        assert!(d.is_identical());
+        assert_eq!(d.rmse, 0.0);
+        assert_eq!(d.psnr, f64::INFINITY);
+        assert!(approx_equal(d.ssim, 1.0));
+        assert_eq!(d.pixels_above(0.0), 0);
+    }
+
+    #[test]
+    fn diffing_canvases_of_different_size_fails() {
+        let a = Canvas::new(2, 2, None);
+        let b = Canvas::new(3, 2, None);
+
+        assert_eq!(
+            a.diff(&b).unwrap_err(),
+            CanvasError::SizeMismatch {
+                expected: (3, 2),
+                found: (2, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn computing_error_statistics() {
+        let a = Canvas::new(2, 1, None);
+        let mut b = Canvas::new(2, 1, None);
+        b.write_pixel(1, 0, Color::new(0.3, 0.0, -0.6));
+
+        let d = a.diff(&b).unwrap();
+
+        assert_eq!(d.error_at(0, 0), Some(0.0));
+        assert_eq!(d.error_at(1, 0), Some(0.6));
+        assert_eq!(d.error_at(2, 0), None);
+        assert!(approx_equal(d.max_error, 0.6));
+        assert!(approx_equal(d.mean_error, 0.3));
+        // (0.09 + 0.36) / 6 channels
+        assert!(approx_equal(d.rmse, 0.075_f64.sqrt()));
+        assert!(approx_equal(d.psnr, -20.0 * 0.075_f64.sqrt().log10()));
+        assert_eq!(d.pixels_above(0.5), 1);
+    }
+
+    #[test]
+    fn alpha_differences_count() {
+        let a = Canvas::new(1, 1, None);
+        let mut b = Canvas::new(1, 1, None);
+        b.write_pixel_with_alpha(0, 0, Color::new_black(), 0.5);
+
+        let d = a.diff(&b).unwrap();
+
+        assert_eq!(d.max_error, 0.5);
+        assert!(approx_equal(d.rmse, (0.25_f64 / 4.0).sqrt()));
+    }
+
+    #[test]
+    fn ssim_penalizes_structure_more_than_brightness() {
+        let reference = gradient();
+        let brighter = reference.map(|_, _, c| c + Color::new(0.02, 0.02, 0.02));
+        let noisy = reference.map(|x, y, c| {
+            let n = if (x + y) % 2 == 0 { 0.04 } else { -0.04 };
+            c + Color::new(n, n, n)
+        });
+
+        let d_bright = brighter.diff(&reference).unwrap();
+        let d_noisy = noisy.diff(&reference).unwrap();
+
+        assert!(d_noisy.ssim < d_bright.ssim);
+        assert!(d_bright.ssim < 1.0 && d_bright.ssim > 0.9);
+    }
+
+    #[test]
+    fn rendering_heatmaps() {
+        let a = Canvas::new(3, 1, None);
+        let b = Canvas::from_fn(3, 1, |x, _| Color::new(x as f64 * 0.5, 0.0, 0.0));
+
+        let heatmap = a.diff(&b).unwrap().heatmap(None);
+
+        assert_eq!(heatmap[(0, 0)], Color::new_black());
+        assert_eq!(heatmap[(1, 0)], Color::new(0.0, 1.0, 0.0));
+        assert_eq!(heatmap[(2, 0)], Color::new(1.0, 0.0, 0.0));
+
+        let scaled = a.diff(&b).unwrap().heatmap(Some(2.0));
+        assert_eq!(scaled[(2, 0)], Color::new(0.0, 1.0, 0.0));
+    }
+}
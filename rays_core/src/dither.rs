@@ -0,0 +1,300 @@
+use std::sync::OnceLock;
+
+use crate::image::quantize_channel;
+
+/// How float channels are spread over the 8-bit levels when exporting
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Dither {
+    /// Round each channel to the nearest level
+    #[default]
+    None,
+    /// 8x8 Bayer matrix thresholds
+    Ordered,
+    /// Floyd-Steinberg error diffusion (serpentine scan)
+    FloydSteinberg,
+    /// Thresholds from a tiled 32x32 void-and-cluster blue noise mask
+    BlueNoise,
+}
+
+const BAYER_SIZE: usize = 8;
+const BLUE_NOISE_SIZE: usize = 32;
+
+/// Quantizes a single channel plane, given as values in [0, 1], to 8 bits
+pub fn dither_plane(dither: Dither, values: &[f64], width: usize) -> Vec<u8> {
+    match dither {
+        Dither::None => values.iter().map(|&v| quantize(v, 0.0)).collect(),
+        Dither::Ordered => values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| quantize(v, bayer_threshold(i % width, i / width)))
+            .collect(),
+        Dither::BlueNoise => values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| quantize(v, blue_noise_threshold(i % width, i / width)))
+            .collect(),
+        Dither::FloydSteinberg => floyd_steinberg(values, width),
+    }
+}
+
+// `threshold` is in levels, so it shifts the value by up to half a step
+fn quantize(v: f64, threshold: f64) -> u8 {
+    quantize_channel(v + threshold / 255.0)
+}
+
+/// Bayer threshold in [-0.5, 0.5) for pixel (x, y)
+pub fn bayer_threshold(x: usize, y: usize) -> f64 {
+    let mut rank = 0;
+
+    // M(2n) = 4 * M(n) + [[0, 2], [3, 1]], so the lowest coordinate bits
+    // pick the most significant entry
+    for bit in 0..3 {
+        let (xb, yb) = ((x >> bit) & 1, (y >> bit) & 1);
+        rank = rank * 4 + 2 * (xb ^ yb) + yb;
+    }
+
+    ((rank as f64) + 0.5) / (BAYER_SIZE * BAYER_SIZE) as f64 - 0.5
+}
+
+/// Blue noise threshold in [-0.5, 0.5) for pixel (x, y)
+pub fn blue_noise_threshold(x: usize, y: usize) -> f64 {
+    let mask = blue_noise_mask();
+    let rank = mask[(y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE];
+
+    (rank as f64 + 0.5) / mask.len() as f64 - 0.5
+}
+
+fn blue_noise_mask() -> &'static [u16] {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+/// Ulichney's void-and-cluster method: ranks every pixel of a toroidal
+/// `size` x `size` grid so that each threshold level is evenly spread.
+fn void_and_cluster(size: usize) -> Vec<u16> {
+    const SIGMA: f64 = 1.5;
+
+    let n = size * size;
+
+    // gaussian falloff by toroidal offset
+    let mut kernel = vec![0.; n];
+    for dy in 0..size {
+        for dx in 0..size {
+            let wx = dx.min(size - dx) as f64;
+            let wy = dy.min(size - dy) as f64;
+            kernel[dy * size + dx] = (-(wx * wx + wy * wy) / (2. * SIGMA * SIGMA)).exp();
+        }
+    }
+
+    let toggle = |energy: &mut Vec<f64>, i: usize, sign: f64| {
+        let (px, py) = (i % size, i / size);
+        for y in 0..size {
+            for x in 0..size {
+                let dx = (x + size - px) % size;
+                let dy = (y + size - py) % size;
+                energy[y * size + x] += sign * kernel[dy * size + dx];
+            }
+        }
+    };
+
+    let tightest_cluster = |pattern: &Vec<bool>, energy: &Vec<f64>| {
+        (0..n)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+
+    let largest_void = |pattern: &Vec<bool>, energy: &Vec<f64>| {
+        (0..n)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+
+    // deterministic sparse initial pattern
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.; n];
+    let mut seed: u32 = 0x2545_f491;
+    let initial = n / 10;
+    let mut ones = 0;
+
+    while ones < initial {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let i = seed as usize % n;
+
+        if !pattern[i] {
+            pattern[i] = true;
+            toggle(&mut energy, i, 1.0);
+            ones += 1;
+        }
+    }
+
+    // spread the initial points out by moving clusters into voids
+    loop {
+        let cluster = tightest_cluster(&pattern, &energy);
+        pattern[cluster] = false;
+        toggle(&mut energy, cluster, -1.0);
+
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0_u16; n];
+
+    // rank the initial points, removing the tightest clusters first
+    let mut phase1 = pattern.clone();
+    let mut phase1_energy = energy.clone();
+    for rank in (0..ones).rev() {
+        let cluster = tightest_cluster(&phase1, &phase1_energy);
+        phase1[cluster] = false;
+        toggle(&mut phase1_energy, cluster, -1.0);
+        ranks[cluster] = rank as u16;
+    }
+
+    // fill the remaining voids in order
+    for rank in ones..n {
+        let void = largest_void(&pattern, &energy);
+        pattern[void] = true;
+        toggle(&mut energy, void, 1.0);
+        ranks[void] = rank as u16;
+    }
+
+    ranks
+}
+
+fn floyd_steinberg(values: &[f64], width: usize) -> Vec<u8> {
+    let height = values.len() / width.max(1);
+    let mut work: Vec<f64> = values.iter().map(|v| v.clamp(0.0, 1.0) * 255.0).collect();
+    let mut out = vec![0; values.len()];
+
+    for y in 0..height {
+        let reverse = y % 2 == 1;
+
+        for step in 0..width {
+            let x = if reverse { width - 1 - step } else { step };
+            let i = y * width + x;
+
+            let level = work[i].round().clamp(0.0, 255.0);
+            let error = work[i] - level;
+            out[i] = level as u8;
+
+            // (dx forward, dy, weight) with dx mirrored on reversed rows
+            for &(dx, dy, weight) in &[(1, 0, 7.), (-1, 1, 3.), (0, 1, 5.), (1, 1, 1.)] {
+                let dx = if reverse { -dx } else { dx };
+                let nx = x as isize + dx;
+                let ny = y + dy;
+
+                if nx >= 0 && (nx as usize) < width && ny < height {
+                    work[ny * width + nx as usize] += error * weight / 16.;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean(levels: &[u8]) -> f64 {
+        levels.iter().map(|&l| l as f64).sum::<f64>() / levels.len() as f64
+    }
+
+    #[test]
+    fn bayer_matrix_ranks_are_a_permutation() {
+        let mut seen = [false; 64];
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let rank = ((bayer_threshold(x, y) + 0.5) * 64.0 - 0.5).round() as usize;
+                assert!(!seen[rank]);
+                seen[rank] = true;
+            }
+        }
+
+        assert_eq!(bayer_threshold(0, 0), 0.5 / 64.0 - 0.5);
+        assert_eq!(bayer_threshold(8, 16), bayer_threshold(0, 0));
+    }
+
+    #[test]
+    fn bayer_matrix_matches_recursive_definition() {
+        // first row of the classic 8x8 matrix
+        let row: Vec<usize> = (0..8)
+            .map(|x| ((bayer_threshold(x, 0) + 0.5) * 64.0 - 0.5).round() as usize)
+            .collect();
+
+        assert_eq!(row, vec![0, 32, 8, 40, 2, 34, 10, 42]);
+    }
+
+    #[test]
+    fn blue_noise_mask_ranks_are_a_permutation() {
+        let mask = blue_noise_mask();
+        let mut seen = vec![false; mask.len()];
+
+        for &rank in mask {
+            assert!(!seen[rank as usize]);
+            seen[rank as usize] = true;
+        }
+    }
+
+    #[test]
+    fn blue_noise_thresholds_are_evenly_spread() {
+        // the lowest ranked 1/8th of pixels should never be direct neighbours
+        let limit = (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE / 8) as u16;
+        let mask = blue_noise_mask();
+        let s = BLUE_NOISE_SIZE;
+
+        for y in 0..s {
+            for x in 0..s {
+                if mask[y * s + x] < limit {
+                    assert!(mask[y * s + (x + 1) % s] >= limit);
+                    assert!(mask[((y + 1) % s) * s + x] >= limit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_dither_rounds() {
+        let values = [0.0, 0.5, 1.0, 1.5];
+
+        assert_eq!(
+            dither_plane(Dither::None, &values, 4),
+            vec![0, 128, 255, 255]
+        );
+    }
+
+    #[test]
+    fn dithering_preserves_average_level() {
+        // a level between two 8-bit steps
+        let level = 100.3 / 255.0;
+        let values = vec![level; 64 * 64];
+
+        for dither in [Dither::Ordered, Dither::FloydSteinberg, Dither::BlueNoise].iter() {
+            let out = dither_plane(*dither, &values, 64);
+
+            assert!((mean(&out) - 100.3).abs() < 0.05, "{:?}", dither);
+            assert!(out.iter().all(|&l| l == 100 || l == 101), "{:?}", dither);
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_exact_levels() {
+        let values = vec![0.0; 16];
+
+        for dither in [Dither::Ordered, Dither::FloydSteinberg, Dither::BlueNoise].iter() {
+            assert!(dither_plane(*dither, &values, 4).iter().all(|&l| l == 0));
+        }
+    }
+}
@@ -0,0 +1,515 @@
+// Shape coordinates are in pixels, with pixel (x, y) centered on the
+// integer point (x, y). Anything falling outside the canvas is clipped.
+
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+const GLYPH_WIDTH: i64 = 5;
+const GLYPH_HEIGHT: i64 = 7;
+
+impl Canvas {
+    /// Blends `color` over the pixel with the given coverage
+    pub fn blend_pixel(&mut self, x: i64, y: i64, color: Color, coverage: f64) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+
+        let coverage = coverage.clamp(0.0, 1.0);
+        let i = (y as u32 * self.width + x as u32) as usize;
+
+        self.pixels[i] = color * coverage + self.pixels[i] * (1.0 - coverage);
+
+        if let Some(alpha) = self.alpha.as_mut() {
+            alpha[i] = coverage + alpha[i] * (1.0 - coverage);
+        }
+    }
+
+    fn plot(&mut self, x: i64, y: i64, color: Color) {
+        if x >= 0 && y >= 0 {
+            self.write_pixel(x as u32, y as u32, color);
+        }
+    }
+
+    /// Bresenham line between two pixels, endpoints included
+    pub fn draw_line(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, color: Color) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+
+        loop {
+            self.plot(x, y, color);
+
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Anti-aliased line (Xiaolin Wu)
+    pub fn draw_line_aa(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, color: Color) {
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+
+        let (mut x0, mut y0, mut x1, mut y1) = if steep {
+            (y0, x0, y1, x1)
+        } else {
+            (x0, y0, x1, y1)
+        };
+
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+
+        let dx = x1 - x0;
+        let gradient = if dx == 0.0 { 1.0 } else { (y1 - y0) / dx };
+
+        let plot = |canvas: &mut Canvas, x: f64, y: f64, coverage: f64| {
+            let (px, py) = if steep { (y, x) } else { (x, y) };
+            canvas.blend_pixel(px as i64, py as i64, color, coverage);
+        };
+
+        // first endpoint
+        let x_end = x0.round();
+        let y_end = y0 + gradient * (x_end - x0);
+        let x_gap = 1.0 - (x0 + 0.5).fract();
+        let x_start = x_end;
+        plot(self, x_start, y_end.floor(), (1.0 - y_end.fract()) * x_gap);
+        plot(self, x_start, y_end.floor() + 1.0, y_end.fract() * x_gap);
+        let mut inter_y = y_end + gradient;
+
+        // second endpoint
+        let x_end = x1.round();
+        let y_end = y1 + gradient * (x_end - x1);
+        let x_gap = (x1 + 0.5).fract();
+        let x_stop = x_end;
+        plot(self, x_stop, y_end.floor(), (1.0 - y_end.fract()) * x_gap);
+        plot(self, x_stop, y_end.floor() + 1.0, y_end.fract() * x_gap);
+
+        let mut x = x_start + 1.0;
+        while x < x_stop {
+            plot(self, x, inter_y.floor(), 1.0 - inter_y.fract());
+            plot(self, x, inter_y.floor() + 1.0, inter_y.fract());
+            inter_y += gradient;
+            x += 1.0;
+        }
+    }
+
+    /// Circle outline (midpoint algorithm)
+    pub fn draw_circle(&mut self, cx: i64, cy: i64, radius: i64, color: Color) {
+        let (mut x, mut y) = (radius, 0);
+        let mut err = 1 - radius;
+
+        while x >= y {
+            for &(px, py) in &[
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                self.plot(cx + px, cy + py, color);
+            }
+
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, cx: f64, cy: f64, radius: f64, color: Color) {
+        // rows and spans are clipped first, so huge circles cost no more
+        // than the canvas
+        let y_min = ((cy - radius).ceil() as i64).max(0);
+        let y_max = ((cy + radius).floor() as i64).min(self.height as i64 - 1);
+
+        for y in y_min..=y_max {
+            let dy = y as f64 - cy;
+            let half = (radius * radius - dy * dy).max(0.0).sqrt();
+            let x_min = ((cx - half).ceil() as i64).max(0);
+            let x_max = ((cx + half).floor() as i64).min(self.width as i64 - 1);
+
+            for x in x_min..=x_max {
+                self.plot(x, y, color);
+            }
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i64, y: i64, width: u32, height: u32, color: Color) {
+        for py in y.max(0)..(y + height as i64).min(self.height as i64) {
+            for px in x.max(0)..(x + width as i64).min(self.width as i64) {
+                self.plot(px, py, color);
+            }
+        }
+    }
+
+    /// Fills a polygon with the even-odd rule, sampling pixel centers.
+    /// Pixels on the top and left edges are filled, those on the bottom and
+    /// right edges are not, so adjacent polygons never overlap.
+    pub fn fill_polygon(&mut self, points: &[(f64, f64)], color: Color) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let y_min = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let y_max = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+
+        let first = (y_min.ceil() as i64).max(0);
+        let last = (y_max.floor() as i64).min(self.height as i64 - 1);
+
+        let mut crossings = Vec::new();
+
+        for y in first..=last {
+            let sample = y as f64;
+            crossings.clear();
+
+            for (i, &(ax, ay)) in points.iter().enumerate() {
+                let (bx, by) = points[(i + 1) % points.len()];
+
+                // half-open so shared vertices are only counted once
+                if (ay <= sample && by > sample) || (by <= sample && ay > sample) {
+                    crossings.push(ax + (sample - ay) / (by - ay) * (bx - ax));
+                }
+            }
+
+            crossings.sort_by(f64::total_cmp);
+
+            for span in crossings.chunks_exact(2) {
+                let from = (span[0].ceil() as i64).max(0);
+                let to = (span[1].ceil() as i64).min(self.width as i64);
+
+                for x in from..to {
+                    self.plot(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Polyline of the given thickness with round joins and caps
+    pub fn draw_polyline(&mut self, points: &[(f64, f64)], thickness: f64, color: Color) {
+        let half = thickness / 2.0;
+
+        for segment in points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+
+            if length > 0.0 {
+                let (nx, ny) = (-(y1 - y0) / length * half, (x1 - x0) / length * half);
+
+                self.fill_polygon(
+                    &[
+                        (x0 + nx, y0 + ny),
+                        (x1 + nx, y1 + ny),
+                        (x1 - nx, y1 - ny),
+                        (x0 - nx, y0 - ny),
+                    ],
+                    color,
+                );
+            }
+        }
+
+        for &(x, y) in points {
+            self.fill_circle(x, y, half, color);
+        }
+    }
+
+    /// Draws text with the built-in 5x7 font, each font pixel covering
+    /// `scale` x `scale` canvas pixels. Lowercase letters are drawn as
+    /// uppercase and unknown characters as '?'.
+    pub fn draw_text(&mut self, x: i64, y: i64, text: &str, scale: u32, color: Color) {
+        let scale = scale.max(1) as i64;
+        let (mut pen_x, mut pen_y) = (x, y);
+
+        for ch in text.chars() {
+            if ch == '\n' {
+                pen_x = x;
+                pen_y += (GLYPH_HEIGHT + 1) * scale;
+                continue;
+            }
+
+            for (row, bits) in glyph(ch).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        self.fill_rect(
+                            pen_x + col * scale,
+                            pen_y + row as i64 * scale,
+                            scale as u32,
+                            scale as u32,
+                            color,
+                        );
+                    }
+                }
+            }
+
+            pen_x += (GLYPH_WIDTH + 1) * scale;
+        }
+    }
+}
+
+/// Width and height in pixels of `text` drawn with `draw_text`
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let lines = text.split('\n');
+    let (mut width, mut count) = (0, 0);
+
+    for line in lines {
+        width = width.max(line.chars().count() as u32);
+        count += 1;
+    }
+
+    let w = (width * (GLYPH_WIDTH as u32 + 1)).saturating_sub(1);
+    let h = count * (GLYPH_HEIGHT as u32 + 1) - 1;
+
+    (w * scale, h * scale)
+}
+
+/// Rows of a 5x7 glyph, most significant of the low 5 bits leftmost
+fn glyph(ch: char) -> [u8; 7] {
+    match ch.to_ascii_uppercase() {
+        ' ' => [0, 0, 0, 0, 0, 0, 0],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '.' => [0, 0, 0, 0, 0, 0x0c, 0x0c],
+        ',' => [0, 0, 0, 0, 0x0c, 0x04, 0x08],
+        ':' => [0, 0x0c, 0x0c, 0, 0x0c, 0x0c, 0],
+        '-' => [0, 0, 0, 0x1f, 0, 0, 0],
+        '+' => [0, 0x04, 0x04, 0x1f, 0x04, 0x04, 0],
+        '=' => [0, 0, 0x1f, 0, 0x1f, 0, 0],
+        '*' => [0, 0x04, 0x15, 0x0e, 0x15, 0x04, 0],
+        '/' => [0, 0x01, 0x02, 0x04, 0x08, 0x10, 0],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0, 0x04],
+        '\'' => [0x0c, 0x04, 0x08, 0, 0, 0, 0],
+        '_' => [0, 0, 0, 0, 0, 0, 0x1f],
+        _ => [0x0e, 0x11, 0x01, 0x02, 0x04, 0, 0x04],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn lit(c: &Canvas) -> Vec<(u32, u32)> {
+        c.enumerate_pixels()
+            .filter(|(_, _, p)| p.r > 0.0)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn drawing_bresenham_lines() {
+        let mut c = Canvas::new(5, 5, None);
+        c.draw_line(0, 0, 4, 2, white());
+
+        assert_eq!(lit(&c), vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]);
+
+        // endpoints may be given in any order and lie off canvas
+        let mut c = Canvas::new(3, 3, None);
+        c.draw_line(1, 10, 1, -10, white());
+        assert_eq!(lit(&c), vec![(1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn drawing_anti_aliased_lines() {
+        let mut c = Canvas::new(10, 5, None);
+        c.draw_line_aa(1.0, 1.0, 8.0, 1.0, white());
+
+        // a horizontal line through pixel centers is solid
+        for x in 2..8 {
+            assert_eq!(c[(x, 1)], white());
+            assert_eq!(c[(x, 2)], Color::new_black());
+        }
+
+        let mut c = Canvas::new(10, 5, None);
+        c.draw_line_aa(1.0, 1.5, 8.0, 1.5, white());
+
+        // halfway between two rows both get half coverage
+        for x in 2..8 {
+            assert_eq!(c[(x, 1)], Color::new(0.5, 0.5, 0.5));
+            assert_eq!(c[(x, 2)], Color::new(0.5, 0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn blending_updates_alpha() {
+        let mut c = Canvas::new_transparent(2, 1);
+        c.blend_pixel(0, 0, white(), 0.25);
+        c.blend_pixel(5, 0, white(), 1.0);
+
+        assert_eq!(c[(0, 0)], Color::new(0.25, 0.25, 0.25));
+        assert_eq!(c.alpha_at(0, 0), Some(0.25));
+    }
+
+    #[test]
+    fn drawing_circles() {
+        let mut c = Canvas::new(7, 7, None);
+        c.draw_circle(3, 3, 2, white());
+
+        let points = lit(&c);
+        assert!(points.contains(&(3, 1)) && points.contains(&(5, 3)));
+        assert!(!points.contains(&(3, 3)));
+
+        let mut c = Canvas::new(7, 7, None);
+        c.fill_circle(3.0, 3.0, 2.0, white());
+
+        let points = lit(&c);
+        assert_eq!(points.len(), 13);
+        assert!(points.contains(&(3, 3)));
+        assert!(!points.contains(&(1, 1)));
+    }
+
+    #[test]
+    fn filling_rectangles_clips() {
+        let mut c = Canvas::new(4, 4, None);
+        c.fill_rect(-1, 2, 3, 5, white());
+
+        assert_eq!(lit(&c), vec![(0, 2), (1, 2), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn filling_polygons() {
+        let mut c = Canvas::new(6, 6, None);
+        c.fill_polygon(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)], white());
+
+        // upper left triangle (y grows downwards), the diagonal where
+        // x + y = 4 left out
+        assert_eq!(lit(&c).len(), 4 + 3 + 2 + 1);
+        assert_eq!(c[(4, 0)], Color::new_black());
+        assert_eq!(c[(2, 2)], Color::new_black());
+        assert_eq!(c[(1, 2)], white());
+
+        // far off or broken vertices are clipped rather than walked over
+        let mut c = Canvas::new(6, 6, None);
+        c.fill_polygon(&[(-1e12, 0.0), (1e12, 0.0), (0.0, 1e12)], white());
+        assert_eq!(lit(&c).len(), 36);
+        c.fill_polygon(&[(0.0, 0.0), (f64::NAN, 2.0), (0.0, 4.0)], white());
+
+        let mut c = Canvas::new(6, 6, None);
+        c.fill_circle(3.0, 3.0, 1e12, white());
+        assert_eq!(lit(&c).len(), 36);
+    }
+
+    #[test]
+    fn filling_concave_polygon_uses_even_odd() {
+        let mut c = Canvas::new(7, 5, None);
+        // a "U" shape
+        c.fill_polygon(
+            &[
+                (0.0, 0.0),
+                (2.0, 0.0),
+                (2.0, 2.0),
+                (4.0, 2.0),
+                (4.0, 0.0),
+                (6.0, 0.0),
+                (6.0, 4.0),
+                (0.0, 4.0),
+            ],
+            white(),
+        );
+
+        assert_eq!(c[(3, 0)], Color::new_black());
+        assert_eq!(c[(3, 3)], white());
+        assert_eq!(c[(1, 0)], white());
+    }
+
+    #[test]
+    fn drawing_thick_polylines() {
+        let mut c = Canvas::new(20, 20, None);
+        c.draw_polyline(&[(2.0, 10.0), (10.0, 10.0), (10.0, 2.0)], 3.0, white());
+
+        for y in 9..=11 {
+            assert_eq!(c[(5, y)], white());
+        }
+        assert_eq!(c[(5, 8)], Color::new_black());
+        assert_eq!(c[(10, 5)], white());
+        assert_eq!(c[(15, 15)], Color::new_black());
+    }
+
+    #[test]
+    fn drawing_text() {
+        let mut c = Canvas::new(12, 8, None);
+        c.draw_text(0, 0, "T1", 1, white());
+
+        // top bar of the T
+        for x in 0..5 {
+            assert_eq!(c[(x, 0)], white());
+        }
+        assert_eq!(c[(2, 6)], white());
+        assert_eq!(c[(0, 6)], Color::new_black());
+        // base of the 1, after one column of spacing
+        for x in 7..10 {
+            assert_eq!(c[(x, 6)], white());
+        }
+    }
+
+    #[test]
+    fn scaling_and_measuring_text() {
+        let mut c = Canvas::new(10, 14, None);
+        c.draw_text(0, 0, "l", 2, white());
+
+        assert_eq!(c[(0, 13)], white());
+        assert_eq!(c[(9, 13)], white());
+
+        assert_eq!(text_size("AB", 1), (11, 7));
+        assert_eq!(text_size("A\nBCD", 2), (34, 30));
+    }
+}
@@ -1,3 +1,500 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::canvas::Canvas;
+use crate::color::{linear_to_srgb, srgb_to_linear, Color};
+use crate::dither::{dither_plane, Dither};
+use crate::tonemap::ToneMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum ImageFormat {
     PPM,
+    PNG,
+    TGA,
+    /// OpenEXR, stored as uncompressed 32-bit float scanlines
+    EXR,
+}
+
+/// Transfer function between linear canvas values and stored 8-bit values
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Transfer {
+    /// Store linear values as-is
+    Linear,
+    /// Encode with the sRGB curve on export, decode on import
+    #[default]
+    Srgb,
+}
+
+impl Transfer {
+    pub fn encode(&self, color: Color) -> Color {
+        match self {
+            Transfer::Linear => color,
+            Transfer::Srgb => color.to_srgb(),
+        }
+    }
+
+    pub fn decode(&self, color: Color) -> Color {
+        match self {
+            Transfer::Linear => color,
+            Transfer::Srgb => color.to_linear(),
+        }
+    }
+
+    pub fn encode_channel(&self, v: f64) -> f64 {
+        match self {
+            Transfer::Linear => v,
+            Transfer::Srgb => linear_to_srgb(v),
+        }
+    }
+
+    pub fn decode_channel(&self, v: f64) -> f64 {
+        match self {
+            Transfer::Linear => v,
+            Transfer::Srgb => srgb_to_linear(v),
+        }
+    }
+}
+
+/// Settings used when quantizing a `Canvas` to 8-bit channels
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ExportOptions {
+    pub tone_map: ToneMap,
+    pub transfer: Transfer,
+    pub dither: Dither,
+}
+
+impl ExportOptions {
+    /// Tone maps and encodes a linear color, then rounds it to 8 bits
+    /// (without dithering, which needs the whole image)
+    pub fn quantize(&self, color: Color) -> (u8, u8, u8) {
+        let encoded = self.transfer.encode(self.tone_map.apply(color));
+
+        (
+            quantize_channel(encoded.r),
+            quantize_channel(encoded.g),
+            quantize_channel(encoded.b),
+        )
+    }
+}
+
+pub fn quantize_channel(v: f64) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+impl Canvas {
+    /// Encodes the canvas in the given format. Canvases with an alpha channel
+    /// are written with alpha to PNG, TGA and EXR.
+    pub fn encode(
+        &self,
+        format: ImageFormat,
+        options: &ExportOptions,
+    ) -> Result<Vec<u8>, ImageError> {
+        Ok(match format {
+            ImageFormat::PPM => self.to_ppm_with(options).into_bytes(),
+            ImageFormat::PNG => self.to_png(options),
+            ImageFormat::TGA => self.to_tga(options)?,
+            ImageFormat::EXR => self.to_exr(),
+        })
+    }
+
+    /// Quantized and dithered RGBA pixels, row by row. Colors are divided by
+    /// alpha when `straight` is set, formats without alpha keep them
+    /// premultiplied (i.e. composited over black).
+    pub(crate) fn to_rgba8(&self, options: &ExportOptions, straight: bool) -> Vec<[u8; 4]> {
+        let width = self.width as usize;
+        let mut planes = [
+            Vec::with_capacity(self.pixels.len()),
+            Vec::with_capacity(self.pixels.len()),
+            Vec::with_capacity(self.pixels.len()),
+        ];
+
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let color = if straight {
+                self.straight_color_at_index(i)
+            } else {
+                *pixel
+            };
+            let encoded = options.transfer.encode(options.tone_map.apply(color));
+
+            planes[0].push(encoded.r);
+            planes[1].push(encoded.g);
+            planes[2].push(encoded.b);
+        }
+
+        let [r, g, b] = planes;
+        let (r, g, b) = (
+            dither_plane(options.dither, &r, width),
+            dither_plane(options.dither, &g, width),
+            dither_plane(options.dither, &b, width),
+        );
+
+        (0..self.pixels.len())
+            .map(|i| [r[i], g[i], b[i], quantize_channel(self.alpha_at_index(i))])
+            .collect()
+    }
+
+    pub fn to_png(&self, options: &ExportOptions) -> Vec<u8> {
+        let channels = if self.has_alpha() { 4 } else { 3 };
+
+        // every scanline starts with its filter type (0, none)
+        let mut raw =
+            Vec::with_capacity((self.width as usize * channels + 1) * self.height as usize);
+        for row in self
+            .to_rgba8(options, true)
+            .chunks(self.width.max(1) as usize)
+        {
+            raw.push(0);
+            for px in row {
+                raw.extend_from_slice(&px[..channels]);
+            }
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        // bit depth, color type (RGB or RGBA), compression, filter, interlace
+        ihdr.extend_from_slice(&[8, if self.has_alpha() { 6 } else { 2 }, 0, 0, 0]);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        write_png_chunk(&mut png, b"IHDR", &ihdr);
+        write_png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_png_chunk(&mut png, b"IEND", &[]);
+
+        png
+    }
+
+    /// TGA stores its dimensions in 16 bits, larger canvases are rejected
+    pub fn to_tga(&self, options: &ExportOptions) -> Result<Vec<u8>, ImageError> {
+        let dimension = |v: u32| {
+            u16::try_from(v).map_err(|_| {
+                ImageError::Malformed(format!(
+                    "{}x{} is too large for TGA",
+                    self.width, self.height
+                ))
+            })
+        };
+        let (width, height) = (dimension(self.width)?, dimension(self.height)?);
+
+        let alpha = self.has_alpha();
+        let depth: u8 = if alpha { 32 } else { 24 };
+        // top-left origin, plus the number of alpha bits
+        let descriptor: u8 = 0x20 | if alpha { 8 } else { 0 };
+
+        let mut tga = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        tga.extend_from_slice(&width.to_le_bytes());
+        tga.extend_from_slice(&height.to_le_bytes());
+        tga.push(depth);
+        tga.push(descriptor);
+
+        for [r, g, b, a] in self.to_rgba8(options, true) {
+            tga.extend_from_slice(&[b, g, r]);
+            if alpha {
+                tga.push(a);
+            }
+        }
+
+        Ok(tga)
+    }
+
+    /// Writes linear, premultiplied values without tone mapping
+    pub fn to_exr(&self) -> Vec<u8> {
+        // channels are stored in alphabetical order
+        let channels: &[&str] = if self.has_alpha() {
+            &["A", "B", "G", "R"]
+        } else {
+            &["B", "G", "R"]
+        };
+
+        let mut exr = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+        let mut chlist = Vec::new();
+        for name in channels {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            // FLOAT pixel type, pLinear + reserved, x/y sampling
+            chlist.extend_from_slice(&2_i32.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1_i32.to_le_bytes());
+            chlist.extend_from_slice(&1_i32.to_le_bytes());
+        }
+        chlist.push(0);
+
+        let mut window = Vec::new();
+        for v in &[0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+
+        write_exr_attribute(&mut exr, "channels", "chlist", &chlist);
+        write_exr_attribute(&mut exr, "compression", "compression", &[0]);
+        write_exr_attribute(&mut exr, "dataWindow", "box2i", &window);
+        write_exr_attribute(&mut exr, "displayWindow", "box2i", &window);
+        write_exr_attribute(&mut exr, "lineOrder", "lineOrder", &[0]);
+        write_exr_attribute(&mut exr, "pixelAspectRatio", "float", &1_f32.to_le_bytes());
+        write_exr_attribute(&mut exr, "screenWindowCenter", "v2f", &[0; 8]);
+        write_exr_attribute(&mut exr, "screenWindowWidth", "float", &1_f32.to_le_bytes());
+        exr.push(0);
+
+        // offset table: one uncompressed scanline per chunk
+        let line_size = self.width as usize * channels.len() * 4;
+        let first_chunk = exr.len() + self.height as usize * 8;
+        for y in 0..self.height as usize {
+            let offset = (first_chunk + y * (line_size + 8)) as u64;
+            exr.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        for y in 0..self.height {
+            exr.extend_from_slice(&(y as i32).to_le_bytes());
+            exr.extend_from_slice(&(line_size as i32).to_le_bytes());
+
+            let row = (y * self.width) as usize..((y + 1) * self.width) as usize;
+            for name in channels {
+                for i in row.clone() {
+                    let c = self.pixels[i];
+                    let v = match *name {
+                        "A" => self.alpha_at_index(i),
+                        "B" => c.b,
+                        "G" => c.g,
+                        _ => c.r,
+                    };
+                    exr.extend_from_slice(&(v as f32).to_le_bytes());
+                }
+            }
+        }
+
+        exr
+    }
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+
+    (b << 16) | a
+}
+
+fn write_exr_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageError {
+    UnsupportedFormat,
+    UnexpectedEof,
+    Malformed(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::UnsupportedFormat => write!(f, "unsupported image format"),
+            ImageError::UnexpectedEof => write!(f, "unexpected end of image data"),
+            ImageError::Malformed(msg) => write!(f, "malformed image: {}", msg),
+        }
+    }
+}
+
+impl Error for ImageError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantizing_rounds_to_nearest() {
+        let options = ExportOptions {
+            transfer: Transfer::Linear,
+            ..Default::default()
+        };
+
+        assert_eq!(options.quantize(Color::new(1.0, 0.5, 0.0)), (255, 128, 0));
+        assert_eq!(
+            options.quantize(Color::new(0.998, 0.002, 0.001)),
+            (254, 1, 0)
+        );
+    }
+
+    #[test]
+    fn quantizing_encodes_srgb_by_default() {
+        let options = ExportOptions::default();
+
+        assert_eq!(options.quantize(Color::new(1.0, 0.5, 0.2)), (255, 188, 124));
+        assert_eq!(options.quantize(Color::new(-1.0, 2.0, 0.0)), (0, 255, 0));
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn encoding_png_with_alpha() {
+        let mut c = Canvas::new_transparent(2, 1);
+        c.write_pixel_with_alpha(0, 0, Color::new(0.5, 0.0, 0.0), 0.5);
+
+        let options = ExportOptions {
+            transfer: Transfer::Linear,
+            ..Default::default()
+        };
+        let png = c.to_png(&options);
+
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+        assert_eq!(&png[12..16], b"IHDR");
+        // 2x1, 8 bit RGBA
+        assert_eq!(&png[16..29], &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert_eq!(&png[37..41], b"IDAT");
+
+        // zlib header, one final stored block of 9 bytes, then the scanline
+        assert_eq!(&png[41..48], &[0x78, 0x01, 1, 9, 0, 0xf6, 0xff]);
+        // straight (unpremultiplied) red at half coverage, then transparent
+        assert_eq!(&png[48..57], &[0, 255, 0, 0, 128, 0, 0, 0, 0]);
+
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn encoding_opaque_png_as_rgb() {
+        let c = Canvas::new(1, 1, Some(Color::new(1.0, 1.0, 1.0)));
+        let png = c
+            .encode(ImageFormat::PNG, &ExportOptions::default())
+            .unwrap();
+
+        assert_eq!(png[25], 2);
+        assert_eq!(&png[48..52], &[0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn encoding_tga() {
+        let mut c = Canvas::new(2, 1, Some(Color::new(0.0, 0.0, 1.0)));
+        let tga = c.to_tga(&ExportOptions::default()).unwrap();
+
+        assert_eq!(tga.len(), 18 + 2 * 3);
+        assert_eq!(&tga[12..18], &[2, 0, 1, 0, 24, 0x20]);
+        assert_eq!(&tga[18..21], &[255, 0, 0]);
+
+        c.write_pixel_with_alpha(1, 0, Color::new_black(), 0.0);
+        let tga = c.to_tga(&ExportOptions::default()).unwrap();
+
+        assert_eq!(&tga[16..18], &[32, 0x28]);
+        assert_eq!(&tga[18..], &[255, 0, 0, 255, 0, 0, 0, 0]);
+
+        let wide = Canvas::new(65536, 1, None);
+        assert_eq!(
+            wide.to_tga(&ExportOptions::default()),
+            Err(ImageError::Malformed("65536x1 is too large for TGA".into()))
+        );
+    }
+
+    #[test]
+    fn encoding_exr() {
+        let c = Canvas::new_transparent(3, 2);
+        let exr = c.to_exr();
+
+        assert_eq!(&exr[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        assert_eq!(&exr[8..24], b"channels\0chlist\0");
+
+        let line = 3 * 4 * 4;
+        let chunks = 2 * (8 + line);
+        let header_end = exr.len() - chunks - 2 * 8;
+
+        assert_eq!(exr[header_end - 1], 0);
+
+        let first = u64::from_le_bytes([
+            exr[header_end],
+            exr[header_end + 1],
+            exr[header_end + 2],
+            exr[header_end + 3],
+            exr[header_end + 4],
+            exr[header_end + 5],
+            exr[header_end + 6],
+            exr[header_end + 7],
+        ]);
+        assert_eq!(first as usize, exr.len() - chunks);
+    }
+
+    #[test]
+    fn exports_are_dithered() {
+        let c = Canvas::new(16, 16, Some(Color::new(100.5 / 255.0, 0.0, 1.0)));
+        let options = ExportOptions {
+            transfer: Transfer::Linear,
+            dither: Dither::Ordered,
+            ..Default::default()
+        };
+
+        let pixels = c.to_rgba8(&options, true);
+
+        assert!(pixels.iter().any(|p| p[0] == 100));
+        assert!(pixels.iter().any(|p| p[0] == 101));
+        assert!(pixels
+            .iter()
+            .all(|p| p[1] == 0 && p[2] == 255 && p[3] == 255));
+    }
 }
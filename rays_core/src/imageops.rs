@@ -0,0 +1,544 @@
+use std::f64::consts::PI;
+
+use crate::canvas::Canvas;
+use crate::color::Color;
+
+// premultiplied r, g, b and alpha
+type Rgba = [f64; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    /// Lanczos windowed sinc with a = 3
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn support(&self) -> f64 {
+        match self {
+            ResizeFilter::Nearest => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f64) -> f64 {
+        let x = x.abs();
+
+        match self {
+            ResizeFilter::Nearest => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+            ResizeFilter::Lanczos3 => {
+                if x < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-8 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+impl Canvas {
+    /// Builds a canvas by evaluating `f` for every pixel
+    pub fn from_fn<F>(width: u32, height: u32, mut f: F) -> Canvas
+    where
+        F: FnMut(u32, u32) -> Color,
+    {
+        let mut canvas = Canvas::new(width, height, None);
+
+        for y in 0..height {
+            for x in 0..width {
+                canvas.pixels[(y * width + x) as usize] = f(x, y);
+            }
+        }
+
+        canvas
+    }
+
+    /// Applies `f` to every pixel, passing its coordinates along
+    pub fn map<F>(&self, mut f: F) -> Canvas
+    where
+        F: FnMut(u32, u32, Color) -> Color,
+    {
+        let mut out = self.with_rgba(self.width, self.height, |x, y| self.rgba(x, y));
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = (y * self.width + x) as usize;
+                out.pixels[i] = f(x, y, self.pixels[i]);
+            }
+        }
+
+        out
+    }
+
+    /// Copies a `width` x `height` region starting at (x, y), clipped to
+    /// the canvas
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Canvas {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+
+        self.with_rgba(width, height, |cx, cy| self.rgba(x + cx, y + cy))
+    }
+
+    pub fn flip_horizontal(&self) -> Canvas {
+        self.with_rgba(self.width, self.height, |x, y| {
+            self.rgba(self.width - 1 - x, y)
+        })
+    }
+
+    pub fn flip_vertical(&self) -> Canvas {
+        self.with_rgba(self.width, self.height, |x, y| {
+            self.rgba(x, self.height - 1 - y)
+        })
+    }
+
+    /// Rotates a quarter turn clockwise
+    pub fn rotate_90(&self) -> Canvas {
+        self.with_rgba(self.height, self.width, |x, y| {
+            self.rgba(y, self.height - 1 - x)
+        })
+    }
+
+    pub fn rotate_180(&self) -> Canvas {
+        self.with_rgba(self.width, self.height, |x, y| {
+            self.rgba(self.width - 1 - x, self.height - 1 - y)
+        })
+    }
+
+    /// Rotates a quarter turn counter-clockwise
+    pub fn rotate_270(&self) -> Canvas {
+        self.with_rgba(self.height, self.width, |x, y| {
+            self.rgba(self.width - 1 - y, x)
+        })
+    }
+
+    /// Copies `src` onto this canvas with its top left corner at (x, y).
+    /// Pixels falling outside the canvas are skipped.
+    pub fn blit(&mut self, src: &Canvas, x: i64, y: i64) {
+        if src.has_alpha() {
+            self.enable_alpha();
+        }
+
+        for sy in 0..src.height {
+            for sx in 0..src.width {
+                let (dx, dy) = (x + sx as i64, y + sy as i64);
+
+                if dx < 0 || dy < 0 || dx >= self.width as i64 || dy >= self.height as i64 {
+                    continue;
+                }
+
+                let [r, g, b, a] = src.rgba(sx, sy);
+                self.set_rgba(dx as u32, dy as u32, [r, g, b, a]);
+            }
+        }
+    }
+
+    pub fn resize(&self, width: u32, height: u32, filter: ResizeFilter) -> Canvas {
+        let horizontal = resample(
+            &self.rgba_buffer(),
+            (self.width as usize, self.height as usize),
+            width as usize,
+            true,
+            filter,
+        );
+        let both = resample(
+            &horizontal,
+            (width as usize, self.height as usize),
+            height as usize,
+            false,
+            filter,
+        );
+
+        self.with_rgba(width, height, |x, y| both[(y * width + x) as usize])
+    }
+
+    /// Convolves with a horizontal and a vertical kernel (each of odd length,
+    /// centered on the pixel), extending the edges
+    pub fn convolve_separable(&self, kernel_x: &[f64], kernel_y: &[f64]) -> Canvas {
+        let size = (self.width as usize, self.height as usize);
+        let horizontal = convolve_axis(&self.rgba_buffer(), size, kernel_x, true);
+        let both = convolve_axis(&horizontal, size, kernel_y, false);
+
+        self.with_rgba(self.width, self.height, |x, y| {
+            both[(y * self.width + x) as usize]
+        })
+    }
+
+    pub fn gaussian_blur(&self, sigma: f64) -> Canvas {
+        let kernel = gaussian_kernel(sigma);
+        self.convolve_separable(&kernel, &kernel)
+    }
+
+    /// Unsharp masking: adds `amount` times the difference to a blurred copy
+    pub fn sharpen(&self, sigma: f64, amount: f64) -> Canvas {
+        let blurred = self.gaussian_blur(sigma);
+
+        self.with_rgba(self.width, self.height, |x, y| {
+            let (o, b) = (self.rgba(x, y), blurred.rgba(x, y));
+            let mut out = [0.; 4];
+
+            for c in 0..4 {
+                out[c] = o[c] + amount * (o[c] - b[c]);
+            }
+            out[3] = out[3].clamp(0.0, 1.0);
+
+            out
+        })
+    }
+
+    fn rgba(&self, x: u32, y: u32) -> Rgba {
+        let i = (y * self.width + x) as usize;
+        let c = self.pixels[i];
+
+        [c.r, c.g, c.b, self.alpha_at_index(i)]
+    }
+
+    fn set_rgba(&mut self, x: u32, y: u32, [r, g, b, a]: Rgba) {
+        let i = (y * self.width + x) as usize;
+        self.pixels[i] = Color::new(r, g, b);
+
+        if let Some(alpha) = self.alpha.as_mut() {
+            alpha[i] = a;
+        }
+    }
+
+    fn rgba_buffer(&self) -> Vec<Rgba> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.rgba(x, y))
+            .collect()
+    }
+
+    /// A new canvas, keeping the alpha channel if this canvas has one
+    fn with_rgba<F>(&self, width: u32, height: u32, mut f: F) -> Canvas
+    where
+        F: FnMut(u32, u32) -> Rgba,
+    {
+        let mut out = Canvas::new(width, height, None);
+        if self.has_alpha() {
+            out.enable_alpha();
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                out.set_rgba(x, y, f(x, y));
+            }
+        }
+
+        out
+    }
+}
+
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    // a zero width blur leaves the image as is
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+
+    let radius = (3.0 * sigma).ceil().max(0.0) as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+
+    weights.iter().map(|w| w / sum).collect()
+}
+
+fn convolve_axis(
+    src: &[Rgba],
+    (w, h): (usize, usize),
+    kernel: &[f64],
+    horizontal: bool,
+) -> Vec<Rgba> {
+    let radius = (kernel.len() / 2) as i64;
+    let mut out = vec![[0.; 4]; src.len()];
+
+    for y in 0..h {
+        for x in 0..w {
+            let mut acc = [0.; 4];
+
+            for (k, weight) in kernel.iter().enumerate() {
+                let offset = k as i64 - radius;
+                let (sx, sy) = if horizontal {
+                    ((x as i64 + offset).clamp(0, w as i64 - 1) as usize, y)
+                } else {
+                    (x, (y as i64 + offset).clamp(0, h as i64 - 1) as usize)
+                };
+
+                let p = src[sy * w + sx];
+                for c in 0..4 {
+                    acc[c] += p[c] * weight;
+                }
+            }
+
+            out[y * w + x] = acc;
+        }
+    }
+
+    out
+}
+
+/// Resamples one axis of a `w` x `h` buffer to `len` pixels
+fn resample(
+    src: &[Rgba],
+    (w, h): (usize, usize),
+    len: usize,
+    horizontal: bool,
+    filter: ResizeFilter,
+) -> Vec<Rgba> {
+    let src_len = if horizontal { w } else { h };
+    let (out_w, out_h) = if horizontal { (len, h) } else { (w, len) };
+    let mut out = vec![[0.; 4]; out_w * out_h];
+
+    if src_len == 0 || len == 0 {
+        return out;
+    }
+
+    let scale = src_len as f64 / len as f64;
+    // widen the filter when shrinking so every source pixel contributes
+    let stretch = scale.max(1.0);
+    let support = filter.support() * stretch;
+
+    for o in 0..len {
+        let center = (o as f64 + 0.5) * scale - 0.5;
+        let taps: Vec<(usize, f64)> = if filter == ResizeFilter::Nearest {
+            vec![((((o as f64 + 0.5) * scale) as usize).min(src_len - 1), 1.0)]
+        } else {
+            let first = (center - support).floor() as i64;
+            let last = (center + support).ceil() as i64;
+
+            (first..=last)
+                .map(|i| {
+                    let weight = filter.weight((i as f64 - center) / stretch);
+                    (i.clamp(0, src_len as i64 - 1) as usize, weight)
+                })
+                .filter(|&(_, weight)| weight != 0.0)
+                .collect()
+        };
+        let total: f64 = taps.iter().map(|&(_, weight)| weight).sum();
+
+        for line in 0..(if horizontal { h } else { w }) {
+            let mut acc = [0.; 4];
+
+            for &(i, weight) in &taps {
+                let p = if horizontal {
+                    src[line * w + i]
+                } else {
+                    src[i * w + line]
+                };
+                for c in 0..4 {
+                    acc[c] += p[c] * weight / total;
+                }
+            }
+
+            let dst = if horizontal {
+                line * out_w + o
+            } else {
+                o * out_w + line
+            };
+            out[dst] = acc;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> Canvas {
+        Canvas::from_fn(width, height, |x, y| Color::new(x as f64, y as f64, 0.0))
+    }
+
+    #[test]
+    fn building_a_canvas_from_a_function() {
+        let c = gradient(3, 2);
+
+        assert_eq!(c.pixel_at(2, 1), Some(Color::new(2.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn mapping_pixels() {
+        let c = gradient(3, 2).map(|x, _, color| color * 2.0 + Color::new(0.0, 0.0, x as f64));
+
+        assert_eq!(c.pixel_at(2, 1), Some(Color::new(4.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn cropping_is_clipped_to_the_canvas() {
+        let c = gradient(4, 4);
+
+        let cropped = c.crop(1, 2, 2, 2);
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(cropped.pixel_at(0, 0), Some(Color::new(1.0, 2.0, 0.0)));
+        assert_eq!(cropped.pixel_at(1, 1), Some(Color::new(2.0, 3.0, 0.0)));
+
+        let clipped = c.crop(3, 3, 10, 10);
+        assert_eq!((clipped.width, clipped.height), (1, 1));
+    }
+
+    #[test]
+    fn flipping_and_rotating() {
+        let c = gradient(3, 2);
+
+        assert_eq!(
+            c.flip_horizontal().pixel_at(0, 0),
+            Some(Color::new(2.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            c.flip_vertical().pixel_at(0, 0),
+            Some(Color::new(0.0, 1.0, 0.0))
+        );
+        assert_eq!(
+            c.rotate_180().pixel_at(0, 0),
+            Some(Color::new(2.0, 1.0, 0.0))
+        );
+
+        // the bottom left corner moves to the top left when turning clockwise
+        let cw = c.rotate_90();
+        assert_eq!((cw.width, cw.height), (2, 3));
+        assert_eq!(cw.pixel_at(0, 0), Some(Color::new(0.0, 1.0, 0.0)));
+        assert_eq!(cw.pixel_at(1, 2), Some(Color::new(2.0, 0.0, 0.0)));
+
+        let ccw = c.rotate_270();
+        assert_eq!(ccw.pixel_at(0, 0), Some(Color::new(2.0, 0.0, 0.0)));
+        assert_eq!(ccw.rotate_90().pixel_at(2, 1), c.pixel_at(2, 1));
+    }
+
+    #[test]
+    fn blitting_with_offset() {
+        let mut dst = Canvas::new(4, 4, None);
+        let src = Canvas::new(2, 2, Some(Color::new(1.0, 1.0, 1.0)));
+
+        dst.blit(&src, -1, 3);
+
+        assert_eq!(dst.pixel_at(0, 3), Some(Color::new(1.0, 1.0, 1.0)));
+        assert_eq!(dst.pixel_at(1, 3), Some(Color::new_black()));
+        assert_eq!(dst.pixel_at(0, 2), Some(Color::new_black()));
+    }
+
+    #[test]
+    fn blitting_keeps_alpha() {
+        let mut dst = Canvas::new(2, 1, None);
+        let src = Canvas::new_transparent(1, 1);
+
+        dst.blit(&src, 1, 0);
+
+        assert_eq!(dst.alpha_at(0, 0), Some(1.0));
+        assert_eq!(dst.alpha_at(1, 0), Some(0.0));
+    }
+
+    #[test]
+    fn resizing_nearest_neighbour() {
+        let c = gradient(2, 2).resize(4, 4, ResizeFilter::Nearest);
+
+        assert_eq!(c.pixel_at(1, 1), Some(Color::new(0.0, 0.0, 0.0)));
+        assert_eq!(c.pixel_at(2, 1), Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(c.pixel_at(3, 3), Some(Color::new(1.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn resizing_preserves_flat_colors() {
+        let c = Canvas::new(7, 5, Some(Color::new(0.3, 0.6, 0.9)));
+
+        for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3].iter() {
+            for &(w, h) in &[(3, 2), (16, 11)] {
+                let resized = c.resize(w, h, *filter);
+
+                assert!(resized
+                    .pixels
+                    .iter()
+                    .all(|&p| p == Color::new(0.3, 0.6, 0.9)));
+            }
+        }
+    }
+
+    #[test]
+    fn downscaling_filters_out_fine_detail() {
+        let c = Canvas::from_fn(8, 1, |x, _| Color::new((x % 2) as f64, 0.0, 0.0));
+
+        for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3].iter() {
+            let quarter = c.resize(2, 1, *filter);
+
+            for x in 0..2 {
+                assert!((quarter.pixel_at(x, 0).unwrap().r - 0.5).abs() < 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn bilinear_upscale_interpolates() {
+        let c = gradient(2, 1).resize(4, 1, ResizeFilter::Bilinear);
+
+        assert_eq!(c.pixel_at(0, 0), Some(Color::new(0.0, 0.0, 0.0)));
+        assert_eq!(c.pixel_at(1, 0), Some(Color::new(0.25, 0.0, 0.0)));
+        assert_eq!(c.pixel_at(2, 0), Some(Color::new(0.75, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn gaussian_blur_spreads_energy() {
+        let c = Canvas::from_fn(9, 9, |x, y| {
+            let v = if (x, y) == (4, 4) { 1.0 } else { 0.0 };
+            Color::new(v, v, v)
+        });
+
+        let blurred = c.gaussian_blur(1.0);
+        let total: f64 = blurred.pixels.iter().map(|p| p.r).sum();
+        let center = blurred.pixel_at(4, 4).unwrap().r;
+
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(center < 1.0 && center > blurred.pixel_at(5, 4).unwrap().r);
+        assert_eq!(blurred.pixel_at(5, 4), blurred.pixel_at(4, 3));
+        assert_eq!(c.gaussian_blur(0.0).pixels, c.pixels);
+    }
+
+    #[test]
+    fn convolving_with_a_box_kernel() {
+        let c = Canvas::from_fn(3, 1, |x, _| Color::new(x as f64 * 3.0, 0.0, 0.0));
+        let third = 1.0 / 3.0;
+        let boxed = c.convolve_separable(&[third, third, third], &[1.0]);
+
+        // edges are extended
+        assert_eq!(boxed.pixel_at(0, 0), Some(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(boxed.pixel_at(1, 0), Some(Color::new(3.0, 0.0, 0.0)));
+        assert_eq!(boxed.pixel_at(2, 0), Some(Color::new(5.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn sharpening_boosts_edges() {
+        let c = Canvas::from_fn(6, 1, |x, _| {
+            if x < 3 {
+                Color::new_black()
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            }
+        });
+        let sharp = c.sharpen(1.0, 1.0);
+
+        assert!(sharp.pixel_at(2, 0).unwrap().r < 0.0);
+        assert!(sharp.pixel_at(3, 0).unwrap().r > 1.0);
+
+        let flat = Canvas::new(4, 4, Some(Color::new(0.5, 0.5, 0.5))).sharpen(1.0, 2.0);
+        assert!(flat.pixels.iter().all(|&p| p == Color::new(0.5, 0.5, 0.5)));
+    }
+}
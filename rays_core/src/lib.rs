@@ -2,13 +2,33 @@ pub mod util;
 
 pub mod canvas;
 pub mod color;
+pub mod composite;
+pub mod diff;
+pub mod dither;
+pub mod draw;
 pub mod image;
+pub mod imageops;
 pub mod matrices;
+pub mod quaternion;
+pub mod ray;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod simd;
+pub mod tonemap;
 pub mod transformations;
 pub mod tuples;
 
-pub use canvas::Canvas;
-pub use color::Color;
+pub use canvas::{Canvas, CanvasError, CanvasMetadata};
+pub use color::{Color, ParseColorError};
+pub use composite::CompositeOp;
+pub use diff::ImageDiff;
+pub use dither::Dither;
+pub use image::{ExportOptions, ImageFormat, Transfer};
+pub use imageops::ResizeFilter;
+pub use quaternion::{EulerOrder, Quaternion};
+pub use ray::Ray;
+pub use tonemap::{ToneMap, ToneMapOperator};
+pub use util::Float;
 
 #[cfg(test)]
 mod tests {}
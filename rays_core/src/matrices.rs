@@ -2,230 +2,603 @@ use std::cmp::PartialEq;
 use std::ops::{Index, IndexMut, Mul};
 
 use crate::tuples::*;
-use crate::util::array_approx_equal;
-
-// Macro for implementing square matrices
-macro_rules! mat_impl {
-    ($mat_name:ident, $size:expr, $type:ident) => {
-        #[derive(Debug, Clone, Copy)]
-        pub struct $mat_name {
-            size: usize,
-            m: [[$type; $size]; $size],
+use crate::util::{array_approx_equal, Float};
+
+/// Row-major matrix with `R` rows and `C` columns
+#[derive(Debug, Clone, Copy)]
+pub struct Matrix<const R: usize, const C: usize, T = f64> {
+    m: [[T; C]; R],
+}
+
+pub type Matrix2x2<T = f64> = Matrix<2, 2, T>;
+pub type Matrix3x3<T = f64> = Matrix<3, 3, T>;
+pub type Matrix4x4<T = f64> = Matrix<4, 4, T>;
+
+impl<T: Float, const R: usize, const C: usize> Index<usize> for Matrix<R, C, T> {
+    type Output = [T; C];
+
+    fn index(&self, idx: usize) -> &Self::Output {
+        &self.m[idx]
+    }
+}
+
+impl<T: Float, const R: usize, const C: usize> IndexMut<usize> for Matrix<R, C, T> {
+    fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
+        &mut self.m[idx]
+    }
+}
+
+impl<T: Float, const R: usize, const C: usize> PartialEq for Matrix<R, C, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.m
+            .iter()
+            .zip(other.m.iter())
+            .all(|(a, b)| array_approx_equal(a, b))
+    }
+}
+
+// Copies the overlapping top-left block into an array of another shape; the
+// closed forms below only call it when both shapes are the same, which the
+// compiler sees through
+fn resize<T: Float, const R1: usize, const C1: usize, const R2: usize, const C2: usize>(
+    m: &[[T; C1]; R1],
+) -> [[T; C2]; R2] {
+    let mut out = [[T::ZERO; C2]; R2];
+
+    for (out_row, row) in out.iter_mut().zip(m.iter()) {
+        for (o, val) in out_row.iter_mut().zip(row.iter()) {
+            *o = *val;
         }
+    }
 
-        impl Index<usize> for $mat_name {
-            type Output = [$type; $size];
+    out
+}
+
+impl<T: Float, const R: usize, const C: usize> Matrix<R, C, T> {
+    pub fn new(matrix: [[T; C]; R]) -> Self {
+        Self { m: matrix }
+    }
+
+    pub fn zeros() -> Self {
+        Self::new([[T::ZERO; C]; R])
+    }
+
+    /// Number of rows and columns
+    pub fn shape(&self) -> (usize, usize) {
+        (R, C)
+    }
 
-            fn index(&self, idx: usize) -> &Self::Output {
-                &self.m[idx]
+    pub fn rows(&self) -> &[[T; C]; R] {
+        &self.m
+    }
+
+    /// Converts to another precision
+    pub fn cast<U: Float>(&self) -> Matrix<R, C, U> {
+        let mut m = [[U::ZERO; C]; R];
+
+        for (out, row) in m.iter_mut().zip(self.m.iter()) {
+            for (o, val) in out.iter_mut().zip(row.iter()) {
+                *o = U::from_f64(val.to_f64());
             }
         }
 
-        impl IndexMut<usize> for $mat_name {
-            fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
-                &mut self.m[idx]
+        Matrix { m }
+    }
+
+    pub fn transpose(&self) -> Matrix<C, R, T> {
+        let mut m = [[T::ZERO; R]; C];
+
+        for (r, row) in self.m.iter().enumerate() {
+            for (c, val) in row.iter().enumerate() {
+                m[c][r] = *val;
             }
         }
 
-        impl PartialEq for $mat_name {
-            fn eq(&self, other: &Self) -> bool {
-                self.m
-                    .iter()
-                    .zip(other.m.iter())
-                    .all(|(a, b)| array_approx_equal(a, b))
+        Matrix { m }
+    }
+
+    /// Largest absolute row sum
+    pub fn norm_inf(&self) -> T {
+        self.m
+            .iter()
+            .map(|row| row.iter().fold(T::ZERO, |sum, v| sum + v.abs()))
+            .fold(T::ZERO, T::max)
+    }
+
+    // The matrix without one row and one column, `M` must be one less than
+    // the matrix's size
+    fn remove<const M: usize>(&self, remove_row: usize, remove_col: usize) -> Matrix<M, M, T> {
+        debug_assert!(M + 1 == R && M + 1 == C);
+        let mut m = [[T::ZERO; M]; M];
+
+        let rows = self.m.iter().enumerate().filter(|(r, _)| *r != remove_row);
+        for (out, (_, row)) in m.iter_mut().zip(rows) {
+            let vals = row.iter().enumerate().filter(|(c, _)| *c != remove_col);
+            for (o, (_, val)) in out.iter_mut().zip(vals) {
+                *o = *val;
             }
         }
 
-        impl $mat_name {
-            pub fn new(matrix: [[$type; $size]; $size]) -> Self {
-                Self {
-                    size: $size,
-                    m: matrix,
-                }
+        Matrix { m }
+    }
+}
+
+// Relative error allowed in a computed inverse; the error is at most about
+// the condition number times the machine epsilon
+const MAX_INVERSE_ERROR: f64 = 1e-2;
+
+fn well_conditioned<T: Float>(condition: T) -> bool {
+    condition.is_finite() && condition * T::MACHINE_EPSILON < T::from_f64(MAX_INVERSE_ERROR)
+}
+
+impl<T: Float, const N: usize> Matrix<N, N, T> {
+    pub fn identity() -> Self {
+        let mut m = [[T::ZERO; N]; N];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = T::ONE;
+        }
+
+        Self { m }
+    }
+
+    /// Closed form up to 4x4, LU decomposition beyond
+    pub fn determinant(&self) -> T {
+        match N {
+            1 => self.m[0][0],
+            2 => det2(&resize(&self.m)),
+            3 => det3(&resize(&self.m)),
+            4 => det4(&resize(&self.m)),
+            _ => self.lu().map_or(T::ZERO, |lu| lu.determinant()),
+        }
+    }
+
+    /// Determinant of the matrix without `row` and `col`
+    pub fn minor(&self, row: usize, col: usize) -> T {
+        let min = self.cofactor(row, col);
+        // if row+col is even
+        if (row + col).is_multiple_of(2) {
+            min
+        } else {
+            -min
+        }
+    }
+
+    pub fn cofactor(&self, row: usize, col: usize) -> T {
+        // expanding along a row and column that are zero apart from a one
+        // where they cross leaves exactly the cofactor
+        let mut m = self.m;
+        for (r, vals) in m.iter_mut().enumerate() {
+            vals[col] = T::ZERO;
+            if r == row {
+                *vals = [T::ZERO; N];
             }
+        }
+        m[row][col] = T::ONE;
+
+        Self { m }.determinant()
+    }
 
-            pub fn transpose(&self) -> Self {
-                let mut m: [[$type; $size]; $size] = [[0 as $type; $size]; $size];
+    /// The inverse, or `None` when the matrix is singular or too badly
+    /// conditioned for the result to be accurate (see `is_invertible`)
+    pub fn inverse(&self) -> Option<Self> {
+        let inv = self.raw_inverse()?;
 
-                for (r, row) in self.m.iter().enumerate() {
-                    for c in 0..m.len() {
-                        m[c][r] = row[c];
-                    }
-                }
+        if well_conditioned(self.norm_inf() * inv.norm_inf()) {
+            Some(inv)
+        } else {
+            None
+        }
+    }
+
+    fn raw_inverse(&self) -> Option<Self> {
+        let m = match N {
+            2 => resize(&inv2(&resize(&self.m))?),
+            3 => resize(&inv3(&resize(&self.m))?),
+            4 => resize(&inv4(&resize(&self.m))?),
+            _ => return self.inverse_gauss_jordan(),
+        };
 
-                Self { size: $size, m: m }
+        Some(Self { m })
+    }
+
+    /// Inverse by Gauss-Jordan elimination with partial pivoting,
+    /// `None` when a pivot vanishes
+    pub fn inverse_gauss_jordan(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+
+        for col in 0..N {
+            let pivot = pivot_row(&a, col)?;
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = T::ONE / a[col][col];
+            for c in 0..N {
+                a[col][c] *= scale;
+                inv[col][c] *= scale;
             }
 
-            pub fn inverse(&self) -> Option<Self> {
-                match self.is_invertible() {
-                    false => None,
-                    true => {
-                        let mut inv: [[$type; $size]; $size] = [[0 as $type; $size]; $size];
-                        let determinant = self.determinant();
-
-                        for row in 0..self.size {
-                            for col in 0..self.size {
-                                let c = self.cofactor(row, col);
-                                inv[col][row] = c / determinant;
-                            }
-                        }
-
-                        Some(Self {
-                            m: inv,
-                            size: $size,
-                        })
-                    }
+            for row in 0..N {
+                let factor = a[row][col];
+                if row == col || factor == T::ZERO {
+                    continue;
                 }
-            }
 
-            pub fn determinant(&self) -> f64 {
-                match self.size {
-                    2 => (self.m[0][0] * self.m[1][1]) - (self.m[0][1] * self.m[1][0]),
-                    3..=4 => {
-                        let mut det: f64 = 0.;
-                        for col in 0..self.size {
-                            det = det + self.m[0][col] * self.cofactor(0, col)
-                        }
-
-                        det
-                    }
-                    _ => panic!("Unsupported size"),
+                for c in 0..N {
+                    a[row][c] -= factor * a[col][c];
+                    inv[row][c] -= factor * inv[col][c];
                 }
             }
+        }
 
-            pub fn minor(&self, row: usize, col: usize) -> f64 {
-                self.submatrix(row, col).determinant()
+        Some(Self::new(inv))
+    }
+
+    /// Condition number in the infinity norm, infinite when singular.
+    /// Roughly, `log10` of it is the number of digits lost when
+    /// inverting or solving with this matrix.
+    pub fn condition_number(&self) -> T {
+        match self.raw_inverse() {
+            Some(inv) => self.norm_inf() * inv.norm_inf(),
+            None => T::from_f64(f64::INFINITY),
+        }
+    }
+
+    /// True when the inverse can be computed accurately, which unlike
+    /// a zero determinant test does not depend on the matrix's scale
+    pub fn is_invertible(&self) -> bool {
+        well_conditioned(self.condition_number())
+    }
+
+    /// LU decomposition with partial pivoting, `None` when a pivot vanishes
+    pub fn lu(&self) -> Option<Lu<N, T>> {
+        let mut lu = self.m;
+        let mut perm = [0; N];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i;
+        }
+        let mut sign = T::ONE;
+
+        for col in 0..N {
+            let pivot = pivot_row(&lu, col)?;
+            if pivot != col {
+                lu.swap(col, pivot);
+                perm.swap(col, pivot);
+                sign = -sign;
             }
 
-            pub fn cofactor(&self, row: usize, col: usize) -> f64 {
-                let min = self.minor(row, col);
-                // if row+col is even
-                if (row + col) % 2 == 0 {
-                    min
-                } else {
-                    -min
+            let (top, below) = lu.split_at_mut(col + 1);
+            let pivot = &top[col];
+            for row in below.iter_mut() {
+                let factor = row[col] / pivot[col];
+                row[col] = factor;
+
+                for (v, p) in row[col + 1..].iter_mut().zip(pivot[col + 1..].iter()) {
+                    *v -= factor * *p;
                 }
             }
+        }
 
-            #[allow(dead_code)]
-            pub fn is_invertible(&self) -> bool {
-                self.determinant() != 0 as $type
-            }
+        Some(Lu { lu, perm, sign })
+    }
 
-            #[allow(dead_code)]
-            fn gen_submatrix(
-                &self,
-                remove_row: usize,
-                remove_col: usize,
-            ) -> [[$type; $size - 1]; $size - 1] {
-                let mut m: [[$type; $size - 1]; $size - 1] = [[0 as $type; $size - 1]; $size - 1];
-                let mut r_i = 0;
-                let mut c_i = 0;
-
-                for (ri, row) in self.m.iter().enumerate() {
-                    if ri == remove_row {
-                        continue;
-                    }
-
-                    for (ci, val) in row.iter().enumerate() {
-                        if ci == remove_col {
-                            continue;
-                        }
-                        m[r_i][c_i] = *val;
-                        c_i += 1;
-                    }
-
-                    r_i += 1;
-                    c_i = 0;
-                }
+    /// Solves `self * x = b`, `None` when the matrix is singular
+    pub fn solve(&self, b: [T; N]) -> Option<[T; N]> {
+        self.lu().map(|lu| lu.solve(b))
+    }
+}
+
+// Row with the largest entry in `col` at or below the diagonal, `None` when
+// they are all zero or any of them isn't finite
+fn pivot_row<T: Float, const N: usize>(a: &[[T; N]; N], col: usize) -> Option<usize> {
+    let mut pivot = col;
+    for row in col..N {
+        if !a[row][col].is_finite() {
+            return None;
+        }
+        if a[row][col].abs() > a[pivot][col].abs() {
+            pivot = row;
+        }
+    }
+
+    if a[pivot][col] == T::ZERO {
+        None
+    } else {
+        Some(pivot)
+    }
+}
 
-                m
+/// LU decomposition with row pivoting, `P * A = L * U`, where L has a unit
+/// diagonal. Factor once to solve for many right-hand sides.
+#[derive(Debug, Clone, Copy)]
+pub struct Lu<const N: usize, T = f64> {
+    // L below the diagonal, U on and above it
+    lu: [[T; N]; N],
+    perm: [usize; N],
+    sign: T,
+}
+
+impl<T: Float, const N: usize> Lu<N, T> {
+    pub fn lower(&self) -> Matrix<N, N, T> {
+        let mut m = Matrix::identity();
+        for (r, row) in self.lu.iter().enumerate() {
+            m.m[r][..r].copy_from_slice(&row[..r]);
+        }
+        m
+    }
+
+    pub fn upper(&self) -> Matrix<N, N, T> {
+        let mut m = Matrix::zeros();
+        for (r, row) in self.lu.iter().enumerate() {
+            m.m[r][r..].copy_from_slice(&row[r..]);
+        }
+        m
+    }
+
+    /// The permutation P as a matrix
+    pub fn permutation(&self) -> Matrix<N, N, T> {
+        let mut m = Matrix::zeros();
+        for (r, p) in self.perm.iter().enumerate() {
+            m.m[r][*p] = T::ONE;
+        }
+        m
+    }
+
+    pub fn determinant(&self) -> T {
+        (0..N).fold(self.sign, |det, i| det * self.lu[i][i])
+    }
+
+    /// Solves `A * x = b` by forward and back substitution
+    pub fn solve(&self, b: [T; N]) -> [T; N] {
+        let mut x = [T::ZERO; N];
+        for (i, p) in self.perm.iter().enumerate() {
+            x[i] = b[*p];
+        }
+
+        for i in 0..N {
+            for j in 0..i {
+                let l = self.lu[i][j] * x[j];
+                x[i] -= l;
+            }
+        }
+
+        for i in (0..N).rev() {
+            for j in i + 1..N {
+                let u = self.lu[i][j] * x[j];
+                x[i] -= u;
             }
+            x[i] /= self.lu[i][i];
         }
+
+        x
+    }
+}
+
+fn det2<T: Float>(m: &[[T; 2]; 2]) -> T {
+    (m[0][0] * m[1][1]) - (m[0][1] * m[1][0])
+}
+
+fn det3<T: Float>(m: &[[T; 3]; 3]) -> T {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn inv2<T: Float>(m: &[[T; 2]; 2]) -> Option<[[T; 2]; 2]> {
+    let det = det2(m);
+    if det == T::ZERO || !det.is_finite() {
+        return None;
+    }
+
+    Some([
+        [m[1][1] / det, -m[0][1] / det],
+        [-m[1][0] / det, m[0][0] / det],
+    ])
+}
+
+/// Adjugate divided by the determinant
+fn inv3<T: Float>(m: &[[T; 3]; 3]) -> Option<[[T; 3]; 3]> {
+    let det = det3(m);
+    if det == T::ZERO || !det.is_finite() {
+        return None;
+    }
+
+    let inv_det = T::ONE / det;
+    let c = |r0: usize, r1: usize, c0: usize, c1: usize| {
+        (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) * inv_det
     };
+
+    Some([
+        [c(1, 2, 1, 2), c(0, 2, 2, 1), c(0, 1, 1, 2)],
+        [c(1, 2, 2, 0), c(0, 2, 0, 2), c(0, 1, 2, 0)],
+        [c(1, 2, 0, 1), c(0, 2, 1, 0), c(0, 1, 0, 1)],
+    ])
+}
+
+// Laplace expansion over the 2x2 minors of the top two (s) and bottom
+// two (c) rows, shared by the determinant and the inverse
+fn minors_2x2<T: Float>(m: &[[T; 4]; 4]) -> ([T; 6], [T; 6]) {
+    let s = [
+        m[0][0] * m[1][1] - m[1][0] * m[0][1],
+        m[0][0] * m[1][2] - m[1][0] * m[0][2],
+        m[0][0] * m[1][3] - m[1][0] * m[0][3],
+        m[0][1] * m[1][2] - m[1][1] * m[0][2],
+        m[0][1] * m[1][3] - m[1][1] * m[0][3],
+        m[0][2] * m[1][3] - m[1][2] * m[0][3],
+    ];
+
+    let c = [
+        m[2][0] * m[3][1] - m[3][0] * m[2][1],
+        m[2][0] * m[3][2] - m[3][0] * m[2][2],
+        m[2][0] * m[3][3] - m[3][0] * m[2][3],
+        m[2][1] * m[3][2] - m[3][1] * m[2][2],
+        m[2][1] * m[3][3] - m[3][1] * m[2][3],
+        m[2][2] * m[3][3] - m[3][2] * m[2][3],
+    ];
+
+    (s, c)
 }
 
-// create basic square matrices
-mat_impl!(Matrix2x2, 2, f64);
-mat_impl!(Matrix3x3, 3, f64);
-mat_impl!(Matrix4x4, 4, f64);
+fn det4<T: Float>(m: &[[T; 4]; 4]) -> T {
+    let (s, c) = minors_2x2(m);
 
-impl Matrix2x2 {
-    pub fn submatrix(&self, _: usize, _: usize) -> Self {
-        unimplemented!();
+    s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+}
+
+/// Closed-form adjugate divided by the determinant
+fn inv4<T: Float>(m: &[[T; 4]; 4]) -> Option<[[T; 4]; 4]> {
+    let (s, c) = minors_2x2(m);
+
+    let det = s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+    if det == T::ZERO || !det.is_finite() {
+        return None;
     }
+
+    let d = T::ONE / det;
+
+    Some([
+        [
+            (m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3]) * d,
+            (-m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3]) * d,
+            (m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3]) * d,
+            (-m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3]) * d,
+        ],
+        [
+            (-m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1]) * d,
+            (m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1]) * d,
+            (-m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1]) * d,
+            (m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1]) * d,
+        ],
+        [
+            (m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0]) * d,
+            (-m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0]) * d,
+            (m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0]) * d,
+            (-m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0]) * d,
+        ],
+        [
+            (-m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0]) * d,
+            (m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0]) * d,
+            (-m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0]) * d,
+            (m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0]) * d,
+        ],
+    ])
 }
 
-impl Matrix3x3 {
-    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix2x2 {
-        let m = self.gen_submatrix(remove_row, remove_col);
-        Matrix2x2::new(m)
+impl<T: Float> Matrix2x2<T> {
+    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix<1, 1, T> {
+        self.remove(remove_row, remove_col)
     }
 }
 
-impl Matrix4x4 {
-    pub fn identity() -> Self {
-        Self {
-            size: 4,
-            m: [
-                [1., 0., 0., 0.],
-                [0., 1., 0., 0.],
-                [0., 0., 1., 0.],
-                [0., 0., 0., 1.],
-            ],
-        }
+impl<T: Float> Matrix3x3<T> {
+    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix2x2<T> {
+        self.remove(remove_row, remove_col)
     }
+}
 
-    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix3x3 {
-        let m = self.gen_submatrix(remove_row, remove_col);
-        Matrix3x3::new(m)
+impl<T: Float> Matrix4x4<T> {
+    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix3x3<T> {
+        self.remove(remove_row, remove_col)
+    }
+
+    /// True when the bottom row is (0, 0, 0, 1), as for all translations,
+    /// rotations, scalings and shears
+    pub fn is_affine(&self) -> bool {
+        let (o, l) = (T::ZERO, T::ONE);
+        self.m[3] == [o, o, o, l]
+    }
+
+    /// Fast inverse of an affine matrix: inverts the 3x3 linear part and
+    /// maps the translation back through it. Falls back to `inverse` for
+    /// other matrices.
+    pub fn inverse_affine(&self) -> Option<Self> {
+        if !self.is_affine() {
+            return self.inverse();
+        }
+
+        let m = &self.m;
+        let linear = Matrix3x3::new(resize(m)).inverse()?;
+
+        let l = &linear.m;
+        let t = [m[0][3], m[1][3], m[2][3]];
+        let row = |r: usize| -(l[r][0] * t[0] + l[r][1] * t[1] + l[r][2] * t[2]);
+        let (o, one) = (T::ZERO, T::ONE);
+
+        Some(Self::new([
+            [l[0][0], l[0][1], l[0][2], row(0)],
+            [l[1][0], l[1][1], l[1][2], row(1)],
+            [l[2][0], l[2][1], l[2][2], row(2)],
+            [o, o, o, one],
+        ]))
     }
 }
 
-impl Mul for Matrix4x4 {
-    type Output = Self;
+/// Products of compatible shapes; 4x4 products go through the SIMD kernel
+impl<T: Float, const R: usize, const C: usize, const K: usize> Mul<Matrix<C, K, T>>
+    for Matrix<R, C, T>
+{
+    type Output = Matrix<R, K, T>;
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        let mut m = [[0.; 4]; 4];
+    fn mul(self, rhs: Matrix<C, K, T>) -> Self::Output {
+        if R == 4 && C == 4 && K == 4 {
+            let m = T::mat4_mul(&resize(&self.m), &resize(&rhs.m));
+            return Matrix::new(resize(&m));
+        }
 
-        for row in 0..m.len() {
-            for col in 0..m.len() {
-                m[row][col] = self.m[row][0] * rhs.m[0][col]
-                    + self.m[row][1] * rhs.m[1][col]
-                    + self.m[row][2] * rhs.m[2][col]
-                    + self.m[row][3] * rhs.m[3][col];
+        let mut m = [[T::ZERO; K]; R];
+        for (out, row) in m.iter_mut().zip(self.m.iter()) {
+            for (k, o) in out.iter_mut().enumerate() {
+                *o = row.iter().zip(rhs.m.iter()).map(|(a, b)| *a * b[k]).sum();
             }
         }
 
-        Matrix4x4 { size: 4, m }
+        Matrix::new(m)
+    }
+}
+
+impl<T: Float> Mul<Tuple<T>> for Matrix4x4<T> {
+    type Output = Tuple<T>;
+
+    fn mul(self, rhs: Tuple<T>) -> Self::Output {
+        Tuple::from_array(T::mat4_mul_vec4(&self.m, rhs.to_array()))
     }
 }
 
-impl Mul<Tuple> for Matrix4x4 {
-    type Output = Tuple;
+/// Points pick up the translation column, the w row is skipped since it
+/// is 1 for affine transforms
+impl<T: Float> Mul<Point3<T>> for Matrix4x4<T> {
+    type Output = Point3<T>;
 
-    fn mul(self, rhs: Tuple) -> Self::Output {
-        let mut t = tuple(0., 0., 0., 0.);
+    fn mul(self, p: Point3<T>) -> Self::Output {
+        let [x, y, z] = T::mat4_transform3(&self.m, [p.x, p.y, p.z], T::ONE);
+        point(x, y, z)
+    }
+}
 
-        // dot product of each row and the tuple as a one column matrix
-        for (i, row) in self.m.iter().enumerate() {
-            let val = row[0] * rhs.x + row[1] * rhs.y + row[2] * rhs.z + row[3] * rhs.w;
+/// Vectors ignore the translation column and the w row
+impl<T: Float> Mul<Vector3<T>> for Matrix4x4<T> {
+    type Output = Vector3<T>;
 
-            match i {
-                0 => t.x = val,
-                1 => t.y = val,
-                2 => t.z = val,
-                3 => t.w = val,
-                _ => (),
-            }
-        }
-        t
+    fn mul(self, v: Vector3<T>) -> Self::Output {
+        let [x, y, z] = T::mat4_transform3(&self.m, [v.x, v.y, v.z], T::ZERO);
+        vector(x, y, z)
+    }
+}
+
+/// Multiplies like a vector; pass the inverse transpose of the surface's
+/// transform and normalize the result
+impl<T: Float> Mul<Normal3<T>> for Matrix4x4<T> {
+    type Output = Normal3<T>;
+
+    fn mul(self, n: Normal3<T>) -> Self::Output {
+        (self * Vector3::from(n)).into()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::approx_equal;
 
     #[test]
     fn construct_4x4_matrix() {
@@ -355,7 +728,7 @@ mod tests {
 
         let identity = Matrix4x4::identity();
 
-        assert_eq!(a.clone() * identity, a);
+        assert_eq!(a * identity, a);
     }
 
     #[test]
@@ -363,7 +736,7 @@ mod tests {
         let a = tuple(1., 2., 3., 4.);
         let identity = Matrix4x4::identity();
 
-        assert_eq!(identity * a.clone(), a);
+        assert_eq!(identity * a, a);
     }
 
     #[test]
@@ -385,7 +758,10 @@ mod tests {
         assert_eq!(a.transpose(), expected_result);
 
         // The transpose of the identity, equals the identity
-        assert_eq!(Matrix4x4::identity().transpose(), Matrix4x4::identity());
+        assert_eq!(
+            Matrix4x4::<f64>::identity().transpose(),
+            Matrix4x4::identity()
+        );
     }
 
     #[test]
@@ -486,7 +862,7 @@ mod tests {
 
         // If determinant 0, then a is not invertible
         assert_eq!(a.determinant(), 0.);
-        assert_eq!(a.is_invertible(), false);
+        assert!(!a.is_invertible());
     }
 
     #[test]
@@ -567,4 +943,197 @@ mod tests {
 
         assert_eq!((a * b) * b.inverse().unwrap(), a);
     }
+
+    #[test]
+    fn single_precision_matrices() {
+        let a: Matrix4x4<f32> = Matrix4x4::new([
+            [8., -5., 9., 2.],
+            [7., 5., 6., 1.],
+            [-6., 0., 9., 6.],
+            [-3., 0., -9., -4.],
+        ]);
+
+        assert_eq!(a.determinant(), -585.);
+        assert_eq!(a * a.inverse().unwrap(), Matrix4x4::identity());
+        assert_eq!(a.cast::<f64>().cast::<f32>(), a);
+    }
+
+    #[test]
+    fn multiplying_points_vectors_and_normals() {
+        let a = Matrix4x4::new([
+            [1., 2., 3., 4.],
+            [2., 4., 4., 2.],
+            [8., 6., 4., 1.],
+            [0., 0., 0., 1.],
+        ]);
+
+        assert_eq!(a * point(1., 2., 3.), point(18., 24., 33.));
+        assert_eq!(a * vector(1., 2., 3.), vector(14., 22., 32.));
+        assert_eq!(a * normal(1., 2., 3.), normal(14., 22., 32.));
+        // same as the homogeneous product
+        assert_eq!(a * point(1., 2., 3.), a * tuple(1., 2., 3., 1.));
+    }
+
+    #[test]
+    fn gauss_jordan_inverse_matches_closed_form() {
+        let a = Matrix4x4::new([
+            [0., 2., 6., -8.],
+            [1., -5., 1., 8.],
+            [7., 7., -6., -7.],
+            [1., -3., 7., 4.],
+        ]);
+
+        // the zero in the corner forces a row swap
+        assert_eq!(a.inverse_gauss_jordan().unwrap(), a.inverse().unwrap());
+        assert_eq!(a * a.inverse_gauss_jordan().unwrap(), Matrix4x4::identity());
+
+        let b = Matrix3x3::new([[1., 2., 6.], [-5., 8., -4.], [2., 6., 4.]]);
+        assert_eq!(b.inverse_gauss_jordan().unwrap(), b.inverse().unwrap());
+
+        let singular = Matrix4x4::new([
+            [-4., 2., -2., -3.],
+            [9., 6., 2., 6.],
+            [0., -5., 1., -5.],
+            [0., 0., 0., 0.],
+        ]);
+        assert_eq!(singular.inverse_gauss_jordan(), None);
+
+        let mut broken = a;
+        broken[2][0] = f64::NAN;
+        assert_eq!(broken.inverse_gauss_jordan(), None);
+
+        let mut broken = Matrix::<5, 5>::identity();
+        broken[3][3] = f64::NAN;
+        assert!(broken.lu().is_none());
+        assert_eq!(broken.solve([1.; 5]), None);
+    }
+
+    #[test]
+    fn invertibility_depends_on_conditioning_not_scale() {
+        // tiny determinant, but perfectly conditioned
+        let tiny = Matrix4x4::new([
+            [1e-6, 0., 0., 0.],
+            [0., 1e-6, 0., 0.],
+            [0., 0., 1e-6, 0.],
+            [0., 0., 0., 1.],
+        ]);
+        assert!(tiny.is_invertible());
+        assert!(tiny.inverse().is_some());
+
+        // non-zero determinant, but two rows are equal up to rounding noise
+        let nearly_singular = Matrix4x4::new([
+            [1., 2., 3., 4.],
+            [1., 2., 3., 4. + 1e-15],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+        ]);
+        assert!(nearly_singular.determinant() != 0.);
+        assert!(!nearly_singular.is_invertible());
+        assert_eq!(nearly_singular.inverse(), None);
+
+        assert!(Matrix4x4::<f64>::identity().condition_number() == 1.);
+    }
+
+    #[test]
+    fn affine_inverse_matches_general_inverse() {
+        use crate::transformations::*;
+
+        let t = translation(5., -3., 2.)
+            * rotation_y(0.7)
+            * shearing(0.5, 0., 0., 0.2, 0., 0.)
+            * scaling(2., 0.5, 3.);
+
+        assert!(t.is_affine());
+        assert_eq!(t.inverse_affine().unwrap(), t.inverse().unwrap());
+        assert_eq!(t * t.inverse_affine().unwrap(), Matrix4x4::identity());
+
+        // non-affine matrices take the general path
+        let a = Matrix4x4::new([
+            [8., -5., 9., 2.],
+            [7., 5., 6., 1.],
+            [-6., 0., 9., 6.],
+            [-3., 0., -9., -4.],
+        ]);
+        assert!(!a.is_affine());
+        assert_eq!(a.inverse_affine(), a.inverse());
+
+        assert_eq!(scaling(1., 0., 1.).inverse_affine(), None);
+    }
+
+    #[test]
+    fn multiplying_and_transposing_non_square_matrices() {
+        let a = Matrix::new([[1., 2., 3.], [4., 5., 6.]]);
+        let b = Matrix::new([[7., 8.], [9., 10.], [11., 12.]]);
+
+        assert_eq!(a * b, Matrix::new([[58., 64.], [139., 154.]]));
+        assert_eq!((a * b).shape(), (2, 2));
+        assert_eq!((b * a).shape(), (3, 3));
+        assert_eq!(a.transpose(), Matrix::new([[1., 4.], [2., 5.], [3., 6.]]));
+        assert_eq!((a * b).transpose(), b.transpose() * a.transpose());
+
+        let column = Matrix::new([[1.], [0.], [-1.]]);
+        assert_eq!(a * column, Matrix::new([[-2.], [-2.]]));
+    }
+
+    #[test]
+    fn submatrix_of_2x2_matrix() {
+        let a = Matrix2x2::new([[1., 5.], [-3., 2.]]);
+
+        assert_eq!(a.submatrix(0, 1), Matrix::new([[-3.]]));
+        assert_eq!(a.minor(0, 1), -3.);
+        assert_eq!(a.cofactor(0, 1), 3.);
+    }
+
+    fn matrix5() -> Matrix<5, 5> {
+        Matrix::new([
+            [2., -1., 0., 3., 1.],
+            [4., 1., -2., 0., 5.],
+            [-1., 3., 1., 2., 0.],
+            [0., 2., 4., -3., 1.],
+            [3., 0., 1., 1., -2.],
+        ])
+    }
+
+    #[test]
+    fn determinant_and_inverse_of_5x5_matrix() {
+        let a = matrix5();
+
+        // expanding along the first row
+        let expansion: f64 = (0..5).map(|c| a[0][c] * a.cofactor(0, c)).sum();
+        assert!(approx_equal(a.determinant(), expansion));
+        assert!(approx_equal(a.determinant(), 1226.));
+
+        let inv = a.inverse().unwrap();
+        assert_eq!(a * inv, Matrix::identity());
+        assert_eq!(inv, a.inverse_gauss_jordan().unwrap());
+    }
+
+    #[test]
+    fn lu_decomposition_reconstructs_the_matrix() {
+        let a = matrix5();
+        let lu = a.lu().unwrap();
+
+        assert_eq!(lu.permutation() * a, lu.lower() * lu.upper());
+        assert!(approx_equal(lu.determinant(), a.determinant()));
+
+        let singular = Matrix3x3::new([[1., 2., 3.], [2., 4., 6.], [0., 1., 1.]]);
+        assert!(singular.lu().is_none());
+        assert_eq!(singular.determinant(), 0.);
+    }
+
+    #[test]
+    fn solving_linear_systems() {
+        // 2x + y - z = 8, -3x - y + 2z = -11, -2x + y + 2z = -3
+        let a = Matrix3x3::new([[2., 1., -1.], [-3., -1., 2.], [-2., 1., 2.]]);
+        let x = a.solve([8., -11., -3.]).unwrap();
+        assert!(array_approx_equal(&x, &[2., 3., -1.]));
+
+        let a = matrix5();
+        let expected = [1., -2., 0.5, 3., -1.];
+        let b = a * Matrix::new(expected.map(|v| [v]));
+        let x = a.lu().unwrap().solve(b.transpose()[0]);
+        assert!(array_approx_equal(&x, &expected));
+
+        assert_eq!(Matrix2x2::new([[1., 2.], [2., 4.]]).solve([1., 1.]), None);
+    }
 }
@@ -0,0 +1,457 @@
+use std::cmp::PartialEq;
+use std::ops::{Mul, Neg};
+
+use crate::matrices::Matrix4x4;
+use crate::tuples::{point, vector, Point3, Vector3};
+use crate::util::{approx_equal, Float};
+
+/// Order in which Euler angle rotations are applied: `XYZ` rotates around
+/// x first, then y, then z, which is `rotation_z * rotation_y * rotation_x`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EulerOrder {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+impl EulerOrder {
+    // Axis indices in the order they are applied
+    fn axes(self) -> [usize; 3] {
+        match self {
+            EulerOrder::XYZ => [0, 1, 2],
+            EulerOrder::XZY => [0, 2, 1],
+            EulerOrder::YXZ => [1, 0, 2],
+            EulerOrder::YZX => [1, 2, 0],
+            EulerOrder::ZXY => [2, 0, 1],
+            EulerOrder::ZYX => [2, 1, 0],
+        }
+    }
+}
+
+/// A rotation as a unit quaternion `w + xi + yj + zk`. Unlike Euler angles
+/// these compose without gimbal lock and interpolate with `slerp` into
+/// rigid in-between rotations.
+#[derive(Debug, Clone, Copy)]
+pub struct Quaternion<T = f64> {
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T: Float> Default for Quaternion<T> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T: Float> PartialEq for Quaternion<T> {
+    fn eq(&self, rhs: &Self) -> bool {
+        approx_equal(self.w, rhs.w)
+            && approx_equal(self.x, rhs.x)
+            && approx_equal(self.y, rhs.y)
+            && approx_equal(self.z, rhs.z)
+    }
+}
+
+impl<T: Float> Quaternion<T> {
+    pub fn new(w: T, x: T, y: T, z: T) -> Self {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(T::ONE, T::ZERO, T::ZERO, T::ZERO)
+    }
+
+    /// Rotation by `r` radians around `axis`, matching `rotation_axis_angle`
+    pub fn from_axis_angle(axis: Vector3<T>, r: T) -> Self {
+        let half = r / T::from_f64(2.);
+        let a = axis.normalize() * half.sin();
+
+        Self::new(half.cos(), a.x, a.y, a.z)
+    }
+
+    /// Unit axis and angle in radians in `[0, 2pi]`; the axis is +x for
+    /// the identity
+    pub fn to_axis_angle(&self) -> (Vector3<T>, T) {
+        let q = self.normalize();
+        let angle = T::from_f64(2.) * q.w.clamp(-T::ONE, T::ONE).acos();
+        let s = (T::ONE - q.w * q.w).max(T::ZERO).sqrt();
+
+        if s < T::EPSILON {
+            (vector(T::ONE, T::ZERO, T::ZERO), angle)
+        } else {
+            (vector(q.x / s, q.y / s, q.z / s), angle)
+        }
+    }
+
+    /// Rotations by `angles` (around x, y and z) applied in `order`
+    pub fn from_euler(angles: [T; 3], order: EulerOrder) -> Self {
+        let unit = [
+            vector(T::ONE, T::ZERO, T::ZERO),
+            vector(T::ZERO, T::ONE, T::ZERO),
+            vector(T::ZERO, T::ZERO, T::ONE),
+        ];
+
+        order.axes().iter().fold(Self::identity(), |q, &axis| {
+            Self::from_axis_angle(unit[axis], angles[axis]) * q
+        })
+    }
+
+    /// Angles around x, y and z that `from_euler` turns back into this
+    /// rotation. The middle axis is kept within `[-pi/2, pi/2]`; in gimbal
+    /// lock the last axis' angle is folded into the first one.
+    pub fn to_euler(&self, order: EulerOrder) -> [T; 3] {
+        let m = self.to_matrix();
+        let [i, j, k] = order.axes();
+        // the formulas below are for cyclic orders, mirrored for the others
+        let s = if (j + 3 - i) % 3 == 1 {
+            T::ONE
+        } else {
+            -T::ONE
+        };
+
+        let mut angles = [T::ZERO; 3];
+        let sin_j = (-s * m[k][i]).clamp(-T::ONE, T::ONE);
+        angles[j] = sin_j.asin();
+
+        if sin_j.abs() < T::ONE - T::EPSILON {
+            angles[i] = (s * m[k][j]).atan2(m[k][k]);
+            angles[k] = (s * m[j][i]).atan2(m[i][i]);
+        } else {
+            angles[i] = (-s * m[j][k]).atan2(m[j][j]);
+        }
+
+        angles
+    }
+
+    /// The rotation part of `m`, which must not contain scaling or shearing
+    pub fn from_matrix(m: &Matrix4x4<T>) -> Self {
+        let (one, two, quarter) = (T::ONE, T::from_f64(2.), T::from_f64(0.25));
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // divide by the largest of the four candidate components
+        let q = if trace > T::ZERO {
+            let s = (trace + one).sqrt() * two;
+            Self::new(
+                quarter * s,
+                (m[2][1] - m[1][2]) / s,
+                (m[0][2] - m[2][0]) / s,
+                (m[1][0] - m[0][1]) / s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (one + m[0][0] - m[1][1] - m[2][2]).sqrt() * two;
+            Self::new(
+                (m[2][1] - m[1][2]) / s,
+                quarter * s,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = (one + m[1][1] - m[0][0] - m[2][2]).sqrt() * two;
+            Self::new(
+                (m[0][2] - m[2][0]) / s,
+                (m[0][1] + m[1][0]) / s,
+                quarter * s,
+                (m[1][2] + m[2][1]) / s,
+            )
+        } else {
+            let s = (one + m[2][2] - m[0][0] - m[1][1]).sqrt() * two;
+            Self::new(
+                (m[1][0] - m[0][1]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                quarter * s,
+            )
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_matrix(&self) -> Matrix4x4<T> {
+        let Quaternion { w, x, y, z } = self.normalize();
+        let (o, l, two) = (T::ZERO, T::ONE, T::from_f64(2.));
+
+        Matrix4x4::new([
+            [
+                l - two * (y * y + z * z),
+                two * (x * y - w * z),
+                two * (x * z + w * y),
+                o,
+            ],
+            [
+                two * (x * y + w * z),
+                l - two * (x * x + z * z),
+                two * (y * z - w * x),
+                o,
+            ],
+            [
+                two * (x * z - w * y),
+                two * (y * z + w * x),
+                l - two * (x * x + y * y),
+                o,
+            ],
+            [o, o, o, l],
+        ])
+    }
+
+    pub fn dot(&self, rhs: Self) -> T {
+        self.w * rhs.w + self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn magnitude(&self) -> T {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let mag = self.magnitude();
+        Self::new(self.w / mag, self.x / mag, self.y / mag, self.z / mag)
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let sq = self.dot(*self);
+        let c = self.conjugate();
+        Self::new(c.w / sq, c.x / sq, c.y / sq, c.z / sq)
+    }
+
+    /// Spherical linear interpolation at constant angular speed along the
+    /// shorter arc, `t` = 0 gives `self` and 1 gives `to`
+    pub fn slerp(&self, to: Self, t: T) -> Self {
+        let from = self.normalize();
+        let mut to = to.normalize();
+
+        // q and -q are the same rotation, take the one closer to `from`
+        let mut cos = from.dot(to);
+        if cos < T::ZERO {
+            to = -to;
+            cos = -cos;
+        }
+
+        let (a, b) = if cos > T::ONE - T::EPSILON {
+            // nearly parallel: the sine below vanishes, lerp is accurate
+            (T::ONE - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((T::ONE - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Self::new(
+            a * from.w + b * to.w,
+            a * from.x + b * to.x,
+            a * from.y + b * to.y,
+            a * from.z + b * to.z,
+        )
+        .normalize()
+    }
+
+    /// Converts to another precision
+    pub fn cast<U: Float>(&self) -> Quaternion<U> {
+        Quaternion::new(
+            U::from_f64(self.w.to_f64()),
+            U::from_f64(self.x.to_f64()),
+            U::from_f64(self.y.to_f64()),
+            U::from_f64(self.z.to_f64()),
+        )
+    }
+}
+
+impl<T: Float> From<Quaternion<T>> for Matrix4x4<T> {
+    fn from(q: Quaternion<T>) -> Self {
+        q.to_matrix()
+    }
+}
+
+/// Hamilton product; `a * b` rotates by `b` first, then by `a`, like
+/// matrices
+impl<T: Float> Mul for Quaternion<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (self, rhs);
+
+        Quaternion::new(
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        )
+    }
+}
+
+impl<T: Float> Neg for Quaternion<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+/// Rotates a unit quaternion's vector
+impl<T: Float> Mul<Vector3<T>> for Quaternion<T> {
+    type Output = Vector3<T>;
+
+    fn mul(self, v: Vector3<T>) -> Self::Output {
+        let u = vector(self.x, self.y, self.z);
+        let t = u.cross(v) * T::from_f64(2.);
+
+        v + t * self.w + u.cross(t)
+    }
+}
+
+/// Rotates a point around the origin
+impl<T: Float> Mul<Point3<T>> for Quaternion<T> {
+    type Output = Point3<T>;
+
+    fn mul(self, p: Point3<T>) -> Self::Output {
+        let v = self * vector(p.x, p.y, p.z);
+        point(v.x, v.y, v.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::*;
+    use crate::util::array_approx_equal;
+    use std::f64::consts::PI;
+
+    const ORDERS: [EulerOrder; 6] = [
+        EulerOrder::XYZ,
+        EulerOrder::XZY,
+        EulerOrder::YXZ,
+        EulerOrder::YZX,
+        EulerOrder::ZXY,
+        EulerOrder::ZYX,
+    ];
+
+    #[test]
+    fn axis_angle_matches_rotation_matrices() {
+        let q = Quaternion::from_axis_angle(vector(1., 0., 0.), PI / 4.);
+        assert_eq!(q.to_matrix(), rotation_x(PI / 4.));
+
+        let axis = vector(1., -2., 0.5);
+        let q = Quaternion::from_axis_angle(axis, 1.2);
+        assert_eq!(Matrix4x4::from(q), rotation_axis_angle(axis, 1.2));
+        assert_eq!(Transform::identity().rotate(q).matrix(), Matrix4x4::from(q));
+
+        let (a, angle) = q.to_axis_angle();
+        assert_eq!(a, axis.normalize());
+        assert!(approx_equal(angle, 1.2));
+        assert!(approx_equal(
+            Quaternion::<f64>::identity().to_axis_angle().1,
+            0.
+        ));
+    }
+
+    #[test]
+    fn rotating_points_and_vectors() {
+        let q = Quaternion::from_axis_angle(vector(0., 0., 1.), PI / 2.);
+
+        assert_eq!(q * point(0., 1., 0.), point(-1., 0., 0.));
+        assert_eq!(q * vector(1., 0., 0.), vector(0., 1., 0.));
+
+        let q = Quaternion::from_axis_angle(vector(1., 1., 0.), 0.7);
+        let p = point(2., -3., 4.);
+        assert_eq!(q * p, q.to_matrix() * p);
+    }
+
+    #[test]
+    fn multiplication_composes_like_matrices() {
+        let a = Quaternion::from_axis_angle(vector(1., 0., 0.), 0.4);
+        let b = Quaternion::from_axis_angle(vector(0., 1., 1.), -1.1);
+
+        assert_eq!((a * b).to_matrix(), a.to_matrix() * b.to_matrix());
+        assert_eq!(a * a.inverse(), Quaternion::identity());
+        assert_eq!(a * a.conjugate(), Quaternion::identity());
+        assert!(approx_equal(
+            Quaternion::new(1., 2., 3., 4.).normalize().magnitude(),
+            1.
+        ));
+    }
+
+    #[test]
+    fn converting_from_matrices() {
+        // exercises each branch of the conversion
+        let rotations = [
+            rotation_y(0.3),
+            rotation_x(PI - 0.1),
+            rotation_y(PI - 0.1),
+            rotation_z(PI - 0.1),
+            rotation_axis_angle(vector(-1., 2., 3.), 2.5),
+        ];
+
+        for m in rotations.iter() {
+            assert_eq!(Quaternion::from_matrix(m).to_matrix(), *m);
+        }
+    }
+
+    #[test]
+    fn euler_angles_round_trip_in_every_order() {
+        let angles = [0.3, -1.1, 1.4];
+        let matrices = [
+            rotation_x(angles[0]),
+            rotation_y(angles[1]),
+            rotation_z(angles[2]),
+        ];
+
+        for &order in ORDERS.iter() {
+            let q = Quaternion::from_euler(angles, order);
+
+            let expected = order
+                .axes()
+                .iter()
+                .fold(Matrix4x4::identity(), |m, &axis| matrices[axis] * m);
+            assert_eq!(q.to_matrix(), expected);
+
+            let back = q.to_euler(order);
+            assert!(
+                array_approx_equal(&back, &angles),
+                "{:?}: {:?}",
+                order,
+                back
+            );
+        }
+    }
+
+    #[test]
+    fn euler_angles_in_gimbal_lock() {
+        for &order in ORDERS.iter() {
+            let mut angles = [0.5, 0.5, 0.5];
+            angles[order.axes()[1]] = PI / 2.;
+
+            let q = Quaternion::from_euler(angles, order);
+            let back = Quaternion::from_euler(q.to_euler(order), order);
+            assert_eq!(back.to_matrix(), q.to_matrix(), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn slerp_interpolates_at_constant_speed() {
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(vector(0., 1., 0.), PI / 2.);
+
+        assert_eq!(a.slerp(b, 0.), a);
+        assert_eq!(a.slerp(b, 1.), b);
+        assert_eq!(
+            a.slerp(b, 0.5),
+            Quaternion::from_axis_angle(vector(0., 1., 0.), PI / 4.)
+        );
+        assert_eq!(a.slerp(b, 0.25).to_matrix(), rotation_y(PI / 8.));
+
+        // takes the short way round even when given the far representation
+        assert_eq!(a.slerp(-b, 0.5).to_matrix(), rotation_y(PI / 4.));
+
+        // in-betweens stay rigid rotations
+        let c = Quaternion::from_axis_angle(vector(1., 1., 1.), 2.);
+        let m = a.slerp(c, 0.3).to_matrix();
+        assert_eq!(m * m.transpose(), Matrix4x4::identity());
+    }
+}
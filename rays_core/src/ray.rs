@@ -0,0 +1,70 @@
+use crate::matrices::Matrix4x4;
+use crate::tuples::{Point3, Vector3};
+use crate::util::Float;
+
+/// A half-line from `origin` along `direction`. The direction isn't
+/// normalized, so distances along the ray are in units of its length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray<T: Float = f64> {
+    pub origin: Point3<T>,
+    pub direction: Vector3<T>,
+}
+
+impl<T: Float> Ray<T> {
+    pub fn new(origin: Point3<T>, direction: Vector3<T>) -> Self {
+        Ray { origin, direction }
+    }
+
+    /// The point at distance `t` along the ray
+    pub fn position(&self, t: T) -> Point3<T> {
+        self.origin + self.direction * t
+    }
+
+    pub fn transform(&self, m: &Matrix4x4<T>) -> Self {
+        Ray {
+            origin: *m * self.origin,
+            direction: *m * self.direction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations::*;
+    use crate::tuples::{point, vector};
+
+    #[test]
+    fn creating_and_querying_a_ray() {
+        let origin = point(1., 2., 3.);
+        let direction = vector(4., 5., 6.);
+        let r = Ray::new(origin, direction);
+
+        assert_eq!(r.origin, origin);
+        assert_eq!(r.direction, direction);
+    }
+
+    #[test]
+    fn computing_a_point_from_a_distance() {
+        let r = Ray::new(point(2., 3., 4.), vector(1., 0., 0.));
+
+        assert_eq!(r.position(0.), point(2., 3., 4.));
+        assert_eq!(r.position(1.), point(3., 3., 4.));
+        assert_eq!(r.position(-1.), point(1., 3., 4.));
+        assert_eq!(r.position(2.5), point(4.5, 3., 4.));
+    }
+
+    #[test]
+    fn transforming_a_ray() {
+        /* Translating a ray */
+        let r = Ray::new(point(1., 2., 3.), vector(0., 1., 0.));
+        let r2 = r.transform(&translation(3., 4., 5.));
+        assert_eq!(r2.origin, point(4., 6., 8.));
+        assert_eq!(r2.direction, vector(0., 1., 0.));
+
+        /* Scaling a ray */
+        let r2 = r.transform(&scaling(2., 3., 4.));
+        assert_eq!(r2.origin, point(2., 6., 12.));
+        assert_eq!(r2.direction, vector(0., 3., 0.));
+    }
+}
@@ -0,0 +1,200 @@
+//! Serde support, behind the `serde` feature.
+//!
+//! The math types use compact forms that are easy to write by hand: points,
+//! vectors and normals are `[x, y, z]`, tuples `[x, y, z, w]`, quaternions
+//! `[w, x, y, z]` and matrices nested row arrays. Colors serialize as
+//! linear `[r, g, b]` and also deserialize from sRGB hex strings like
+//! `"#ff8000"`.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::color::Color;
+use crate::matrices::Matrix;
+use crate::quaternion::Quaternion;
+use crate::tuples::{Normal3, Point3, Tuple, Vector3};
+use crate::util::Float;
+
+macro_rules! array_serde {
+    ($name:ident, $len:expr, [$($field:ident),+]) => {
+        impl<T: Float + Serialize> Serialize for $name<T> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                [$(self.$field),+].serialize(serializer)
+            }
+        }
+
+        impl<'de, T: Float + Deserialize<'de>> Deserialize<'de> for $name<T> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let [$($field),+] = <[T; $len]>::deserialize(deserializer)?;
+                Ok($name { $($field),+ })
+            }
+        }
+    };
+}
+
+array_serde!(Point3, 3, [x, y, z]);
+array_serde!(Vector3, 3, [x, y, z]);
+array_serde!(Normal3, 3, [x, y, z]);
+array_serde!(Tuple, 4, [x, y, z, w]);
+array_serde!(Quaternion, 4, [w, x, y, z]);
+
+impl<T: Float + Serialize> Serialize for Color<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.r, self.g, self.b].serialize(serializer)
+    }
+}
+
+struct ColorVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Float + Deserialize<'de>> Visitor<'de> for ColorVisitor<T> {
+    type Value = Color<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an [r, g, b] array or a hex color string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut next = |i: usize| -> Result<T, A::Error> {
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))
+        };
+        let (r, g, b) = (next(0)?, next(1)?, next(2)?);
+
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(4, &self));
+        }
+
+        Ok(Color::new(r, g, b))
+    }
+}
+
+impl<'de, T: Float + Deserialize<'de>> Deserialize<'de> for Color<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ColorVisitor(PhantomData))
+    }
+}
+
+impl<T: Float + Serialize, const R: usize, const C: usize> Serialize for Matrix<R, C, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.rows().iter().map(|row| &row[..]))
+    }
+}
+
+impl<'de, T: Float + Deserialize<'de>, const R: usize, const C: usize> Deserialize<'de>
+    for Matrix<R, C, T>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rows = Vec::<Vec<T>>::deserialize(deserializer)?;
+
+        if rows.len() != R || rows.iter().any(|row| row.len() != C) {
+            return Err(de::Error::custom(format!(
+                "expected a {}x{} matrix given as {} rows of {} values",
+                R, C, R, C
+            )));
+        }
+
+        let mut m = Matrix::zeros();
+        for (r, row) in rows.iter().enumerate() {
+            m[r].copy_from_slice(row);
+        }
+
+        Ok(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::{Canvas, CanvasMetadata};
+    use crate::image::ExportOptions;
+    use crate::matrices::Matrix4x4;
+    use crate::tonemap::{ToneMap, ToneMapOperator};
+    use crate::transformations::*;
+    use crate::tuples::{normal, point, tuple, vector};
+    use crate::Dither;
+
+    fn round_trip<V>(value: &V, json: &str)
+    where
+        V: Serialize + for<'de> Deserialize<'de> + PartialEq + fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string(value).unwrap(), json);
+        assert_eq!(&serde_json::from_str::<V>(json).unwrap(), value);
+    }
+
+    #[test]
+    fn tuples_are_flat_arrays() {
+        round_trip(&point(1., 2.5, -3.), "[1.0,2.5,-3.0]");
+        round_trip(&vector(0., 1., 0.), "[0.0,1.0,0.0]");
+        round_trip(&normal(0_f32, 0., 1.), "[0.0,0.0,1.0]");
+        round_trip(&tuple(1., 2., 3., 1.), "[1.0,2.0,3.0,1.0]");
+        round_trip(&Quaternion::<f64>::identity(), "[1.0,0.0,0.0,0.0]");
+
+        assert!(serde_json::from_str::<Point3>("[1.0,2.0]").is_err());
+        assert!(serde_json::from_str::<Point3>("{\"x\":1.0}").is_err());
+    }
+
+    #[test]
+    fn colors_accept_arrays_and_hex_strings() {
+        round_trip(&Color::new(1., 0.5, 0.), "[1.0,0.5,0.0]");
+
+        let c: Color = serde_json::from_str("\"#ff8000\"").unwrap();
+        assert_eq!(c, Color::<f64>::from_hex("#ff8000").unwrap());
+
+        assert!(serde_json::from_str::<Color>("\"#ff80\"").is_err());
+        assert!(serde_json::from_str::<Color>("[1.0,0.5]").is_err());
+        assert!(serde_json::from_str::<Color>("[1.0,0.5,0.0,1.0]").is_err());
+    }
+
+    #[test]
+    fn matrices_are_nested_rows() {
+        let m = translation(1., 2., 3.);
+        let json = "[[1.0,0.0,0.0,1.0],[0.0,1.0,0.0,2.0],[0.0,0.0,1.0,3.0],[0.0,0.0,0.0,1.0]]";
+        round_trip(&m, json);
+
+        round_trip(
+            &Matrix::new([[1., 2., 3.], [4., 5., 6.]]),
+            "[[1.0,2.0,3.0],[4.0,5.0,6.0]]",
+        );
+
+        let err = serde_json::from_str::<Matrix4x4>("[[1.0,0.0],[0.0,1.0]]").unwrap_err();
+        assert!(err.to_string().contains("expected a 4x4 matrix"));
+    }
+
+    #[test]
+    fn canvas_metadata_and_export_settings() {
+        let canvas = Canvas::new_transparent(20, 10);
+        let json = serde_json::to_string(&canvas.metadata()).unwrap();
+        assert_eq!(json, "{\"width\":20,\"height\":10,\"alpha\":true}");
+        let metadata: CanvasMetadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(Canvas::from_metadata(metadata).metadata(), metadata);
+
+        let options = ExportOptions {
+            tone_map: ToneMap::new(ToneMapOperator::ReinhardExtended { white: 4. })
+                .with_exposure(1.),
+            dither: Dither::FloydSteinberg,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(
+            json,
+            "{\"tone_map\":{\"operator\":{\"reinhard_extended\":{\"white\":4.0}},\"exposure\":1.0},\
+             \"transfer\":\"srgb\",\"dither\":\"floyd_steinberg\"}"
+        );
+        assert_eq!(
+            serde_json::from_str::<ExportOptions>(&json).unwrap(),
+            options
+        );
+
+        // missing settings fall back to their defaults
+        let partial: ExportOptions = serde_json::from_str("{\"dither\":\"blue_noise\"}").unwrap();
+        assert_eq!(partial.dither, Dither::BlueNoise);
+        assert_eq!(partial.tone_map, ToneMap::default());
+    }
+}
@@ -0,0 +1,503 @@
+//! Small fixed-size kernels behind tuple and matrix arithmetic.
+//!
+//! With the `simd` feature enabled on x86_64 the f32 and f64 kernels use
+//! SSE2 (and AVX for f64 matrix products when the CPU supports it); other
+//! targets and builds use the scalar versions in `scalar`.
+
+pub type Mat4<T> = [[T; 4]; 4];
+
+/// Per-type dispatch of the kernels, implemented for f32 and f64
+pub trait Kernels: Sized {
+    fn add4(a: [Self; 4], b: [Self; 4]) -> [Self; 4];
+    fn sub4(a: [Self; 4], b: [Self; 4]) -> [Self; 4];
+    fn scale4(a: [Self; 4], s: Self) -> [Self; 4];
+    fn dot4(a: [Self; 4], b: [Self; 4]) -> Self;
+    fn cross3(a: [Self; 3], b: [Self; 3]) -> [Self; 3];
+    fn mat4_mul(a: &Mat4<Self>, b: &Mat4<Self>) -> Mat4<Self>;
+    fn mat4_mul_vec4(m: &Mat4<Self>, v: [Self; 4]) -> [Self; 4];
+    /// The first three rows of `m * (v, w)`
+    fn mat4_transform3(m: &Mat4<Self>, v: [Self; 3], w: Self) -> [Self; 3];
+}
+
+/// Plain loops, used without the `simd` feature and as the reference in
+/// tests and benchmarks
+pub mod scalar {
+    use super::Mat4;
+    use crate::util::Float;
+
+    #[inline]
+    pub fn add4<T: Float>(a: [T; 4], b: [T; 4]) -> [T; 4] {
+        [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]
+    }
+
+    #[inline]
+    pub fn sub4<T: Float>(a: [T; 4], b: [T; 4]) -> [T; 4] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]]
+    }
+
+    #[inline]
+    pub fn scale4<T: Float>(a: [T; 4], s: T) -> [T; 4] {
+        [a[0] * s, a[1] * s, a[2] * s, a[3] * s]
+    }
+
+    #[inline]
+    pub fn dot4<T: Float>(a: [T; 4], b: [T; 4]) -> T {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
+    }
+
+    #[inline]
+    pub fn cross3<T: Float>(a: [T; 3], b: [T; 3]) -> [T; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    #[inline]
+    pub fn mat4_mul<T: Float>(a: &Mat4<T>, b: &Mat4<T>) -> Mat4<T> {
+        let mut m = [[T::ZERO; 4]; 4];
+
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, val) in out.iter_mut().enumerate() {
+                *val = a[row][0] * b[0][col]
+                    + a[row][1] * b[1][col]
+                    + a[row][2] * b[2][col]
+                    + a[row][3] * b[3][col];
+            }
+        }
+
+        m
+    }
+
+    #[inline]
+    pub fn mat4_mul_vec4<T: Float>(m: &Mat4<T>, v: [T; 4]) -> [T; 4] {
+        [dot4(m[0], v), dot4(m[1], v), dot4(m[2], v), dot4(m[3], v)]
+    }
+
+    #[inline]
+    pub fn mat4_transform3<T: Float>(m: &Mat4<T>, v: [T; 3], w: T) -> [T; 3] {
+        let row = |r: [T; 4]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2] + r[3] * w;
+        [row(m[0]), row(m[1]), row(m[2])]
+    }
+}
+
+macro_rules! kernels_impl {
+    ($type:ident, $($backend:ident)::+) => {
+        impl Kernels for $type {
+            #[inline]
+            fn add4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+                $($backend)::+::add4(a, b)
+            }
+
+            #[inline]
+            fn sub4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+                $($backend)::+::sub4(a, b)
+            }
+
+            #[inline]
+            fn scale4(a: [Self; 4], s: Self) -> [Self; 4] {
+                $($backend)::+::scale4(a, s)
+            }
+
+            #[inline]
+            fn dot4(a: [Self; 4], b: [Self; 4]) -> Self {
+                $($backend)::+::dot4(a, b)
+            }
+
+            #[inline]
+            fn cross3(a: [Self; 3], b: [Self; 3]) -> [Self; 3] {
+                $($backend)::+::cross3(a, b)
+            }
+
+            #[inline]
+            fn mat4_mul(a: &Mat4<Self>, b: &Mat4<Self>) -> Mat4<Self> {
+                $($backend)::+::mat4_mul(a, b)
+            }
+
+            #[inline]
+            fn mat4_mul_vec4(m: &Mat4<Self>, v: [Self; 4]) -> [Self; 4] {
+                $($backend)::+::mat4_mul_vec4(m, v)
+            }
+
+            #[inline]
+            fn mat4_transform3(m: &Mat4<Self>, v: [Self; 3], w: Self) -> [Self; 3] {
+                $($backend)::+::mat4_transform3(m, v, w)
+            }
+        }
+    };
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+kernels_impl!(f32, x86::f32);
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+kernels_impl!(f64, x86::f64);
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+kernels_impl!(f32, scalar);
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+kernels_impl!(f64, scalar);
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    // SSE2 is part of the x86_64 baseline so its intrinsics are always safe
+    // to call; only AVX needs detecting at runtime
+
+    pub mod f64 {
+        use super::super::Mat4;
+        use std::arch::x86_64::*;
+        use std::sync::OnceLock;
+
+        #[inline(always)]
+        fn load2(a: &[f64]) -> __m128d {
+            debug_assert!(a.len() >= 2);
+            unsafe { _mm_loadu_pd(a.as_ptr()) }
+        }
+
+        #[inline(always)]
+        fn store4(lo: __m128d, hi: __m128d) -> [f64; 4] {
+            let mut out = [0.; 4];
+            unsafe {
+                _mm_storeu_pd(out.as_mut_ptr(), lo);
+                _mm_storeu_pd(out.as_mut_ptr().add(2), hi);
+            }
+            out
+        }
+
+        #[inline(always)]
+        fn hsum(v: __m128d) -> f64 {
+            unsafe { _mm_cvtsd_f64(_mm_add_sd(v, _mm_unpackhi_pd(v, v))) }
+        }
+
+        #[inline]
+        pub fn add4(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+            unsafe {
+                store4(
+                    _mm_add_pd(load2(&a[..2]), load2(&b[..2])),
+                    _mm_add_pd(load2(&a[2..]), load2(&b[2..])),
+                )
+            }
+        }
+
+        #[inline]
+        pub fn sub4(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+            unsafe {
+                store4(
+                    _mm_sub_pd(load2(&a[..2]), load2(&b[..2])),
+                    _mm_sub_pd(load2(&a[2..]), load2(&b[2..])),
+                )
+            }
+        }
+
+        #[inline]
+        pub fn scale4(a: [f64; 4], s: f64) -> [f64; 4] {
+            unsafe {
+                let s = _mm_set1_pd(s);
+                store4(_mm_mul_pd(load2(&a[..2]), s), _mm_mul_pd(load2(&a[2..]), s))
+            }
+        }
+
+        #[inline]
+        pub fn dot4(a: [f64; 4], b: [f64; 4]) -> f64 {
+            unsafe {
+                let lo = _mm_mul_pd(load2(&a[..2]), load2(&b[..2]));
+                let hi = _mm_mul_pd(load2(&a[2..]), load2(&b[2..]));
+                hsum(_mm_add_pd(lo, hi))
+            }
+        }
+
+        #[inline]
+        pub fn cross3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+            // x and y together, z on its own
+            let xy = unsafe {
+                let m1 = _mm_mul_pd(_mm_set_pd(a[2], a[1]), _mm_set_pd(b[0], b[2]));
+                let m2 = _mm_mul_pd(_mm_set_pd(a[0], a[2]), _mm_set_pd(b[2], b[1]));
+                store4(_mm_sub_pd(m1, m2), _mm_setzero_pd())
+            };
+
+            [xy[0], xy[1], a[0] * b[1] - a[1] * b[0]]
+        }
+
+        type Mat4Mul = fn(&Mat4<f64>, &Mat4<f64>) -> Mat4<f64>;
+
+        // the CPU is checked once, on the first product
+        static MAT4_MUL: OnceLock<Mat4Mul> = OnceLock::new();
+
+        #[inline]
+        pub fn mat4_mul(a: &Mat4<f64>, b: &Mat4<f64>) -> Mat4<f64> {
+            let mul = MAT4_MUL.get_or_init(|| {
+                if is_x86_feature_detected!("avx") {
+                    |a, b| unsafe { mat4_mul_avx(a, b) }
+                } else {
+                    mat4_mul_sse2
+                }
+            });
+
+            mul(a, b)
+        }
+
+        // each output row is a combination of the rows of b, accumulated in
+        // the same order as the scalar loop
+        fn mat4_mul_sse2(a: &Mat4<f64>, b: &Mat4<f64>) -> Mat4<f64> {
+            let mut m = [[0.; 4]; 4];
+
+            for (out, row) in m.iter_mut().zip(a.iter()) {
+                *out = unsafe {
+                    let mut lo = _mm_mul_pd(_mm_set1_pd(row[0]), load2(&b[0][..2]));
+                    let mut hi = _mm_mul_pd(_mm_set1_pd(row[0]), load2(&b[0][2..]));
+
+                    for k in 1..4 {
+                        let s = _mm_set1_pd(row[k]);
+                        lo = _mm_add_pd(lo, _mm_mul_pd(s, load2(&b[k][..2])));
+                        hi = _mm_add_pd(hi, _mm_mul_pd(s, load2(&b[k][2..])));
+                    }
+
+                    store4(lo, hi)
+                };
+            }
+
+            m
+        }
+
+        #[target_feature(enable = "avx")]
+        unsafe fn mat4_mul_avx(a: &Mat4<f64>, b: &Mat4<f64>) -> Mat4<f64> {
+            let mut m = [[0.; 4]; 4];
+            let rows = [
+                _mm256_loadu_pd(b[0].as_ptr()),
+                _mm256_loadu_pd(b[1].as_ptr()),
+                _mm256_loadu_pd(b[2].as_ptr()),
+                _mm256_loadu_pd(b[3].as_ptr()),
+            ];
+
+            for (out, row) in m.iter_mut().zip(a.iter()) {
+                let mut acc = _mm256_mul_pd(_mm256_set1_pd(row[0]), rows[0]);
+
+                for k in 1..4 {
+                    acc = _mm256_add_pd(acc, _mm256_mul_pd(_mm256_set1_pd(row[k]), rows[k]));
+                }
+
+                _mm256_storeu_pd(out.as_mut_ptr(), acc);
+            }
+
+            m
+        }
+
+        #[inline]
+        pub fn mat4_mul_vec4(m: &Mat4<f64>, v: [f64; 4]) -> [f64; 4] {
+            [dot4(m[0], v), dot4(m[1], v), dot4(m[2], v), dot4(m[3], v)]
+        }
+
+        #[inline]
+        pub fn mat4_transform3(m: &Mat4<f64>, v: [f64; 3], w: f64) -> [f64; 3] {
+            let v = [v[0], v[1], v[2], w];
+            [dot4(m[0], v), dot4(m[1], v), dot4(m[2], v)]
+        }
+    }
+
+    pub mod f32 {
+        use super::super::Mat4;
+        use std::arch::x86_64::*;
+
+        // _MM_SHUFFLE(3, 0, 2, 1) and _MM_SHUFFLE(3, 1, 0, 2)
+        const YZX: i32 = 0b11_00_10_01;
+        const ZXY: i32 = 0b11_01_00_10;
+
+        #[inline(always)]
+        fn load(a: &[f32; 4]) -> __m128 {
+            unsafe { _mm_loadu_ps(a.as_ptr()) }
+        }
+
+        #[inline(always)]
+        fn store(v: __m128) -> [f32; 4] {
+            let mut out = [0.; 4];
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), v) };
+            out
+        }
+
+        #[inline(always)]
+        fn hsum(v: __m128) -> f32 {
+            unsafe {
+                let pairs = _mm_add_ps(v, _mm_movehl_ps(v, v));
+                _mm_cvtss_f32(_mm_add_ss(pairs, _mm_shuffle_ps::<1>(pairs, pairs)))
+            }
+        }
+
+        #[inline]
+        pub fn add4(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+            store(unsafe { _mm_add_ps(load(&a), load(&b)) })
+        }
+
+        #[inline]
+        pub fn sub4(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+            store(unsafe { _mm_sub_ps(load(&a), load(&b)) })
+        }
+
+        #[inline]
+        pub fn scale4(a: [f32; 4], s: f32) -> [f32; 4] {
+            store(unsafe { _mm_mul_ps(load(&a), _mm_set1_ps(s)) })
+        }
+
+        #[inline]
+        pub fn dot4(a: [f32; 4], b: [f32; 4]) -> f32 {
+            hsum(unsafe { _mm_mul_ps(load(&a), load(&b)) })
+        }
+
+        #[inline]
+        pub fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+            let a = load(&[a[0], a[1], a[2], 0.]);
+            let b = load(&[b[0], b[1], b[2], 0.]);
+
+            // a.yzx * b.zxy - a.zxy * b.yzx
+            let c = store(unsafe {
+                _mm_sub_ps(
+                    _mm_mul_ps(_mm_shuffle_ps::<YZX>(a, a), _mm_shuffle_ps::<ZXY>(b, b)),
+                    _mm_mul_ps(_mm_shuffle_ps::<ZXY>(a, a), _mm_shuffle_ps::<YZX>(b, b)),
+                )
+            });
+
+            [c[0], c[1], c[2]]
+        }
+
+        #[inline]
+        pub fn mat4_mul(a: &Mat4<f32>, b: &Mat4<f32>) -> Mat4<f32> {
+            let rows = [load(&b[0]), load(&b[1]), load(&b[2]), load(&b[3])];
+            let mut m = [[0.; 4]; 4];
+
+            for (out, row) in m.iter_mut().zip(a.iter()) {
+                *out = store(unsafe {
+                    let mut acc = _mm_mul_ps(_mm_set1_ps(row[0]), rows[0]);
+
+                    for k in 1..4 {
+                        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(row[k]), rows[k]));
+                    }
+
+                    acc
+                });
+            }
+
+            m
+        }
+
+        #[inline]
+        pub fn mat4_mul_vec4(m: &Mat4<f32>, v: [f32; 4]) -> [f32; 4] {
+            [dot4(m[0], v), dot4(m[1], v), dot4(m[2], v), dot4(m[3], v)]
+        }
+
+        #[inline]
+        pub fn mat4_transform3(m: &Mat4<f32>, v: [f32; 3], w: f32) -> [f32; 3] {
+            let v = [v[0], v[1], v[2], w];
+            [dot4(m[0], v), dot4(m[1], v), dot4(m[2], v)]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{array_approx_equal, Float};
+
+    // deterministic values in [-4, 4)
+    fn values<T: Float>(seed: u32, n: usize) -> Vec<T> {
+        let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                T::from_f64(state as f64 / u32::MAX as f64 * 8. - 4.)
+            })
+            .collect()
+    }
+
+    fn vec4<T: Float>(seed: u32) -> [T; 4] {
+        let v = values(seed, 4);
+        [v[0], v[1], v[2], v[3]]
+    }
+
+    fn mat4<T: Float>(seed: u32) -> Mat4<T> {
+        [vec4(seed), vec4(seed + 1), vec4(seed + 2), vec4(seed + 3)]
+    }
+
+    fn kernels_match_scalar<T: Float>() {
+        for seed in 1..50 {
+            let (a, b, s) = (
+                vec4::<T>(seed),
+                vec4::<T>(seed + 100),
+                values::<T>(seed, 1)[0],
+            );
+            let (m, n) = (mat4::<T>(seed), mat4::<T>(seed + 200));
+            let tol = T::from_f64(1e-3);
+
+            assert!(array_approx_equal(&T::add4(a, b), &scalar::add4(a, b)));
+            assert!(array_approx_equal(&T::sub4(a, b), &scalar::sub4(a, b)));
+            assert!(array_approx_equal(&T::scale4(a, s), &scalar::scale4(a, s)));
+            assert!((T::dot4(a, b) - scalar::dot4(a, b)).abs() < tol);
+
+            let (a3, b3) = ([a[0], a[1], a[2]], [b[0], b[1], b[2]]);
+            assert!(array_approx_equal(
+                &T::cross3(a3, b3),
+                &scalar::cross3(a3, b3)
+            ));
+
+            let mm = T::mat4_mul(&m, &n);
+            let expected = scalar::mat4_mul(&m, &n);
+            for (row, exp) in mm.iter().zip(expected.iter()) {
+                assert!(row
+                    .iter()
+                    .zip(exp.iter())
+                    .all(|(x, y)| (*x - *y).abs() < tol));
+            }
+
+            let mv = T::mat4_mul_vec4(&m, a);
+            let expected = scalar::mat4_mul_vec4(&m, a);
+            assert!(mv
+                .iter()
+                .zip(expected.iter())
+                .all(|(x, y)| (*x - *y).abs() < tol));
+
+            let t3 = T::mat4_transform3(&m, a3, s);
+            let expected = scalar::mat4_transform3(&m, a3, s);
+            assert!(t3
+                .iter()
+                .zip(expected.iter())
+                .all(|(x, y)| (*x - *y).abs() < tol));
+        }
+    }
+
+    #[test]
+    fn f64_kernels_match_scalar() {
+        kernels_match_scalar::<f64>();
+    }
+
+    #[test]
+    fn f32_kernels_match_scalar() {
+        kernels_match_scalar::<f32>();
+    }
+
+    #[test]
+    fn matrix_product_matches_book_example() {
+        let a = [
+            [1., 2., 3., 4.],
+            [5., 6., 7., 8.],
+            [9., 8., 7., 6.],
+            [5., 4., 3., 2.],
+        ];
+        let b = [
+            [-2., 1., 2., 3.],
+            [3., 2., 1., -1.],
+            [4., 3., 6., 5.],
+            [1., 2., 7., 8.],
+        ];
+
+        assert_eq!(
+            f64::mat4_mul(&a, &b),
+            [
+                [20., 22., 50., 48.],
+                [44., 54., 114., 108.],
+                [40., 58., 110., 102.],
+                [16., 26., 46., 42.],
+            ]
+        );
+    }
+}
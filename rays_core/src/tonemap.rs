@@ -0,0 +1,171 @@
+use crate::color::Color;
+
+// Hable's filmic curve constants (Uncharted 2)
+const HABLE_A: f64 = 0.15;
+const HABLE_B: f64 = 0.50;
+const HABLE_C: f64 = 0.10;
+const HABLE_D: f64 = 0.20;
+const HABLE_E: f64 = 0.02;
+const HABLE_F: f64 = 0.30;
+const HABLE_EXPOSURE_BIAS: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ToneMapOperator {
+    /// Clamp each channel to [0, 1] (no tone mapping)
+    Clamp,
+    /// c / (1 + c)
+    Reinhard,
+    /// Reinhard with a white point; channels >= `white` map to 1.0
+    ReinhardExtended { white: f64 },
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Hable's filmic curve, normalized by the linear `white` point
+    Uncharted2 { white: f64 },
+}
+
+/// Maps scene-referred (unbounded) colors into the displayable [0, 1] range.
+///
+/// Exposure is given in EV stops and applied before the operator, so every
+/// stop doubles (or halves) the incoming radiance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ToneMap {
+    pub operator: ToneMapOperator,
+    pub exposure: f64,
+}
+
+impl ToneMap {
+    pub fn new(operator: ToneMapOperator) -> Self {
+        ToneMap {
+            operator,
+            exposure: 0.0,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let c = color * 2_f64.powf(self.exposure);
+
+        Color::new(
+            self.map_channel(c.r),
+            self.map_channel(c.g),
+            self.map_channel(c.b),
+        )
+    }
+
+    fn map_channel(&self, v: f64) -> f64 {
+        let v = v.max(0.0);
+
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => v,
+            ToneMapOperator::Reinhard => v / (1.0 + v),
+            ToneMapOperator::ReinhardExtended { white } => {
+                v * (1.0 + v / (white * white)) / (1.0 + v)
+            }
+            ToneMapOperator::Aces => (v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14),
+            ToneMapOperator::Uncharted2 { white } => hable(v * HABLE_EXPOSURE_BIAS) / hable(white),
+        };
+
+        mapped.min(1.0)
+    }
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap::new(ToneMapOperator::Clamp)
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    ((x * (HABLE_A * x + HABLE_C * HABLE_B) + HABLE_D * HABLE_E)
+        / (x * (HABLE_A * x + HABLE_B) + HABLE_D * HABLE_F))
+        - HABLE_E / HABLE_F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::approx_equal;
+
+    #[test]
+    fn clamp_leaves_displayable_colors_untouched() {
+        let tm = ToneMap::default();
+        let c = Color::new(0.2, 0.5, 1.0);
+
+        assert_eq!(tm.apply(c), c);
+        assert_eq!(
+            tm.apply(Color::new(1.5, -0.5, 0.3)),
+            Color::new(1.0, 0.0, 0.3)
+        );
+    }
+
+    #[test]
+    fn exposure_is_applied_in_stops() {
+        let tm = ToneMap::default().with_exposure(1.0);
+        assert_eq!(
+            tm.apply(Color::new(0.25, 0.1, 0.0)),
+            Color::new(0.5, 0.2, 0.0)
+        );
+
+        let tm = ToneMap::default().with_exposure(-2.0);
+        assert_eq!(
+            tm.apply(Color::new(2.0, 1.0, 0.0)),
+            Color::new(0.5, 0.25, 0.0)
+        );
+    }
+
+    #[test]
+    fn reinhard_compresses_highlights() {
+        let tm = ToneMap::new(ToneMapOperator::Reinhard);
+
+        assert_eq!(
+            tm.apply(Color::new(1.0, 3.0, 0.0)),
+            Color::new(0.5, 0.75, 0.0)
+        );
+    }
+
+    #[test]
+    fn extended_reinhard_maps_white_point_to_one() {
+        let tm = ToneMap::new(ToneMapOperator::ReinhardExtended { white: 4.0 });
+        let c = tm.apply(Color::new(4.0, 8.0, 1.0));
+
+        assert!(approx_equal(c.r, 1.0));
+        assert!(approx_equal(c.g, 1.0));
+        assert!(c.b > 0.5 && c.b < 1.0);
+    }
+
+    #[test]
+    fn filmic_operators_are_monotonic_and_bounded() {
+        let operators = [
+            ToneMapOperator::Aces,
+            ToneMapOperator::Uncharted2 { white: 11.2 },
+        ];
+
+        for op in operators.iter() {
+            let tm = ToneMap::new(*op);
+            let mut previous = tm.apply(Color::new_black()).r;
+
+            assert!(previous.abs() < 0.01);
+
+            for i in 1..100 {
+                let v = tm.apply(Color::new(i as f64 * 0.25, 0.0, 0.0)).r;
+                assert!(v >= previous && v <= 1.0);
+                previous = v;
+            }
+        }
+    }
+
+    #[test]
+    fn uncharted2_maps_white_point_to_one() {
+        let tm = ToneMap::new(ToneMapOperator::Uncharted2 { white: 11.2 });
+
+        assert!(approx_equal(tm.apply(Color::new(5.6, 0.0, 0.0)).r, 1.0));
+    }
+}
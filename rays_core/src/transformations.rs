@@ -1,6 +1,13 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Mul;
+
 use crate::matrices::*;
+use crate::quaternion::Quaternion;
+use crate::tuples::{vector, Point3, Vector3};
+use crate::util::Float;
 
-pub fn translation(x: f64, y: f64, z: f64) -> Matrix4x4 {
+pub fn translation<T: Float>(x: T, y: T, z: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
     id[0][3] = x;
@@ -10,7 +17,7 @@ pub fn translation(x: f64, y: f64, z: f64) -> Matrix4x4 {
     id
 }
 
-pub fn scaling(x: f64, y: f64, z: f64) -> Matrix4x4 {
+pub fn scaling<T: Float>(x: T, y: T, z: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
     id[0][0] = x;
@@ -20,40 +27,40 @@ pub fn scaling(x: f64, y: f64, z: f64) -> Matrix4x4 {
     id
 }
 
-pub fn rotation_x(r: f64) -> Matrix4x4 {
+pub fn rotation_x<T: Float>(r: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
-    id[1][1] = f64::cos(r);
-    id[1][2] = -f64::sin(r);
-    id[2][1] = f64::sin(r);
-    id[2][2] = f64::cos(r);
+    id[1][1] = r.cos();
+    id[1][2] = -r.sin();
+    id[2][1] = r.sin();
+    id[2][2] = r.cos();
 
     id
 }
 
-pub fn rotation_y(r: f64) -> Matrix4x4 {
+pub fn rotation_y<T: Float>(r: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
-    id[0][0] = f64::cos(r);
-    id[0][2] = f64::sin(r);
-    id[2][0] = -f64::sin(r);
-    id[2][2] = f64::cos(r);
+    id[0][0] = r.cos();
+    id[0][2] = r.sin();
+    id[2][0] = -r.sin();
+    id[2][2] = r.cos();
 
     id
 }
 
-pub fn rotation_z(r: f64) -> Matrix4x4 {
+pub fn rotation_z<T: Float>(r: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
-    id[0][0] = f64::cos(r);
-    id[0][1] = -f64::sin(r);
-    id[1][0] = f64::sin(r);
-    id[1][1] = f64::cos(r);
+    id[0][0] = r.cos();
+    id[0][1] = -r.sin();
+    id[1][0] = r.sin();
+    id[1][1] = r.cos();
 
     id
 }
 
-pub fn shearing(x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64) -> Matrix4x4 {
+pub fn shearing<T: Float>(x_y: T, x_z: T, y_x: T, y_z: T, z_x: T, z_y: T) -> Matrix4x4<T> {
     let mut id = Matrix4x4::identity();
 
     id[0][1] = x_y;
@@ -66,11 +73,300 @@ pub fn shearing(x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64) -> M
     id
 }
 
+pub fn uniform_scaling<T: Float>(s: T) -> Matrix4x4<T> {
+    scaling(s, s, s)
+}
+
+/// Rotation by `r` radians around `axis`, counterclockwise when looking
+/// down the axis towards the origin like `rotation_x` and friends
+pub fn rotation_axis_angle<T: Float>(axis: Vector3<T>, r: T) -> Matrix4x4<T> {
+    let k = axis.normalize();
+    let (sin, cos) = (r.sin(), r.cos());
+    let t = T::ONE - cos;
+
+    Matrix4x4::new([
+        [
+            cos + k.x * k.x * t,
+            k.x * k.y * t - k.z * sin,
+            k.x * k.z * t + k.y * sin,
+            T::ZERO,
+        ],
+        [
+            k.y * k.x * t + k.z * sin,
+            cos + k.y * k.y * t,
+            k.y * k.z * t - k.x * sin,
+            T::ZERO,
+        ],
+        [
+            k.z * k.x * t - k.y * sin,
+            k.z * k.y * t + k.x * sin,
+            cos + k.z * k.z * t,
+            T::ZERO,
+        ],
+        [T::ZERO, T::ZERO, T::ZERO, T::ONE],
+    ])
+}
+
+/// Mirror image across the plane through `origin` with the given normal
+pub fn reflection_across_plane<T: Float>(origin: Point3<T>, normal: Vector3<T>) -> Matrix4x4<T> {
+    let n = normal.normalize();
+    let n = [n.x, n.y, n.z];
+    let two = T::from_f64(2.);
+    let d = two * (origin.x * n[0] + origin.y * n[1] + origin.z * n[2]);
+
+    let mut m = Matrix4x4::identity();
+    for r in 0..3 {
+        for c in 0..3 {
+            m[r][c] -= two * n[r] * n[c];
+        }
+        m[r][3] = d * n[r];
+    }
+
+    m
+}
+
+/// View transform for an eye at `from` looking at `to`, moving the world so
+/// the eye sits at the origin looking down -z with `up` roughly along +y
+pub fn look_at<T: Float>(from: Point3<T>, to: Point3<T>, up: Vector3<T>) -> Matrix4x4<T> {
+    let forward = (to - from).normalize();
+    let left = forward.cross(up.normalize());
+    let true_up = left.cross(forward);
+
+    let (o, l) = (T::ZERO, T::ONE);
+    let orientation = Matrix4x4::new([
+        [left.x, left.y, left.z, o],
+        [true_up.x, true_up.y, true_up.z, o],
+        [-forward.x, -forward.y, -forward.z, o],
+        [o, o, o, l],
+    ]);
+
+    orientation * translation(-from.x, -from.y, -from.z)
+}
+
+/// Builds a transformation from steps written in the order they apply, so
+/// `Transform::identity().rotate_x(a).scale(2., 2., 2.).translate(1., 0., 0.)`
+/// is `translation(1., 0., 0.) * scaling(2., 2., 2.) * rotation_x(a)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform<T: Float = f64> {
+    matrix: Matrix4x4<T>,
+}
+
+impl<T: Float> Default for Transform<T> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T: Float> Transform<T> {
+    pub fn identity() -> Self {
+        Self {
+            matrix: Matrix4x4::identity(),
+        }
+    }
+
+    /// Applies `m` after the steps so far
+    pub fn then(self, m: Matrix4x4<T>) -> Self {
+        Self {
+            matrix: m * self.matrix,
+        }
+    }
+
+    pub fn translate(self, x: T, y: T, z: T) -> Self {
+        self.then(translation(x, y, z))
+    }
+
+    pub fn scale(self, x: T, y: T, z: T) -> Self {
+        self.then(scaling(x, y, z))
+    }
+
+    pub fn scale_uniform(self, s: T) -> Self {
+        self.then(uniform_scaling(s))
+    }
+
+    pub fn rotate_x(self, r: T) -> Self {
+        self.then(rotation_x(r))
+    }
+
+    pub fn rotate_y(self, r: T) -> Self {
+        self.then(rotation_y(r))
+    }
+
+    pub fn rotate_z(self, r: T) -> Self {
+        self.then(rotation_z(r))
+    }
+
+    pub fn rotate_axis_angle(self, axis: Vector3<T>, r: T) -> Self {
+        self.then(rotation_axis_angle(axis, r))
+    }
+
+    pub fn rotate(self, q: Quaternion<T>) -> Self {
+        self.then(q.to_matrix())
+    }
+
+    pub fn shear(self, x_y: T, x_z: T, y_x: T, y_z: T, z_x: T, z_y: T) -> Self {
+        self.then(shearing(x_y, x_z, y_x, y_z, z_x, z_y))
+    }
+
+    pub fn reflect_across_plane(self, origin: Point3<T>, normal: Vector3<T>) -> Self {
+        self.then(reflection_across_plane(origin, normal))
+    }
+
+    pub fn look_at(self, from: Point3<T>, to: Point3<T>, up: Vector3<T>) -> Self {
+        self.then(look_at(from, to, up))
+    }
+
+    pub fn matrix(&self) -> Matrix4x4<T> {
+        self.matrix
+    }
+
+    pub fn inverse(&self) -> Option<Matrix4x4<T>> {
+        self.matrix.inverse_affine()
+    }
+}
+
+impl<T: Float> From<Transform<T>> for Matrix4x4<T> {
+    fn from(t: Transform<T>) -> Self {
+        t.matrix
+    }
+}
+
+/// Transforms anything the underlying matrix can
+impl<T: Float, V> Mul<V> for Transform<T>
+where
+    Matrix4x4<T>: Mul<V>,
+{
+    type Output = <Matrix4x4<T> as Mul<V>>::Output;
+
+    fn mul(self, rhs: V) -> Self::Output {
+        self.matrix * rhs
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecomposeError {
+    /// The bottom row is not (0, 0, 0, 1)
+    Projective,
+    /// An axis is scaled to (nearly) zero, so the rotation is undefined
+    Singular,
+}
+
+impl fmt::Display for DecomposeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecomposeError::Projective => write!(f, "cannot decompose a projective matrix"),
+            DecomposeError::Singular => write!(f, "cannot decompose a singular matrix"),
+        }
+    }
+}
+
+impl Error for DecomposeError {}
+
+/// The parts of an affine transformation, which composes back as
+/// `translation * rotation * shearing(xy, xz, 0, yz, 0, 0) * scaling`.
+/// A reflection shows up as a negative z scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decomposed<T: Float = f64> {
+    pub translation: Vector3<T>,
+    pub rotation: Quaternion<T>,
+    pub scale: Vector3<T>,
+    /// The x_y, x_z and y_z terms of `shearing`
+    pub shear: [T; 3],
+}
+
+impl<T: Float> Decomposed<T> {
+    /// Blends two transformations part by part, slerping the rotation,
+    /// which keeps the in-betweens free of the distortion a blend of the
+    /// matrices would have
+    pub fn interpolate(&self, to: &Self, t: T) -> Self {
+        let lerp = |a: T, b: T| a + (b - a) * t;
+        let lerp_v = |a: Vector3<T>, b: Vector3<T>| a + (b - a) * t;
+
+        Decomposed {
+            translation: lerp_v(self.translation, to.translation),
+            rotation: self.rotation.slerp(to.rotation, t),
+            scale: lerp_v(self.scale, to.scale),
+            shear: [
+                lerp(self.shear[0], to.shear[0]),
+                lerp(self.shear[1], to.shear[1]),
+                lerp(self.shear[2], to.shear[2]),
+            ],
+        }
+    }
+}
+
+impl<T: Float> Matrix4x4<T> {
+    /// Splits an affine matrix into translation, rotation, shear and scale
+    pub fn decompose(&self) -> Result<Decomposed<T>, DecomposeError> {
+        if !self.is_affine() {
+            return Err(DecomposeError::Projective);
+        }
+
+        let m = self;
+        let column = |c: usize| vector(m[0][c], m[1][c], m[2][c]);
+        let translation = column(3);
+
+        // Gram-Schmidt on the columns of the linear part gives the rotation
+        // and an upper triangular remainder holding the shear and scale
+        let mut q = [column(0), column(1), column(2)];
+        let mut u = [[T::ZERO; 3]; 3];
+
+        // relative to the largest scale, so uniformly tiny matrices still
+        // decompose
+        let largest = q.iter().map(|c| c.magnitude()).fold(T::ZERO, T::max);
+        let tolerance = T::EPSILON * largest;
+        for c in 0..3 {
+            for r in 0..c {
+                u[r][c] = q[r].dot(q[c]);
+                q[c] -= q[r] * u[r][c];
+            }
+
+            u[c][c] = q[c].magnitude();
+            if u[c][c] <= tolerance {
+                return Err(DecomposeError::Singular);
+            }
+            q[c] = q[c] / u[c][c];
+        }
+
+        // a reflection: flip the last axis so what remains is a rotation
+        if q[0].cross(q[1]).dot(q[2]) < T::ZERO {
+            q[2] = -q[2];
+            u[2][2] = -u[2][2];
+        }
+
+        let (o, l) = (T::ZERO, T::ONE);
+        let rotation = Matrix4x4::new([
+            [q[0].x, q[1].x, q[2].x, o],
+            [q[0].y, q[1].y, q[2].y, o],
+            [q[0].z, q[1].z, q[2].z, o],
+            [o, o, o, l],
+        ]);
+
+        Ok(Decomposed {
+            translation,
+            rotation: Quaternion::from_matrix(&rotation),
+            scale: vector(u[0][0], u[1][1], u[2][2]),
+            shear: [u[0][1] / u[1][1], u[0][2] / u[2][2], u[1][2] / u[2][2]],
+        })
+    }
+
+    /// Inverse of `decompose`
+    pub fn compose(parts: &Decomposed<T>) -> Self {
+        let (t, s, [x_y, x_z, y_z]) = (parts.translation, parts.scale, parts.shear);
+        let o = T::ZERO;
+
+        translation(t.x, t.y, t.z)
+            * parts.rotation.to_matrix()
+            * shearing(x_y, x_z, o, y_z, o, o)
+            * scaling(s.x, s.y, s.z)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tuples::point;
     use crate::tuples::vector;
+    use crate::util::array_approx_equal;
 
     #[test]
     fn multiplying_by_translation_matrix() {
@@ -207,5 +503,147 @@ mod tests {
         let transform = shearing(0., 0., 0., 0., 0., 1.);
         assert_eq!(transform * p, point(2., 3., 7.));
     }
-}
 
+    #[test]
+    fn chained_transformations_apply_in_reverse_order() {
+        let p = point(1., 0., 1.);
+        let a = rotation_x(std::f64::consts::PI / 2.);
+        let b = scaling(5., 5., 5.);
+        let c = translation(10., 5., 7.);
+
+        let t = c * b * a;
+        assert_eq!(t * p, point(15., 0., 7.));
+
+        let built = Transform::identity()
+            .rotate_x(std::f64::consts::PI / 2.)
+            .scale_uniform(5.)
+            .translate(10., 5., 7.);
+        assert_eq!(built.matrix(), t);
+        assert_eq!(built * p, point(15., 0., 7.));
+        assert_eq!(built.inverse().unwrap() * point(15., 0., 7.), p);
+    }
+
+    #[test]
+    fn rotating_around_an_arbitrary_axis() {
+        let r = 0.9;
+        assert_eq!(rotation_axis_angle(vector(2., 0., 0.), r), rotation_x(r));
+        assert_eq!(rotation_axis_angle(vector(0., 1., 0.), r), rotation_y(r));
+        assert_eq!(rotation_axis_angle(vector(0., 0., 1.), r), rotation_z(r));
+
+        // a third of a turn around the diagonal cycles the axes
+        let t = rotation_axis_angle(vector(1., 1., 1.), 2. * std::f64::consts::PI / 3.);
+        assert_eq!(t * point(1., 0., 0.), point(0., 1., 0.));
+        assert_eq!(t * vector(0., 0., 1.), vector(1., 0., 0.));
+    }
+
+    #[test]
+    fn reflecting_across_a_plane() {
+        let t = reflection_across_plane(point(0., 0., 0.), vector(1., 0., 0.));
+        assert_eq!(t, scaling(-1., 1., 1.));
+
+        let t = reflection_across_plane(point(0., 2., 0.), vector(0., 3., 0.));
+        assert_eq!(t * point(1., 5., -1.), point(1., -1., -1.));
+        assert_eq!(t * vector(1., 1., 0.), vector(1., -1., 0.));
+
+        let built =
+            Transform::identity().reflect_across_plane(point(0., 2., 0.), vector(0., 1., 0.));
+        assert_eq!(built.matrix(), t);
+    }
+
+    #[test]
+    fn view_transformations() {
+        let from = point(0., 0., 0.);
+        let up = vector(0., 1., 0.);
+
+        assert_eq!(look_at(from, point(0., 0., -1.), up), Matrix4x4::identity());
+        assert_eq!(look_at(from, point(0., 0., 1.), up), scaling(-1., 1., -1.));
+        assert_eq!(
+            look_at(point(0., 0., 8.), from, up),
+            translation(0., 0., -8.)
+        );
+
+        let t = look_at(point(1., 3., 2.), point(4., -2., 8.), vector(1., 1., 0.));
+        assert_eq!(
+            t,
+            Matrix4x4::new([
+                [-0.50709, 0.50709, 0.67612, -2.36643],
+                [0.76772, 0.60609, 0.12122, -2.82843],
+                [-0.35857, 0.59761, -0.71714, 0.00000],
+                [0.00000, 0.00000, 0.00000, 1.00000],
+            ])
+        );
+    }
+
+    #[test]
+    fn decomposing_translation_rotation_and_scale() {
+        let t = Transform::identity()
+            .scale(2., 3., 0.5)
+            .rotate_axis_angle(vector(1., 2., -1.), 0.8)
+            .translate(4., -5., 6.)
+            .matrix();
+
+        let parts = t.decompose().unwrap();
+        assert_eq!(parts.translation, vector(4., -5., 6.));
+        assert_eq!(parts.scale, vector(2., 3., 0.5));
+        assert!(array_approx_equal(&parts.shear, &[0., 0., 0.]));
+        assert_eq!(
+            parts.rotation.to_matrix(),
+            rotation_axis_angle(vector(1., 2., -1.), 0.8)
+        );
+        assert_eq!(Matrix4x4::compose(&parts), t);
+    }
+
+    #[test]
+    fn decomposing_shears_and_reflections() {
+        let t = translation(1., 2., 3.)
+            * rotation_z(0.4)
+            * shearing(0.5, -0.25, 0., 1.5, 0., 0.)
+            * scaling(1., 2., 3.);
+
+        let parts = t.decompose().unwrap();
+        assert!(array_approx_equal(&parts.shear, &[0.5, -0.25, 1.5]));
+        assert_eq!(parts.scale, vector(1., 2., 3.));
+        assert_eq!(Matrix4x4::compose(&parts), t);
+
+        // shears the other way round are still recovered as a whole
+        let t = shearing(0., 0., 1., 0., 0.5, 2.) * rotation_x(1.);
+        assert_eq!(Matrix4x4::compose(&t.decompose().unwrap()), t);
+
+        let mirrored = rotation_y(0.3) * scaling(-2., 1., 1.);
+        let parts = mirrored.decompose().unwrap();
+        assert!(parts.scale.z < 0.);
+        assert_eq!(Matrix4x4::compose(&parts), mirrored);
+    }
+
+    #[test]
+    fn decomposing_fails_on_projective_and_singular_matrices() {
+        let mut projective = Matrix4x4::identity();
+        projective[3][2] = -1.;
+        assert_eq!(projective.decompose(), Err(DecomposeError::Projective));
+
+        let flat = scaling(1., 0., 1.);
+        assert_eq!(flat.decompose(), Err(DecomposeError::Singular));
+        assert_eq!(
+            uniform_scaling(0.).decompose(),
+            Err(DecomposeError::Singular)
+        );
+
+        let tiny = rotation_z(0.4) * scaling(1e-6, 2e-6, 1e-6);
+        let scale = tiny.decompose().unwrap().scale * 1e6;
+        assert_eq!(scale, vector(1., 2., 1.));
+    }
+
+    #[test]
+    fn interpolating_decomposed_transformations() {
+        let a = translation(0., 0., 0.).decompose().unwrap();
+        let b =
+            (translation(2., 0., 0.) * rotation_y(std::f64::consts::PI / 2.) * uniform_scaling(3.))
+                .decompose()
+                .unwrap();
+
+        let halfway = Matrix4x4::compose(&a.interpolate(&b, 0.5));
+        let expected =
+            translation(1., 0., 0.) * rotation_y(std::f64::consts::PI / 4.) * uniform_scaling(2.);
+        assert_eq!(halfway, expected);
+    }
+}
@@ -1,75 +1,82 @@
-use crate::util::approx_equal;
+use crate::util::{approx_equal, Float};
 use std::cmp::PartialEq;
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
 
+/// A homogeneous 4-tuple, where w = 1 marks a point and w = 0 a vector.
+///
+/// Prefer `Point3`, `Vector3` and `Normal3` (returned by `point()`,
+/// `vector()` and `normal()`), which only allow meaningful operations.
 #[derive(Debug, Clone, Copy)]
-pub struct Tuple {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
-    pub w: f64,
+pub struct Tuple<T = f64> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
 }
 
 #[allow(dead_code)]
-impl Tuple {
-    pub fn magnitude(&self) -> f64 {
+impl<T: Float> Tuple<T> {
+    pub fn magnitude(&self) -> T {
         let mag = self.x.powi(2) + self.y.powi(2) + self.z.powi(2);
         mag.sqrt()
     }
 
-    pub fn normalize(&self) -> Vector {
-        vector(
-            self.x / self.magnitude(),
-            self.y / self.magnitude(),
-            self.z / self.magnitude(),
-        )
+    /// Scales x, y and z to unit length, keeping w
+    pub fn normalize(&self) -> Tuple<T> {
+        let mag = self.magnitude();
+        tuple(self.x / mag, self.y / mag, self.z / mag, self.w)
+    }
+
+    pub fn dot(&self, rhs: Tuple<T>) -> T {
+        T::dot4(self.to_array(), rhs.to_array())
+    }
+
+    pub fn cross(&self, rhs: Tuple<T>) -> Tuple<T> {
+        let [x, y, z] = T::cross3([self.x, self.y, self.z], [rhs.x, rhs.y, rhs.z]);
+        tuple(x, y, z, T::ZERO)
     }
 
-    pub fn dot(&self, rhs: Vector) -> f64 {
-        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    pub fn to_array(&self) -> [T; 4] {
+        [self.x, self.y, self.z, self.w]
     }
 
-    pub fn cross(&self, rhs: Vector) -> Vector {
-        vector(
-            self.y * rhs.z - self.z * rhs.y,
-            self.z * rhs.x - self.x * rhs.z,
-            self.x * rhs.y - self.y * rhs.x,
+    pub fn from_array([x, y, z, w]: [T; 4]) -> Self {
+        tuple(x, y, z, w)
+    }
+
+    /// Converts to another precision
+    pub fn cast<U: Float>(&self) -> Tuple<U> {
+        tuple(
+            U::from_f64(self.x.to_f64()),
+            U::from_f64(self.y.to_f64()),
+            U::from_f64(self.z.to_f64()),
+            U::from_f64(self.w.to_f64()),
         )
     }
 }
 
-pub type Point = Tuple;
-pub type Vector = Tuple;
+pub type Point<T = f64> = Point3<T>;
+pub type Vector<T = f64> = Vector3<T>;
 
 //impl Eq for Tuple {}
 
-impl Add for Tuple {
-    type Output = Tuple;
+impl<T: Float> Add for Tuple<T> {
+    type Output = Tuple<T>;
 
-    fn add(self, other: Tuple) -> Tuple {
-        tuple(
-            self.x + other.x,
-            self.y + other.y,
-            self.z + other.z,
-            self.w + other.w,
-        )
+    fn add(self, other: Tuple<T>) -> Tuple<T> {
+        Tuple::from_array(T::add4(self.to_array(), other.to_array()))
     }
 }
 
-impl Sub for Tuple {
-    type Output = Tuple;
+impl<T: Float> Sub for Tuple<T> {
+    type Output = Tuple<T>;
 
-    fn sub(self, other: Tuple) -> Tuple {
-        tuple(
-            self.x - other.x,
-            self.y - other.y,
-            self.z - other.z,
-            self.w - other.w,
-        )
+    fn sub(self, other: Tuple<T>) -> Tuple<T> {
+        Tuple::from_array(T::sub4(self.to_array(), other.to_array()))
     }
 }
 
-impl Neg for Tuple {
+impl<T: Float> Neg for Tuple<T> {
     type Output = Self;
 
     fn neg(self) -> Self {
@@ -77,41 +84,40 @@ impl Neg for Tuple {
     }
 }
 
-impl Mul<f64> for Tuple {
+impl<T: Float> Mul<T> for Tuple<T> {
     type Output = Self;
 
-    fn mul(self, rhs: f64) -> Self {
-        tuple(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+    fn mul(self, rhs: T) -> Self {
+        Tuple::from_array(T::scale4(self.to_array(), rhs))
     }
 }
 
-impl Mul<i32> for Tuple {
+impl<T: Float> Mul<i32> for Tuple<T> {
     type Output = Self;
 
     fn mul(self, rhs: i32) -> Self {
-        let mul = rhs as f64;
-        tuple(self.x * mul, self.y * mul, self.z * mul, self.w * mul)
+        self * T::from_f64(rhs as f64)
     }
 }
 
-impl Div<f64> for Tuple {
+impl<T: Float> Div<T> for Tuple<T> {
     type Output = Self;
 
-    fn div(self, rhs: f64) -> Self {
+    fn div(self, rhs: T) -> Self {
         tuple(self.x / rhs, self.y / rhs, self.z / rhs, self.w / rhs)
     }
 }
 
-impl Div<i32> for Tuple {
+impl<T: Float> Div<i32> for Tuple<T> {
     type Output = Self;
 
     fn div(self, rhs: i32) -> Self {
-        let div = rhs as f64;
+        let div = T::from_f64(rhs as f64);
         tuple(self.x / div, self.y / div, self.z / div, self.w / div)
     }
 }
 
-impl PartialEq for Tuple {
+impl<T: Float> PartialEq for Tuple<T> {
     fn eq(&self, rhs: &Self) -> bool {
         approx_equal(self.x, rhs.x)
             && approx_equal(self.y, rhs.y)
@@ -120,16 +126,278 @@ impl PartialEq for Tuple {
     }
 }
 
-pub fn tuple(x: f64, y: f64, z: f64, w: f64) -> Tuple {
+pub fn tuple<T: Float>(x: T, y: T, z: T, w: T) -> Tuple<T> {
     Tuple { x, y, z, w }
 }
 
-pub fn point(x: f64, y: f64, z: f64) -> Point {
-    Point { x, y, z, w: 1.0 }
+pub fn point<T: Float>(x: T, y: T, z: T) -> Point3<T> {
+    Point3 { x, y, z }
+}
+
+pub fn vector<T: Float>(x: T, y: T, z: T) -> Vector3<T> {
+    Vector3 { x, y, z }
+}
+
+pub fn normal<T: Float>(x: T, y: T, z: T) -> Normal3<T> {
+    Normal3 { x, y, z }
+}
+
+// Shared parts of the 3 component types, `$w` is their homogeneous w
+macro_rules! vec3_impl {
+    ($name:ident, $w:ident) => {
+        impl<T: Float> $name<T> {
+            pub fn new(x: T, y: T, z: T) -> Self {
+                $name { x, y, z }
+            }
+
+            /// Converts to another precision
+            pub fn cast<U: Float>(&self) -> $name<U> {
+                $name {
+                    x: U::from_f64(self.x.to_f64()),
+                    y: U::from_f64(self.y.to_f64()),
+                    z: U::from_f64(self.z.to_f64()),
+                }
+            }
+        }
+
+        impl<T: Float> PartialEq for $name<T> {
+            fn eq(&self, rhs: &Self) -> bool {
+                approx_equal(self.x, rhs.x)
+                    && approx_equal(self.y, rhs.y)
+                    && approx_equal(self.z, rhs.z)
+            }
+        }
+
+        impl<T: Float> PartialEq<Tuple<T>> for $name<T> {
+            fn eq(&self, rhs: &Tuple<T>) -> bool {
+                Tuple::from(*self) == *rhs
+            }
+        }
+
+        impl<T: Float> From<$name<T>> for Tuple<T> {
+            fn from(v: $name<T>) -> Self {
+                tuple(v.x, v.y, v.z, T::$w)
+            }
+        }
+
+        /// Takes x, y and z as they are, ignoring w
+        impl<T: Float> From<Tuple<T>> for $name<T> {
+            fn from(t: Tuple<T>) -> Self {
+                $name::new(t.x, t.y, t.z)
+            }
+        }
+    };
+}
+
+/// A position in space; points can be moved by vectors, and subtracting
+/// two gives the vector between them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Point3<T = f64> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+/// A direction and length, unaffected by translation
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vector3<T = f64> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+/// A surface normal. Transform normals with the inverse transpose of the
+/// matrix applied to the surface, and normalize the result.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Normal3<T = f64> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+vec3_impl!(Point3, ONE);
+vec3_impl!(Vector3, ZERO);
+vec3_impl!(Normal3, ZERO);
+
+impl<T: Float> Point3<T> {
+    pub fn origin() -> Self {
+        point(T::ZERO, T::ZERO, T::ZERO)
+    }
+}
+
+impl<T: Float> Vector3<T> {
+    pub fn zero() -> Self {
+        vector(T::ZERO, T::ZERO, T::ZERO)
+    }
+
+    pub fn magnitude(&self) -> T {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        *self / self.magnitude()
+    }
+
+    pub fn dot(&self, rhs: Vector3<T>) -> T {
+        T::dot4(
+            [self.x, self.y, self.z, T::ZERO],
+            [rhs.x, rhs.y, rhs.z, T::ZERO],
+        )
+    }
+
+    pub fn cross(&self, rhs: Vector3<T>) -> Vector3<T> {
+        let [x, y, z] = T::cross3([self.x, self.y, self.z], [rhs.x, rhs.y, rhs.z]);
+        vector(x, y, z)
+    }
+}
+
+impl<T: Float> Normal3<T> {
+    pub fn normalize(&self) -> Self {
+        Vector3::from(*self).normalize().into()
+    }
+
+    pub fn dot(&self, rhs: Vector3<T>) -> T {
+        Vector3::from(*self).dot(rhs)
+    }
+}
+
+impl<T: Float> From<Normal3<T>> for Vector3<T> {
+    fn from(n: Normal3<T>) -> Self {
+        vector(n.x, n.y, n.z)
+    }
+}
+
+impl<T: Float> From<Vector3<T>> for Normal3<T> {
+    fn from(v: Vector3<T>) -> Self {
+        normal(v.x, v.y, v.z)
+    }
+}
+
+impl<T: Float> Add<Vector3<T>> for Point3<T> {
+    type Output = Point3<T>;
+
+    fn add(self, rhs: Vector3<T>) -> Point3<T> {
+        point(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl<T: Float> AddAssign<Vector3<T>> for Point3<T> {
+    fn add_assign(&mut self, rhs: Vector3<T>) {
+        *self = *self + rhs;
+    }
+}
+
+impl<T: Float> Sub<Vector3<T>> for Point3<T> {
+    type Output = Point3<T>;
+
+    fn sub(self, rhs: Vector3<T>) -> Point3<T> {
+        point(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl<T: Float> SubAssign<Vector3<T>> for Point3<T> {
+    fn sub_assign(&mut self, rhs: Vector3<T>) {
+        *self = *self - rhs;
+    }
+}
+
+impl<T: Float> Sub for Point3<T> {
+    type Output = Vector3<T>;
+
+    fn sub(self, rhs: Point3<T>) -> Vector3<T> {
+        vector(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl<T: Float> Add for Vector3<T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        vector(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl<T: Float> AddAssign for Vector3<T> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<T: Float> Sub for Vector3<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        vector(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
 }
 
-pub fn vector(x: f64, y: f64, z: f64) -> Vector {
-    Tuple { x, y, z, w: 0.0 }
+impl<T: Float> SubAssign for Vector3<T> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<T: Float> Neg for Vector3<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        vector(-self.x, -self.y, -self.z)
+    }
+}
+
+impl<T: Float> Mul<T> for Vector3<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self {
+        vector(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl<T: Float> Mul<i32> for Vector3<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        self * T::from_f64(rhs as f64)
+    }
+}
+
+impl<T: Float> MulAssign<T> for Vector3<T> {
+    fn mul_assign(&mut self, rhs: T) {
+        *self = *self * rhs;
+    }
+}
+
+impl<T: Float> Div<T> for Vector3<T> {
+    type Output = Self;
+
+    fn div(self, rhs: T) -> Self {
+        vector(self.x / rhs, self.y / rhs, self.z / rhs)
+    }
+}
+
+impl<T: Float> Div<i32> for Vector3<T> {
+    type Output = Self;
+
+    fn div(self, rhs: i32) -> Self {
+        self / T::from_f64(rhs as f64)
+    }
+}
+
+impl<T: Float> Neg for Normal3<T> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        normal(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Scaling a normal gives an offset along it
+impl<T: Float> Mul<T> for Normal3<T> {
+    type Output = Vector3<T>;
+
+    fn mul(self, rhs: T) -> Vector3<T> {
+        Vector3::from(self) * rhs
+    }
 }
 
 #[cfg(test)]
@@ -138,7 +406,7 @@ mod tests {
 
     /* Compare floating point numbers */
     fn approx_equal(a: f64, b: f64) -> bool {
-        (a - b).abs() <= std::f64::EPSILON
+        (a - b).abs() <= f64::EPSILON
     }
 
     #[test]
@@ -169,8 +437,9 @@ mod tests {
         assert_eq!(a.x, 4.0);
         assert_eq!(a.y, -4.0);
         assert_eq!(a.z, 3.0);
-        assert_eq!(a.w, 1.0);
+        assert_eq!(Tuple::from(a).w, 1.0);
         assert_eq!(a, b);
+        assert_eq!(Point3::from(b), a);
     }
 
     #[test]
@@ -181,8 +450,9 @@ mod tests {
         assert_eq!(a.x, 4.0);
         assert_eq!(a.y, -4.0);
         assert_eq!(a.z, 3.0);
-        assert_eq!(a.w, 0.0);
+        assert_eq!(Tuple::from(a).w, 0.0);
         assert_eq!(a, b);
+        assert_eq!(Vector3::from(b), a);
     }
 
     #[test]
@@ -305,5 +575,69 @@ mod tests {
 
         assert_eq!(a.cross(b), vector(-1.0, 2.0, -1.0));
     }
-}
 
+    #[test]
+    fn single_precision_tuples() {
+        let v = vector(1.0_f32, 2.0, 3.0);
+
+        assert_eq!(v.magnitude(), 14.0_f32.sqrt());
+        assert_eq!(v.cross(vector(2.0, 3.0, 4.0)), vector(-1.0, 2.0, -1.0));
+        assert_eq!(point(1.0_f32, 2.0, 3.0) - v, Point3::origin());
+
+        // comparisons use the f32 tolerance
+        assert_eq!(v.normalize() * 3, vector(0.80178, 1.60357, 2.40535));
+        assert_eq!(v.cast::<f64>(), vector(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn adding_vectors_to_points() {
+        let mut p = point(1.0, 2.0, 3.0);
+        let v = vector(0.5, 0.0, -1.0);
+
+        assert_eq!(p + v, point(1.5, 2.0, 2.0));
+        assert_eq!(p - v, point(0.5, 2.0, 4.0));
+        assert_eq!(point(2.0, 2.0, 2.0) - p, vector(1.0, 0.0, -1.0));
+
+        p += v;
+        p -= v * 2;
+        assert_eq!(p, point(0.5, 2.0, 4.0));
+    }
+
+    #[test]
+    fn vector_operations() {
+        let mut v = vector(1.0, -2.0, 3.0);
+
+        assert_eq!(-v, vector(-1.0, 2.0, -3.0));
+        assert_eq!(v / 2, vector(0.5, -1.0, 1.5));
+
+        v += vector(1.0, 1.0, 1.0);
+        v *= 2.0;
+        v -= vector(0.0, 0.0, 8.0);
+        assert_eq!(v, vector(4.0, -2.0, 0.0));
+        assert_eq!(Vector3::zero() + v, v);
+        assert_eq!(Point3::origin() + v, point(4.0, -2.0, 0.0));
+    }
+
+    #[test]
+    fn normals_are_directions() {
+        let n = normal(0.0, 3.0, 4.0);
+
+        assert_eq!(n.normalize(), normal(0.0, 0.6, 0.8));
+        assert_eq!(n.dot(vector(1.0, 1.0, 1.0)), 7.0);
+        assert_eq!(-n, normal(0.0, -3.0, -4.0));
+        assert_eq!(n.normalize() * 2.0, vector(0.0, 1.2, 1.6));
+        assert_eq!(Vector3::from(n), vector(0.0, 3.0, 4.0));
+        assert_eq!(n, tuple(0.0, 3.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn normalizing_tuples_keeps_w() {
+        let t = tuple(0.0, 3.0, 4.0, 1.0);
+
+        assert_eq!(t.normalize(), tuple(0.0, 0.6, 0.8, 1.0));
+        assert_eq!(
+            t.cross(tuple(1.0, 0.0, 0.0, 1.0)),
+            tuple(0.0, 4.0, -3.0, 0.0)
+        );
+    }
+}
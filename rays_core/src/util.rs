@@ -1,16 +1,184 @@
-const EPSILON:f64 = 0.00001;
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, Sub, SubAssign};
 
-pub fn approx_equal(a: f64, b: f64) -> bool {
-    (a - b).abs() <= EPSILON
+use crate::simd::Kernels;
+
+/// Floating point types the math layer (tuples, matrices, colors) works with.
+///
+/// `EPSILON` is the tolerance used for approximate comparisons, which is
+/// much larger than the machine epsilon and chosen per precision.
+/// `MACHINE_EPSILON` is the type's own rounding error bound.
+pub trait Float:
+    Copy
+    + Debug
+    + Display
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + Kernels
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const EPSILON: Self;
+    const MACHINE_EPSILON: Self;
+
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn cbrt(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn exp(self) -> Self;
+    fn floor(self) -> Self;
+    fn round(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn asin(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+    fn is_finite(self) -> bool;
 }
 
+macro_rules! float_impl {
+    ($type:ident, $epsilon:expr) => {
+        impl Float for $type {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const EPSILON: Self = $epsilon;
+            const MACHINE_EPSILON: Self = $type::EPSILON;
+
+            fn from_f64(v: f64) -> Self {
+                v as $type
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn abs(self) -> Self {
+                $type::abs(self)
+            }
+
+            fn sqrt(self) -> Self {
+                $type::sqrt(self)
+            }
+
+            fn cbrt(self) -> Self {
+                $type::cbrt(self)
+            }
+
+            fn powi(self, n: i32) -> Self {
+                $type::powi(self, n)
+            }
+
+            fn powf(self, n: Self) -> Self {
+                $type::powf(self, n)
+            }
+
+            fn exp(self) -> Self {
+                $type::exp(self)
+            }
+
+            fn floor(self) -> Self {
+                $type::floor(self)
+            }
+
+            fn round(self) -> Self {
+                $type::round(self)
+            }
+
+            fn sin(self) -> Self {
+                $type::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                $type::cos(self)
+            }
+
+            fn tan(self) -> Self {
+                $type::tan(self)
+            }
+
+            fn asin(self) -> Self {
+                $type::asin(self)
+            }
+
+            fn acos(self) -> Self {
+                $type::acos(self)
+            }
 
-pub fn array_approx_equal(a: &[f64], b: &[f64]) -> bool {
-    for (i,j) in a.iter().zip(b.iter()) {
-        let diff = (i - j).abs();
+            fn atan2(self, other: Self) -> Self {
+                $type::atan2(self, other)
+            }
 
-        if diff > EPSILON { return false; } 
+            fn min(self, other: Self) -> Self {
+                $type::min(self, other)
+            }
+
+            fn max(self, other: Self) -> Self {
+                $type::max(self, other)
+            }
+
+            fn clamp(self, min: Self, max: Self) -> Self {
+                $type::clamp(self, min, max)
+            }
+
+            fn is_finite(self) -> bool {
+                $type::is_finite(self)
+            }
+        }
+    };
+}
+
+float_impl!(f32, 0.0001);
+float_impl!(f64, 0.00001);
+
+pub fn approx_equal<T: Float>(a: T, b: T) -> bool {
+    (a - b).abs() <= T::EPSILON
+}
+
+pub fn array_approx_equal<T: Float>(a: &[T], b: &[T]) -> bool {
+    for (i, j) in a.iter().zip(b.iter()) {
+        let diff = (*i - *j).abs();
+
+        if diff > T::EPSILON {
+            return false;
+        }
     }
-    
+
     true
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsilon_depends_on_precision() {
+        assert!(approx_equal(1.0_f64, 1.000_001));
+        assert!(!approx_equal(1.0_f64, 1.000_1));
+
+        assert!(approx_equal(1.0_f32, 1.000_05));
+        assert!(!approx_equal(1.0_f32, 1.001));
+
+        assert!(array_approx_equal(&[0.1_f32 + 0.2, 1.0], &[0.3, 1.0]));
+    }
+}
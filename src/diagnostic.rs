@@ -0,0 +1,203 @@
+//! Error reports that point into hand-written source files.
+
+use std::fmt;
+
+use crate::yaml::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// A hint on how to fix the problem
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(span: Span, message: String) -> Self {
+        Diagnostic {
+            message,
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    /// Formats the diagnostic with the line it points at, underlining the
+    /// offending text:
+    ///
+    /// ```text
+    /// error: unknown key `materal`
+    ///  --> scene.yml:3:3
+    ///   |
+    /// 3 |   materal: shiny
+    ///   |   ^^^^^^^
+    ///   = help: did you mean `material`?
+    /// ```
+    pub fn render(&self, src: &str, name: &str) -> String {
+        let Span {
+            start,
+            line,
+            column,
+            ..
+        } = self.span;
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        let text = src[line_start..line_end].trim_end_matches('\r');
+
+        // only the first line of multi-line spans is shown
+        let end = self.span.end.clamp(start, line_start + text.len());
+        let width = src.get(start..end).map_or(0, |s| s.chars().count()).max(1);
+
+        let number = line.to_string();
+        let pad = " ".repeat(number.len());
+
+        let mut out = format!("error: {}\n", self.message);
+        out += &format!("{}--> {}:{}:{}\n", pad, name, line, column);
+        out += &format!("{} |\n", pad);
+        out += &format!("{} | {}\n", number, text);
+        out += &format!("{} | {}{}", pad, " ".repeat(column - 1), "^".repeat(width));
+        if let Some(help) = &self.help {
+            out += &format!("\n{} = help: {}", pad, help);
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )?;
+        if let Some(help) = &self.help {
+            write!(f, " ({})", help)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The candidate closest to a misspelled `name`, if any is close enough to
+/// be a likely typo
+pub fn did_you_mean<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|&(d, _)| d <= limit)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+/// Suggestion text for `did_you_mean`
+pub fn suggestion<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    did_you_mean(name, candidates).map(|c| format!("did you mean `{}`?", c))
+}
+
+// Edit distance by characters, counting a swap of neighbours as one edit
+// since that's the most common typo
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // rows i - 2, i - 1 and i of the usual table
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        row[0] = i;
+
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (previous[j - 1] + cost)
+                .min(previous[j] + 1)
+                .min(row[j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut row);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggesting_close_names() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(edit_distance("galss", "glass"), 1);
+
+        let keys = ["material", "transform", "shadow"];
+        assert_eq!(
+            did_you_mean("materal", keys.iter().copied()),
+            Some("material")
+        );
+        assert_eq!(did_you_mean("shadwo", keys.iter().copied()), Some("shadow"));
+        assert_eq!(did_you_mean("color", keys.iter().copied()), None);
+        assert_eq!(
+            suggestion("transfrom", keys.iter().copied()),
+            Some("did you mean `transform`?".to_string())
+        );
+    }
+
+    #[test]
+    fn rendering_with_the_source_line() {
+        let src = "- add: sphere\n  materal: shiny\n";
+        let span = Span {
+            start: 16,
+            end: 23,
+            line: 2,
+            column: 3,
+        };
+        let d = Diagnostic::new(span, "unknown key `materal`".into())
+            .with_help("did you mean `material`?".into());
+
+        assert_eq!(
+            d.render(src, "scene.yml"),
+            "error: unknown key `materal`\n \
+             --> scene.yml:2:3\n  \
+             |\n\
+             2 |   materal: shiny\n  \
+             |   ^^^^^^^\n  \
+             = help: did you mean `material`?"
+        );
+        assert_eq!(
+            d.to_string(),
+            "2:3: unknown key `materal` (did you mean `material`?)"
+        );
+
+        // spans ending inside a character still underline it
+        let span = Span {
+            start: 3,
+            end: 4,
+            line: 1,
+            column: 4,
+        };
+        assert!(Diagnostic::new(span, "bad".into())
+            .render("a: é\n", "scene.yml")
+            .ends_with("1 | a: é\n  |    ^"));
+    }
+}
@@ -0,0 +1,1239 @@
+//! glTF 2.0 import, from `.gltf` files with external or embedded buffers and
+//! from binary `.glb` files.
+//!
+//! Meshes, the node hierarchy, materials and perspective cameras of the
+//! default scene are imported. Nodes become groups carrying their local
+//! transform, and each mesh primitive becomes a mesh object. PBR
+//! metallic-roughness materials are approximated by Phong materials; their
+//! textures, emission and skinning or animation data are not carried over.
+//!
+//! glTF is right-handed while our world is left-handed, so the imported
+//! scene is mirrored along z.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use rays_core::matrices::Matrix4x4;
+use rays_core::quaternion::Quaternion;
+use rays_core::transformations::{scaling, Transform};
+use rays_core::tuples::{normal, point, vector};
+use rays_core::Color;
+
+use crate::mesh::Mesh;
+use crate::scene_file::{Camera, Material, Object, Shape};
+use crate::triangle_mesh::TriangleMesh;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+const MODE_TRIANGLES: u32 = 4;
+const MODE_TRIANGLE_STRIP: u32 = 5;
+const MODE_TRIANGLE_FAN: u32 = 6;
+
+// Extensions whose absence would change how the file looks; any other
+// required extension is refused
+const SUPPORTED_EXTENSIONS: [&str; 2] = ["KHR_materials_transmission", "KHR_materials_ior"];
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Json(serde_json::Error),
+    /// The file breaks the glTF specification or uses a feature we don't
+    /// support
+    Invalid(String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Io { path, error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            GltfError::Json(e) => write!(f, "invalid glTF JSON: {}", e),
+            GltfError::Invalid(message) => write!(f, "invalid glTF: {}", message),
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(e: serde_json::Error) -> Self {
+        GltfError::Json(e)
+    }
+}
+
+fn invalid<T>(message: String) -> Result<T, GltfError> {
+    Err(GltfError::Invalid(message))
+}
+
+/// A perspective camera from the file. glTF cameras don't store an image
+/// size, so the renderer picks one with `camera`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfCamera {
+    pub name: Option<String>,
+    /// Camera to world; the camera looks down its local -z with +y up
+    pub transform: Matrix4x4,
+    /// Vertical field of view in radians
+    pub yfov: f64,
+    /// Width over height, if the file asks for one
+    pub aspect_ratio: Option<f64>,
+}
+
+impl GltfCamera {
+    pub fn camera(&self, width: u32, height: u32) -> Camera {
+        let aspect = width as f64 / height as f64;
+
+        // our field of view spans the longer side of the image
+        let field_of_view = if aspect >= 1. {
+            2. * ((self.yfov / 2.).tan() * aspect).atan()
+        } else {
+            self.yfov
+        };
+
+        Camera {
+            width,
+            height,
+            field_of_view,
+            from: self.transform * point(0., 0., 0.),
+            to: self.transform * point(0., 0., -1.),
+            up: self.transform * vector(0., 1., 0.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GltfScene {
+    /// One object per root node of the scene
+    pub objects: Vec<Object>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+impl GltfScene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|error| GltfError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        Self::from_bytes(&bytes, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Reads `.gltf` JSON or a `.glb` container, resolving external buffers
+    /// against `base_dir`
+    pub fn from_bytes(bytes: &[u8], base_dir: &Path) -> Result<GltfScene, GltfError> {
+        let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+            split_glb(bytes)?
+        } else {
+            (bytes, None)
+        };
+
+        let doc: Document = serde_json::from_slice(json)?;
+        if !doc.asset.version.starts_with("2.") {
+            return invalid(format!(
+                "version {} is not supported, only 2.x",
+                doc.asset.version
+            ));
+        }
+        if let Some(ext) = doc
+            .extensions_required
+            .iter()
+            .find(|e| !SUPPORTED_EXTENSIONS.contains(&e.as_str()))
+        {
+            return invalid(format!("required extension {} is not supported", ext));
+        }
+
+        let buffers = doc
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(i, b)| load_buffer(i, b, bin, base_dir))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut importer = Importer {
+            doc: &doc,
+            buffers,
+            meshes: HashMap::new(),
+            visited: vec![false; doc.nodes.len()],
+            scene: GltfScene::default(),
+        };
+
+        let roots = match (doc.scene, doc.scenes.first()) {
+            (Some(i), _) => match doc.scenes.get(i) {
+                Some(scene) => scene.nodes.clone(),
+                None => return invalid(format!("scene {} does not exist", i)),
+            },
+            (None, Some(scene)) => scene.nodes.clone(),
+            // without scenes, every node that isn't a child is a root
+            (None, None) => {
+                let children: Vec<usize> =
+                    doc.nodes.iter().flat_map(|n| n.children.clone()).collect();
+                (0..doc.nodes.len())
+                    .filter(|i| !children.contains(i))
+                    .collect()
+            }
+        };
+
+        // mirror into our left-handed world
+        let mirror = scaling(1., 1., -1.);
+        for root in roots {
+            if let Some(mut object) = importer.node(root, mirror)? {
+                object.transform = mirror * object.transform;
+                importer.scene.objects.push(object);
+            }
+        }
+
+        Ok(importer.scene)
+    }
+}
+
+// The JSON and binary chunks of a .glb file
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfError> {
+    let word = |at: usize| -> Option<u32> {
+        let b = bytes.get(at..at + 4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    match word(4) {
+        Some(2) => {}
+        Some(v) => return invalid(format!("GLB version {} is not supported", v)),
+        None => return invalid("truncated GLB header".into()),
+    }
+    let length = (word(8).unwrap_or(0) as usize).min(bytes.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut at = 12;
+    while at + 8 <= length {
+        let size = word(at).unwrap_or(0) as usize;
+        let kind = word(at + 4).unwrap_or(0);
+        let data = match bytes.get(at + 8..at + 8 + size) {
+            Some(data) => data,
+            None => return invalid("GLB chunk runs past the end of the file".into()),
+        };
+
+        match kind {
+            CHUNK_JSON if json.is_none() => json = Some(data),
+            CHUNK_BIN if bin.is_none() => bin = Some(data),
+            // unknown chunks must be ignored
+            _ => {}
+        }
+
+        // chunks are padded to 4 bytes
+        at += 8 + size.div_ceil(4) * 4;
+    }
+
+    match json {
+        Some(json) => Ok((json, bin)),
+        None => invalid("GLB file has no JSON chunk".into()),
+    }
+}
+
+fn load_buffer(
+    index: usize,
+    buffer: &BufferDef,
+    bin: Option<&[u8]>,
+    base_dir: &Path,
+) -> Result<Vec<u8>, GltfError> {
+    let data = match &buffer.uri {
+        // the first buffer of a .glb file without a uri is its binary chunk
+        None => match bin {
+            Some(bin) if index == 0 => bin.to_vec(),
+            _ => return invalid(format!("buffer {} has no data", index)),
+        },
+        Some(uri) if uri.starts_with("data:") => {
+            let encoded = match uri.find(";base64,") {
+                Some(i) => &uri[i + 8..],
+                None => return invalid(format!("buffer {} is not a base64 data URI", index)),
+            };
+            match decode_base64(encoded) {
+                Some(data) => data,
+                None => return invalid(format!("buffer {} has invalid base64 data", index)),
+            }
+        }
+        Some(uri) => {
+            let path = base_dir.join(decode_uri(uri));
+            fs::read(&path).map_err(|error| GltfError::Io { path, error })?
+        }
+    };
+
+    if data.len() < buffer.byte_length {
+        return invalid(format!(
+            "buffer {} holds {} bytes but should have {}",
+            index,
+            data.len(),
+            buffer.byte_length
+        ));
+    }
+
+    Ok(data)
+}
+
+// Relative URIs may percent-encode characters like spaces
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0_u32;
+    let mut count = 0;
+
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+
+        bits = bits << 6 | v as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+struct Importer<'a> {
+    doc: &'a Document,
+    buffers: Vec<Vec<u8>>,
+    /// Primitives of each mesh read so far, shared between the nodes
+    /// using them
+    meshes: HashMap<usize, Vec<(Arc<TriangleMesh>, Material)>>,
+    visited: Vec<bool>,
+    scene: GltfScene,
+}
+
+impl<'a> Importer<'a> {
+    // The node and its subtree as an object, or `None` if it holds no
+    // geometry. `parent` is the world transform above the node.
+    fn node(&mut self, index: usize, parent: Matrix4x4) -> Result<Option<Object>, GltfError> {
+        let doc = self.doc;
+        let node = match doc.nodes.get(index) {
+            Some(node) => node,
+            None => return invalid(format!("node {} does not exist", index)),
+        };
+        if std::mem::replace(&mut self.visited[index], true) {
+            return invalid(format!("node {} appears twice in the hierarchy", index));
+        }
+
+        let local = node.transform();
+        let world = parent * local;
+
+        if let Some(camera) = node.camera {
+            self.camera(camera, node, world)?;
+        }
+
+        let mut parts = vec![];
+        if let Some(mesh) = node.mesh {
+            for (mesh, material) in self.mesh(mesh)? {
+                parts.push(Object {
+                    shape: Shape::Mesh(mesh),
+                    transform: Matrix4x4::identity(),
+                    material,
+                    shadow: true,
+                });
+            }
+        }
+        for &child in &node.children {
+            if let Some(child) = self.node(child, world)? {
+                parts.push(child);
+            }
+        }
+
+        Ok(match parts.len() {
+            0 => None,
+            1 if node.children.is_empty() => {
+                let mut object = parts.remove(0);
+                object.transform = local;
+                Some(object)
+            }
+            _ => Some(Object {
+                shape: Shape::Group(parts),
+                transform: local,
+                material: Material::default(),
+                shadow: true,
+            }),
+        })
+    }
+
+    fn camera(&mut self, index: usize, node: &NodeDef, world: Matrix4x4) -> Result<(), GltfError> {
+        let camera = match self.doc.cameras.get(index) {
+            Some(camera) => camera,
+            None => return invalid(format!("camera {} does not exist", index)),
+        };
+
+        // orthographic cameras have no equivalent here and are skipped
+        if let ("perspective", Some(p)) = (camera.kind.as_str(), &camera.perspective) {
+            self.scene.cameras.push(GltfCamera {
+                name: node.name.clone(),
+                transform: world,
+                yfov: p.yfov,
+                aspect_ratio: p.aspect_ratio,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn mesh(&mut self, index: usize) -> Result<Vec<(Arc<TriangleMesh>, Material)>, GltfError> {
+        if let Some(primitives) = self.meshes.get(&index) {
+            return Ok(primitives.clone());
+        }
+
+        let mesh = match self.doc.meshes.get(index) {
+            Some(mesh) => mesh,
+            None => return invalid(format!("mesh {} does not exist", index)),
+        };
+
+        let mut primitives = vec![];
+        for primitive in &mesh.primitives {
+            if let Some(m) = self.primitive(primitive)? {
+                let material = match primitive.material {
+                    Some(i) => match self.doc.materials.get(i) {
+                        Some(material) => material.to_material(),
+                        None => return invalid(format!("material {} does not exist", i)),
+                    },
+                    None => Material::default(),
+                };
+                primitives.push((Arc::new(TriangleMesh::new(m)), material));
+            }
+        }
+
+        self.meshes.insert(index, primitives.clone());
+        Ok(primitives)
+    }
+
+    // Points and lines are skipped, since they have no surface to render
+    fn primitive(&self, primitive: &PrimitiveDef) -> Result<Option<Mesh>, GltfError> {
+        if !matches!(
+            primitive.mode,
+            MODE_TRIANGLES | MODE_TRIANGLE_STRIP | MODE_TRIANGLE_FAN
+        ) {
+            return Ok(None);
+        }
+
+        let positions = match primitive.attributes.get("POSITION") {
+            Some(&a) => self.accessor(a, "VEC3")?,
+            None => return invalid("mesh primitive without POSITION".into()),
+        };
+        let positions: Vec<_> = positions
+            .chunks_exact(3)
+            .map(|p| point(p[0], p[1], p[2]))
+            .collect();
+
+        let normals = match primitive.attributes.get("NORMAL") {
+            Some(&a) => self
+                .accessor(a, "VEC3")?
+                .chunks_exact(3)
+                .map(|n| normal(n[0], n[1], n[2]))
+                .collect(),
+            None => vec![],
+        };
+
+        let uvs = match primitive.attributes.get("TEXCOORD_0") {
+            Some(&a) => self
+                .accessor(a, "VEC2")?
+                .chunks_exact(2)
+                .map(|uv| [uv[0], uv[1]])
+                .collect(),
+            None => vec![],
+        };
+
+        if !normals.is_empty() && normals.len() != positions.len()
+            || !uvs.is_empty() && uvs.len() != positions.len()
+        {
+            return invalid("vertex attributes have different counts".into());
+        }
+
+        let indices: Vec<u32> = match primitive.indices {
+            Some(a) => self.indices(a)?,
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return invalid(format!(
+                "index {} is out of range for {} vertices",
+                i,
+                positions.len()
+            ));
+        }
+
+        let triangles = assemble(primitive.mode, &indices);
+
+        Ok(Some(Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            ..Default::default()
+        }))
+    }
+
+    // Where the raw bytes of an accessor's elements are
+    fn elements(&self, index: usize) -> Result<(&AccessorDef, Elements<'_>), GltfError> {
+        let accessor = match self.doc.accessors.get(index) {
+            Some(a) => a,
+            None => return invalid(format!("accessor {} does not exist", index)),
+        };
+        if accessor.sparse.is_some() {
+            return invalid(format!("sparse accessor {} is not supported", index));
+        }
+
+        let size = component_size(accessor.component_type)? * components(&accessor.kind)?;
+
+        let view_index = match accessor.buffer_view {
+            Some(v) => v,
+            // accessors without a view are all zeros. Nothing backs them, so
+            // their count is bounded by the data the rest of the mesh has
+            None if accessor.count > self.buffers.iter().map(Vec::len).sum() => {
+                return invalid(format!("accessor {} has too many elements", index))
+            }
+            None => {
+                let zeros = Elements {
+                    data: &ZEROS[..size.min(ZEROS.len())],
+                    start: 0,
+                    stride: 0,
+                    size: size.min(ZEROS.len()),
+                    count: accessor.count,
+                };
+                return Ok((accessor, zeros));
+            }
+        };
+
+        let view = match self.doc.buffer_views.get(view_index) {
+            Some(view) => view,
+            None => return invalid(format!("buffer view {} does not exist", view_index)),
+        };
+        let buffer = match self.buffers.get(view.buffer) {
+            Some(buffer) => buffer,
+            None => return invalid(format!("buffer {} does not exist", view.buffer)),
+        };
+
+        // offsets and counts come straight from the file, so the range is
+        // checked without overflowing
+        let stride = view.byte_stride.unwrap_or(size);
+        let start = view.byte_offset.checked_add(accessor.byte_offset);
+        let end = view.byte_offset.checked_add(view.byte_length);
+        let last = start.and_then(|start| {
+            stride
+                .checked_mul(accessor.count.saturating_sub(1))?
+                .checked_add(start)?
+                .checked_add(size)
+        });
+        let start = match (start, end, last) {
+            _ if accessor.count == 0 => 0,
+            (Some(start), Some(end), Some(last)) if last <= end && end <= buffer.len() => start,
+            _ => return invalid(format!("accessor {} reads past its buffer", index)),
+        };
+
+        let elements = Elements {
+            data: buffer,
+            start,
+            stride,
+            size,
+            count: accessor.count,
+        };
+        Ok((accessor, elements))
+    }
+
+    // Floating point attribute data, unpacking normalized integers
+    fn accessor(&self, index: usize, kind: &str) -> Result<Vec<f32>, GltfError> {
+        let (accessor, elements) = self.elements(index)?;
+        if accessor.kind != kind {
+            return invalid(format!(
+                "accessor {} is {} but should be {}",
+                index, accessor.kind, kind
+            ));
+        }
+
+        let size = component_size(accessor.component_type)?;
+        let unpack = |b: &[u8]| -> f32 {
+            let norm = accessor.normalized;
+            match accessor.component_type {
+                5120 if norm => (b[0] as i8 as f32 / 127.).max(-1.),
+                5120 => b[0] as i8 as f32,
+                5121 if norm => b[0] as f32 / 255.,
+                5121 => b[0] as f32,
+                5122 if norm => (i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.).max(-1.),
+                5122 => i16::from_le_bytes([b[0], b[1]]) as f32,
+                5123 if norm => u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.,
+                5123 => u16::from_le_bytes([b[0], b[1]]) as f32,
+                5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+                _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            }
+        };
+
+        Ok(elements
+            .iter()
+            .flat_map(|e| e.chunks_exact(size).map(unpack))
+            .collect())
+    }
+
+    fn indices(&self, index: usize) -> Result<Vec<u32>, GltfError> {
+        let (accessor, elements) = self.elements(index)?;
+
+        elements
+            .iter()
+            .map(
+                |b| match (accessor.kind.as_str(), accessor.component_type) {
+                    ("SCALAR", 5121) => Ok(b[0] as u32),
+                    ("SCALAR", 5123) => Ok(u16::from_le_bytes([b[0], b[1]]) as u32),
+                    ("SCALAR", 5125) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    _ => invalid(format!(
+                        "accessor {} can't hold indices, they must be unsigned integer scalars",
+                        index
+                    )),
+                },
+            )
+            .collect()
+    }
+}
+
+// An accessor's elements, `size` bytes every `stride` from `start`, checked
+// to lie within `data`. Zero filled accessors repeat the same zeros
+struct Elements<'a> {
+    data: &'a [u8],
+    start: usize,
+    stride: usize,
+    size: usize,
+    count: usize,
+}
+
+impl<'a> Elements<'a> {
+    fn iter(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        (0..self.count).map(move |i| {
+            let at = self.start + i * self.stride;
+            &self.data[at..at + self.size]
+        })
+    }
+}
+
+// Triangles from indices in the primitive's mode
+fn assemble(mode: u32, indices: &[u32]) -> Vec<[u32; 3]> {
+    match mode {
+        MODE_TRIANGLE_STRIP => (0..indices.len().saturating_sub(2))
+            .map(|i| {
+                // every other triangle is flipped to keep the winding
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i + 1], indices[i], indices[i + 2]]
+                }
+            })
+            .collect(),
+        MODE_TRIANGLE_FAN => (1..indices.len().saturating_sub(1))
+            .map(|i| [indices[0], indices[i], indices[i + 1]])
+            .collect(),
+        _ => indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+    }
+}
+
+// Enough for the largest element, a MAT4 of floats
+static ZEROS: [u8; 64] = [0; 64];
+
+fn component_size(component_type: u32) -> Result<usize, GltfError> {
+    match component_type {
+        5120 | 5121 => Ok(1),
+        5122 | 5123 => Ok(2),
+        5125 | 5126 => Ok(4),
+        t => invalid(format!("unknown component type {}", t)),
+    }
+}
+
+fn components(kind: &str) -> Result<usize, GltfError> {
+    match kind {
+        "SCALAR" => Ok(1),
+        "VEC2" => Ok(2),
+        "VEC3" => Ok(3),
+        "VEC4" | "MAT2" => Ok(4),
+        "MAT3" => Ok(9),
+        "MAT4" => Ok(16),
+        k => invalid(format!("unknown accessor type {}", k)),
+    }
+}
+
+// The subset of the glTF schema we read
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    asset: Asset,
+    #[serde(default)]
+    extensions_required: Vec<String>,
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<SceneDef>,
+    #[serde(default)]
+    nodes: Vec<NodeDef>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+    #[serde(default)]
+    materials: Vec<MaterialDef>,
+    #[serde(default)]
+    cameras: Vec<CameraDef>,
+    #[serde(default)]
+    accessors: Vec<AccessorDef>,
+    #[serde(default)]
+    buffer_views: Vec<BufferViewDef>,
+    #[serde(default)]
+    buffers: Vec<BufferDef>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct SceneDef {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct NodeDef {
+    name: Option<String>,
+    #[serde(default)]
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    matrix: Option<[f64; 16]>,
+    translation: Option<[f64; 3]>,
+    rotation: Option<[f64; 4]>,
+    scale: Option<[f64; 3]>,
+}
+
+impl NodeDef {
+    // The local transform, from a column-major matrix or translation,
+    // rotation and scale applied in reverse
+    fn transform(&self) -> Matrix4x4 {
+        if let Some(m) = self.matrix {
+            let mut matrix = Matrix4x4::zeros();
+            for (i, v) in m.iter().enumerate() {
+                matrix[i % 4][i / 4] = *v;
+            }
+            return matrix;
+        }
+
+        let [sx, sy, sz] = self.scale.unwrap_or([1., 1., 1.]);
+        let [x, y, z, w] = self.rotation.unwrap_or([0., 0., 0., 1.]);
+        let [tx, ty, tz] = self.translation.unwrap_or([0., 0., 0.]);
+
+        Transform::identity()
+            .scale(sx, sy, sz)
+            .rotate(Quaternion::new(w, x, y, z))
+            .translate(tx, ty, tz)
+            .matrix()
+    }
+}
+
+#[derive(Deserialize)]
+struct MeshDef {
+    primitives: Vec<PrimitiveDef>,
+}
+
+#[derive(Deserialize)]
+struct PrimitiveDef {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    #[serde(default = "default_mode")]
+    mode: u32,
+}
+
+fn default_mode() -> u32 {
+    MODE_TRIANGLES
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct MaterialDef {
+    #[serde(default)]
+    pbr_metallic_roughness: PbrDef,
+    alpha_mode: Option<String>,
+    #[serde(default)]
+    extensions: MaterialExtensions,
+}
+
+impl MaterialDef {
+    // Smooth metals lose their diffuse color to reflection, rough ones
+    // scatter it, and rough surfaces get broad, faint highlights
+    fn to_material(&self) -> Material {
+        let pbr = &self.pbr_metallic_roughness;
+        let [r, g, b, alpha] = pbr.base_color_factor;
+        let metallic = pbr.metallic_factor.clamp(0., 1.);
+        let roughness = pbr.roughness_factor.clamp(0., 1.);
+
+        // Blinn-Phong exponent matching the microfacet distribution's
+        // width, with the usual alpha = roughness^2
+        let a = (roughness * roughness).max(1e-3);
+        let shininess = (2. / (a * a) - 2.).clamp(1., 1000.);
+
+        let transparency = match &self.extensions.transmission {
+            Some(t) => t.transmission_factor,
+            None if self.alpha_mode.as_deref() == Some("BLEND") => 1. - alpha,
+            None => 0.,
+        };
+
+        let reflective = metallic * (1. - roughness);
+
+        Material {
+            color: Color::new(r, g, b),
+            ambient: 0.1,
+            diffuse: 0.9 * (1. - reflective),
+            specular: 0.9 * (1. - roughness),
+            shininess,
+            reflective,
+            transparency,
+            refractive_index: if transparency > 0. {
+                self.extensions.ior.as_ref().map_or(1.5, |i| i.ior)
+            } else {
+                1.
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PbrDef {
+    #[serde(default = "default_base_color")]
+    base_color_factor: [f64; 4],
+    #[serde(default = "default_factor")]
+    metallic_factor: f64,
+    #[serde(default = "default_factor")]
+    roughness_factor: f64,
+}
+
+impl Default for PbrDef {
+    fn default() -> Self {
+        PbrDef {
+            base_color_factor: default_base_color(),
+            metallic_factor: default_factor(),
+            roughness_factor: default_factor(),
+        }
+    }
+}
+
+fn default_base_color() -> [f64; 4] {
+    [1., 1., 1., 1.]
+}
+
+fn default_factor() -> f64 {
+    1.
+}
+
+#[derive(Deserialize, Default)]
+struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_transmission")]
+    transmission: Option<TransmissionDef>,
+    #[serde(rename = "KHR_materials_ior")]
+    ior: Option<IorDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransmissionDef {
+    #[serde(default)]
+    transmission_factor: f64,
+}
+
+#[derive(Deserialize)]
+struct IorDef {
+    #[serde(default = "default_ior")]
+    ior: f64,
+}
+
+fn default_ior() -> f64 {
+    1.5
+}
+
+#[derive(Deserialize)]
+struct CameraDef {
+    #[serde(rename = "type")]
+    kind: String,
+    perspective: Option<PerspectiveDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PerspectiveDef {
+    yfov: f64,
+    aspect_ratio: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessorDef {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    sparse: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferViewDef {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferDef {
+    uri: Option<String>,
+    byte_length: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::transformations::*;
+    use std::f64::consts::PI;
+
+    // A quad as two triangles of an indexed mesh, then 4 bytes of padding
+    fn quad_buffer() -> Vec<u8> {
+        let positions: [f32; 12] = [0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 0.];
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+        let mut bytes = vec![];
+        positions
+            .iter()
+            .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+        indices
+            .iter()
+            .for_each(|i| bytes.extend_from_slice(&i.to_le_bytes()));
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    fn encode_base64(data: &[u8]) -> String {
+        let table = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in data.chunks(3) {
+            let b = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(table[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn document(buffer: &str) -> String {
+        format!(
+            r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [ {{ "nodes": [0, 3] }} ],
+  "nodes": [
+    {{ "name": "table", "translation": [0, 1, 0], "children": [1, 2] }},
+    {{ "mesh": 0, "scale": [2, 2, 2] }},
+    {{ "mesh": 0, "rotation": [0, 0.7071068, 0, 0.7071068] }},
+    {{ "name": "view", "camera": 0, "translation": [0, 0, 5] }}
+  ],
+  "meshes": [ {{ "primitives": [
+    {{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }}
+  ] }} ],
+  "materials": [ {{
+    "pbrMetallicRoughness": {{
+      "baseColorFactor": [0.8, 0.2, 0.1, 1],
+      "metallicFactor": 0,
+      "roughnessFactor": 0.5
+    }}
+  }} ],
+  "cameras": [ {{ "type": "perspective", "perspective": {{ "yfov": 0.8, "znear": 0.1 }} }} ],
+  "accessors": [
+    {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" }},
+    {{ "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }}
+  ],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+    {{ "buffer": 0, "byteOffset": 48, "byteLength": 12 }}
+  ],
+  "buffers": [ {{ {}"byteLength": 60 }} ]
+}}"#,
+            buffer
+        )
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+
+        let mut out = b"glTF".to_vec();
+        out.extend_from_slice(&2_u32.to_le_bytes());
+        out.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+        out.extend_from_slice(&json);
+        out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        out.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+        out.extend_from_slice(bin);
+        out
+    }
+
+    fn check_scene(scene: &GltfScene) {
+        assert_eq!(scene.objects.len(), 1);
+        let table = &scene.objects[0];
+        assert_eq!(
+            table.transform,
+            scaling(1., 1., -1.) * translation(0., 1., 0.)
+        );
+
+        let legs = match &table.shape {
+            Shape::Group(legs) => legs,
+            s => panic!("expected a group, found {:?}", s),
+        };
+        assert_eq!(legs[0].transform, scaling(2., 2., 2.));
+        assert_eq!(legs[1].transform, rotation_y(PI / 2.));
+
+        match (&legs[0].shape, &legs[1].shape) {
+            (Shape::Mesh(a), Shape::Mesh(b)) => {
+                // both nodes share the one mesh
+                assert!(Arc::ptr_eq(a, b));
+                assert_eq!(a.mesh().triangles, vec![[0, 1, 2], [0, 2, 3]]);
+                assert_eq!(a.mesh().triangle(1)[1], point(1_f32, 1., 0.));
+                assert!(a.mesh().normals.is_empty());
+            }
+            s => panic!("expected meshes, found {:?}", s),
+        }
+
+        let material = &legs[0].material;
+        assert_eq!(material.color, Color::new(0.8, 0.2, 0.1));
+        assert_eq!(material.diffuse, 0.9);
+        assert_eq!(material.specular, 0.45);
+        assert_eq!(material.shininess, 30.);
+        assert_eq!(material.reflective, 0.);
+
+        let camera = scene.cameras[0].camera(200, 100);
+        assert_eq!(scene.cameras[0].name.as_deref(), Some("view"));
+        assert_eq!(camera.from, point(0., 0., -5.));
+        assert_eq!(camera.to, point(0., 0., -4.));
+        assert_eq!(camera.up, vector(0., 1., 0.));
+        assert!((camera.field_of_view - 2. * ((0.4_f64).tan() * 2.).atan()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn importing_embedded_and_binary_files() {
+        let buffer = quad_buffer();
+
+        let uri = format!(
+            "\"uri\": \"data:application/octet-stream;base64,{}\", ",
+            encode_base64(&buffer)
+        );
+        let gltf = GltfScene::from_bytes(document(&uri).as_bytes(), Path::new("")).unwrap();
+        check_scene(&gltf);
+
+        let binary = GltfScene::from_bytes(&glb(&document(""), &buffer), Path::new("")).unwrap();
+        check_scene(&binary);
+    }
+
+    #[test]
+    fn importing_external_buffers() {
+        let dir = std::env::temp_dir().join(format!("rays-gltf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("quad data.bin"), quad_buffer()).unwrap();
+        fs::write(
+            dir.join("quad.gltf"),
+            document("\"uri\": \"quad%20data.bin\", "),
+        )
+        .unwrap();
+
+        let scene = GltfScene::load(dir.join("quad.gltf"));
+        fs::remove_dir_all(&dir).unwrap();
+        check_scene(&scene.unwrap());
+    }
+
+    #[test]
+    fn approximating_pbr_materials() {
+        let material = |json: &str| {
+            serde_json::from_str::<MaterialDef>(json)
+                .unwrap()
+                .to_material()
+        };
+
+        // glTF defaults to a rough metal, which still shows its color
+        let metal =
+            material(r#"{ "pbrMetallicRoughness": { "baseColorFactor": [0.8, 0.2, 0.1, 1] } }"#);
+        assert_eq!(metal.color, Color::new(0.8, 0.2, 0.1));
+        assert_eq!(metal.diffuse, 0.9);
+        assert_eq!(metal.specular, 0.);
+        assert_eq!(metal.shininess, 1.);
+        assert_eq!(metal.reflective, 0.);
+
+        let mirror = material(r#"{ "pbrMetallicRoughness": { "roughnessFactor": 0 } }"#);
+        assert_eq!(mirror.reflective, 1.);
+        assert_eq!(mirror.diffuse, 0.);
+        assert_eq!(mirror.shininess, 1000.);
+
+        let glass = material(
+            r#"{
+              "pbrMetallicRoughness": { "metallicFactor": 0 },
+              "extensions": {
+                "KHR_materials_transmission": { "transmissionFactor": 0.9 },
+                "KHR_materials_ior": { "ior": 1.45 }
+              }
+            }"#,
+        );
+        assert_eq!(glass.transparency, 0.9);
+        assert_eq!(glass.refractive_index, 1.45);
+
+        let blended = material(
+            r#"{ "alphaMode": "BLEND", "pbrMetallicRoughness": { "baseColorFactor": [1, 1, 1, 0.25] } }"#,
+        );
+        assert_eq!(blended.transparency, 0.75);
+        assert_eq!(blended.refractive_index, 1.5);
+    }
+
+    #[test]
+    fn strips_fans_and_matrices() {
+        let node = |json: &str| serde_json::from_str::<NodeDef>(json).unwrap().transform();
+        assert_eq!(
+            node(r#"{ "matrix": [1,0,0,0, 0,1,0,0, 0,0,1,0, 4,5,6,1] }"#),
+            translation(4., 5., 6.)
+        );
+        assert_eq!(
+            node(r#"{ "translation": [1, 0, 0], "scale": [2, 2, 2] }"#),
+            translation(1., 0., 0.) * scaling(2., 2., 2.)
+        );
+
+        let quad = [0, 1, 3, 2];
+        assert_eq!(
+            assemble(MODE_TRIANGLE_STRIP, &quad),
+            vec![[0, 1, 3], [3, 1, 2]]
+        );
+        assert_eq!(
+            assemble(MODE_TRIANGLE_FAN, &quad),
+            vec![[0, 1, 3], [0, 3, 2]]
+        );
+        assert_eq!(assemble(MODE_TRIANGLES, &quad), vec![[0, 1, 3]]);
+        assert!(assemble(MODE_TRIANGLE_STRIP, &quad[..2]).is_empty());
+    }
+
+    #[test]
+    fn rejecting_broken_files() {
+        let error = |json: String| match GltfScene::from_bytes(json.as_bytes(), Path::new("")) {
+            Err(GltfError::Invalid(message)) => message,
+            r => panic!("expected an error, found {:?}", r),
+        };
+
+        let buffer = quad_buffer();
+        let uri = format!(
+            "\"uri\": \"data:application/octet-stream;base64,{}\", ",
+            encode_base64(&buffer)
+        );
+
+        assert_eq!(
+            error(document(&uri).replace("\"2.0\"", "\"1.0\"")),
+            "version 1.0 is not supported, only 2.x"
+        );
+        assert_eq!(
+            error(document(&uri).replace("\"count\": 6", "\"count\": 7")),
+            "accessor 1 reads past its buffer"
+        );
+        assert_eq!(
+            error(document(&uri).replace("\"count\": 6", "\"count\": 4000000000000000000")),
+            "accessor 1 reads past its buffer"
+        );
+        assert_eq!(
+            error(
+                document(&uri)
+                    .replace("\"byteOffset\": 48", "\"byteOffset\": 18446744073709551615")
+            ),
+            "accessor 1 reads past its buffer"
+        );
+        assert_eq!(
+            error(document(&uri).replace(
+                "\"bufferView\": 1, \"componentType\": 5123, \"count\": 6",
+                "\"componentType\": 5123, \"count\": 4000000000000"
+            )),
+            "accessor 1 has too many elements"
+        );
+
+        // zero filled accessors of a sensible size are fine
+        let zeros = document(&uri).replace(
+            "\"bufferView\": 1, \"componentType\": 5123, \"count\": 6",
+            "\"componentType\": 5123, \"count\": 6",
+        );
+        assert!(GltfScene::from_bytes(zeros.as_bytes(), Path::new("")).is_ok());
+        assert_eq!(
+            error(document(&uri).replace("\"children\": [1, 2]", "\"children\": [1, 1]")),
+            "node 1 appears twice in the hierarchy"
+        );
+        assert_eq!(
+            error(document(&uri).replace(
+                "\"scene\": 0,",
+                "\"scene\": 0, \"extensionsRequired\": [\"KHR_draco_mesh_compression\"],"
+            )),
+            "required extension KHR_draco_mesh_compression is not supported"
+        );
+        assert_eq!(
+            error(document(
+                "\"uri\": \"data:application/octet-stream;base64,AAAA\", "
+            )),
+            "buffer 0 holds 3 bytes but should have 60"
+        );
+    }
+}
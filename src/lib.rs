@@ -1 +1,11 @@
+pub mod diagnostic;
+pub mod gltf;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
 pub mod projectile;
+pub mod scene_file;
+pub mod scenes;
+pub mod stl;
+pub mod triangle_mesh;
+pub mod yaml;
@@ -1,3 +1,4 @@
+use rays::obj::{self, Tessellation};
 use rays::projectile;
 use rays_core::Canvas;
 
@@ -7,11 +8,23 @@ use std::path::Path;
 
 fn main() {
     let mut c = Canvas::new(900, 500, None);
-    let mut file = File::create(&Path::new("./tmp/projectile.ppm")).unwrap();
+    let mut file = File::create(Path::new("./tmp/projectile.ppm")).unwrap();
 
     projectile::simulate_projectile(&mut c);
 
     file.write_all(c.to_ppm().as_bytes()).unwrap();
 
     println!("image saved in tmp/projectile.ppm");
+
+    // the same arc as a scene, for other tools and for editing
+    let scene = projectile::trajectory_scene();
+    scene.save("./tmp/projectile.yml").unwrap();
+    let tessellation = Tessellation {
+        // the floor should reach past the whole arc
+        plane_extent: 2000.,
+        ..Tessellation::default()
+    };
+    obj::export(&scene, "./tmp/projectile.obj", &tessellation).unwrap();
+
+    println!("scene saved in tmp/projectile.yml and tmp/projectile.obj");
 }
@@ -0,0 +1,360 @@
+//! Triangle meshes loaded from model files.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use rays_core::tuples::{Normal3, Point3, Vector3};
+use rays_core::Color;
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The file is malformed or uses a feature we don't support
+    Invalid(String),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Io { path, error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            MeshError::Invalid(message) => write!(f, "invalid mesh: {}", message),
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+/// Triangles over shared vertex buffers. Vertices are stored in single
+/// precision since imported models are large and don't need more.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3<f32>>,
+    /// One per vertex, or empty when the model has none
+    pub normals: Vec<Normal3<f32>>,
+    /// One per vertex, or empty when the model has none
+    pub uvs: Vec<[f32; 2]>,
+    /// Linear vertex colors, one per vertex or empty
+    pub colors: Vec<Color<f32>>,
+    /// Indices into the vertex buffers, counter-clockwise
+    pub triangles: Vec<[u32; 3]>,
+    /// A material index for each triangle, or empty when the whole mesh
+    /// shares one material
+    pub materials: Vec<u16>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point3<f32>>, triangles: Vec<[u32; 3]>) -> Self {
+        Mesh {
+            positions,
+            triangles,
+            ..Default::default()
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// The corners of triangle `i`
+    pub fn triangle(&self, i: usize) -> [Point3<f32>; 3] {
+        let [a, b, c] = self.triangles[i];
+        [
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        ]
+    }
+
+    /// Merges vertices closer than `tolerance` whose other attributes are
+    /// equal, dropping triangles that collapse as a result. Returns how many
+    /// vertices were removed.
+    pub fn weld(&mut self, tolerance: f32) -> usize {
+        // vertices are bucketed in cells the size of the tolerance, so a
+        // match is in the same cell or a neighbouring one
+        let cell = |p: &Point3<f32>| -> [i64; 3] {
+            if tolerance > 0. {
+                [p.x, p.y, p.z].map(|v| (v / tolerance).floor() as i64)
+            } else {
+                [p.x, p.y, p.z].map(|v| v.to_bits() as i64)
+            }
+        };
+        let reach = if tolerance > 0. { 1 } else { 0 };
+
+        let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut kept: Vec<usize> = vec![];
+        let mut remap = Vec::with_capacity(self.positions.len());
+
+        for i in 0..self.positions.len() {
+            let [x, y, z] = cell(&self.positions[i]);
+            let mut found = None;
+
+            'search: for dx in -reach..=reach {
+                for dy in -reach..=reach {
+                    for dz in -reach..=reach {
+                        let candidates = grid.get(&[x + dx, y + dy, z + dz]);
+                        for &j in candidates.into_iter().flatten() {
+                            if self.same_vertex(kept[j as usize], i, tolerance) {
+                                found = Some(j);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            remap.push(found.unwrap_or_else(|| {
+                let j = kept.len() as u32;
+                kept.push(i);
+                grid.entry([x, y, z]).or_default().push(j);
+                j
+            }));
+        }
+
+        let removed = self.positions.len() - kept.len();
+        self.positions = kept.iter().map(|&i| self.positions[i]).collect();
+        if !self.normals.is_empty() {
+            self.normals = kept.iter().map(|&i| self.normals[i]).collect();
+        }
+        if !self.uvs.is_empty() {
+            self.uvs = kept.iter().map(|&i| self.uvs[i]).collect();
+        }
+        if !self.colors.is_empty() {
+            self.colors = kept.iter().map(|&i| self.colors[i]).collect();
+        }
+
+        let collapsed = |[a, b, c]: &[u32; 3]| a == b || b == c || a == c;
+        let triangles: Vec<[u32; 3]> = self
+            .triangles
+            .iter()
+            .map(|t| t.map(|v| remap[v as usize]))
+            .collect();
+
+        if !self.materials.is_empty() {
+            self.materials = triangles
+                .iter()
+                .zip(&self.materials)
+                .filter(|(t, _)| !collapsed(t))
+                .map(|(_, &m)| m)
+                .collect();
+        }
+        self.triangles = triangles.into_iter().filter(|t| !collapsed(t)).collect();
+
+        removed
+    }
+
+    fn same_vertex(&self, a: usize, b: usize, tolerance: f32) -> bool {
+        let d = self.positions[a] - self.positions[b];
+
+        d.dot(d) <= tolerance * tolerance
+            && (self.normals.is_empty() || self.normals[a] == self.normals[b])
+            && (self.uvs.is_empty() || self.uvs[a] == self.uvs[b])
+            && (self.colors.is_empty() || self.colors[a] == self.colors[b])
+    }
+
+    /// Replaces the normals with smooth ones, averaging the faces around each
+    /// vertex weighted by their area. Normals point the way `(b - a)` cross
+    /// `(c - a)` does for a triangle's corners `a`, `b` and `c`.
+    pub fn compute_normals(&mut self) {
+        let mut sums = vec![Vector3::zero(); self.positions.len()];
+
+        for i in 0..self.triangles.len() {
+            let [a, b, c] = self.triangle(i);
+            // the cross product's length is twice the area
+            let n = (b - a).cross(c - a);
+            for &v in &self.triangles[i] {
+                sums[v as usize] += n;
+            }
+        }
+
+        // vertices outside any triangle get a zero normal
+        self.normals = sums
+            .into_iter()
+            .map(|n| {
+                if n.magnitude() > 0. {
+                    n.normalize().into()
+                } else {
+                    n.into()
+                }
+            })
+            .collect();
+    }
+
+    /// Like `compute_normals`, but faces meeting at more than
+    /// `crease_angle` radians keep their own normals, so vertices along
+    /// such edges are split. Vertices outside any triangle are dropped.
+    pub fn compute_creased_normals(&mut self, crease_angle: f32) {
+        let faces: Vec<Vector3<f32>> = (0..self.triangles.len())
+            .map(|i| {
+                let [a, b, c] = self.triangle(i);
+                (b - a).cross(c - a)
+            })
+            .collect();
+        let unit = |n: Vector3<f32>| {
+            if n.magnitude() > 0. {
+                n.normalize()
+            } else {
+                n
+            }
+        };
+
+        let mut around = vec![vec![]; self.positions.len()];
+        for (f, t) in self.triangles.iter().enumerate() {
+            for &v in t {
+                around[v as usize].push(f);
+            }
+        }
+
+        // every corner gets its own vertex, smoothed over the faces around
+        // it that are close enough to its own; welding then merges the
+        // corners that ended up with the same normal
+        let min_cos = crease_angle.cos();
+        let mut split = Mesh {
+            materials: self.materials.clone(),
+            ..Default::default()
+        };
+        for (f, t) in self.triangles.iter().enumerate() {
+            let face = unit(faces[f]);
+            for &v in t {
+                let v = v as usize;
+                let sum = around[v]
+                    .iter()
+                    .filter(|&&g| unit(faces[g]).dot(face) >= min_cos)
+                    .fold(Vector3::zero(), |sum, &g| sum + faces[g]);
+
+                split.positions.push(self.positions[v]);
+                split.normals.push(unit(sum).into());
+                if !self.uvs.is_empty() {
+                    split.uvs.push(self.uvs[v]);
+                }
+                if !self.colors.is_empty() {
+                    split.colors.push(self.colors[v]);
+                }
+            }
+
+            let first = 3 * f as u32;
+            split.triangles.push([first, first + 1, first + 2]);
+        }
+
+        split.weld(0.);
+        *self = split;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::tuples::{normal, point};
+
+    // Two triangles of a unit square, written out without sharing vertices
+    fn split_square() -> Mesh {
+        let p = |x, y| point(x, y, 0_f32);
+        Mesh::new(
+            vec![
+                p(0., 0.),
+                p(1., 0.),
+                p(1., 1.),
+                p(0., 0.),
+                p(1., 1.),
+                p(0., 1.),
+            ],
+            vec![[0, 1, 2], [3, 4, 5]],
+        )
+    }
+
+    #[test]
+    fn welding_shared_vertices() {
+        let mut mesh = split_square();
+        assert_eq!(mesh.weld(0.), 2);
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+
+        // close enough, on either side of a cell boundary
+        let mut mesh = split_square();
+        mesh.positions[4] = point(1.0004, 0.9996, 0.);
+        assert_eq!(mesh.weld(1e-3), 2);
+
+        // differing attributes keep seams apart
+        let mut mesh = split_square();
+        mesh.uvs = vec![[0., 0.], [1., 0.], [1., 1.], [0., 0.], [0.5, 1.], [0., 1.]];
+        assert_eq!(mesh.weld(0.), 1);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 3, 4]]);
+        assert_eq!(mesh.uvs[3], [0.5, 1.]);
+
+        // triangles that collapse are dropped, with their materials
+        let mut mesh = split_square();
+        mesh.positions[5] = point(0., 0., 0.);
+        mesh.materials = vec![3, 7];
+        mesh.weld(0.);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.materials, vec![3]);
+    }
+
+    #[test]
+    fn computing_smooth_normals() {
+        let p = |x, y, z| point(x, y, z);
+        // a roof: two faces meeting at a ridge along x
+        let mut mesh = Mesh::new(
+            vec![
+                p(0., 0., 0.),
+                p(1., 0., 0.),
+                p(1., 1., 1.),
+                p(0., 1., 1.),
+                p(0., 0., 2.),
+                p(1., 0., 2.),
+                p(5., 5., 5.),
+            ],
+            vec![[0, 2, 1], [0, 3, 2], [3, 4, 5], [3, 5, 2]],
+        );
+        mesh.compute_normals();
+
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(mesh.normals[0], normal(0., h, -h));
+        // the ridge leans towards the side with more triangles around it
+        assert_eq!(mesh.normals[2], normal(0., 3., -1.).normalize());
+        assert_eq!(mesh.normals[5], normal(0., h, h));
+        assert_eq!(mesh.normals[6], normal(0., 0., 0.));
+    }
+
+    #[test]
+    fn splitting_normals_at_creases() {
+        let p = |x, y, z| point(x, y, z);
+        // the roof again, its ridge a right angle
+        let roof = Mesh::new(
+            vec![
+                p(0., 0., 0.),
+                p(1., 0., 0.),
+                p(1., 1., 1.),
+                p(0., 1., 1.),
+                p(0., 0., 2.),
+                p(1., 0., 2.),
+            ],
+            vec![[0, 2, 1], [0, 3, 2], [3, 4, 5], [3, 5, 2]],
+        );
+
+        let mut smooth = roof.clone();
+        smooth.compute_creased_normals(2.);
+        assert_eq!(smooth.positions.len(), 6);
+        assert_eq!(smooth.positions[1], p(1., 1., 1.));
+        assert_eq!(smooth.normals[1], normal(0., 3., -1.).normalize());
+
+        let mut sharp = roof;
+        sharp.compute_creased_normals(std::f32::consts::FRAC_PI_4);
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(sharp.positions.len(), 8);
+        assert_eq!(
+            sharp.triangles,
+            vec![[0, 1, 2], [0, 3, 1], [4, 5, 6], [4, 6, 7]]
+        );
+        assert_eq!(sharp.positions[1], sharp.positions[7]);
+        assert_eq!(sharp.normals[1], normal(0., h, -h));
+        assert_eq!(sharp.normals[7], normal(0., h, h));
+    }
+}
@@ -0,0 +1,717 @@
+//! Wavefront OBJ meshes, and scenes exported to OBJ with an MTL material
+//! library.
+//!
+//! Reading takes positions (with the common `v x y z r g b` color
+//! extension), texture coordinates, normals and polygonal faces, split into
+//! triangle fans. `usemtl` sets per-face material ids: names that are
+//! numbers are taken as the id, as `write` produces them, others are
+//! numbered in order of appearance after the highest numeric id. Groups,
+//! smoothing and everything else are skipped.
+//!
+//! Exporting tessellates every shape of a scene into world space, so the
+//! result opens in other tools; spheres and planes are approximated.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use rays_core::matrices::Matrix4x4;
+use rays_core::tuples::{normal, point, vector, Normal3, Vector3};
+use rays_core::Color;
+
+use crate::mesh::{Mesh, MeshError};
+use crate::scene_file::{Material, Object, SceneFile, Shape};
+
+fn invalid<T>(message: String) -> Result<T, MeshError> {
+    Err(MeshError::Invalid(message))
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|error| MeshError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    read(&text)
+}
+
+// A face corner's position, uv and normal indices, from 0
+type Corner = (u32, Option<u32>, Option<u32>);
+
+// A `usemtl` id as written, or the order in which a name first appeared
+#[derive(Clone, Copy)]
+enum MaterialId {
+    Number(u16),
+    Name(u16),
+}
+
+pub fn read(text: &str) -> Result<Mesh, MeshError> {
+    let mut positions = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut faces: Vec<(Vec<Corner>, Option<MaterialId>)> = vec![];
+    let mut material = None;
+    let mut material_names: HashMap<String, u16> = HashMap::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let numbers = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, MeshError> {
+            match words.map(str::parse).collect() {
+                Ok(v) => Ok(v),
+                Err(_) => invalid(format!("line {}: expected numbers", n + 1)),
+            }
+        };
+
+        match keyword {
+            "v" => match numbers(words)?[..] {
+                // the rarely used weight is dropped
+                [x, y, z] | [x, y, z, _] => positions.push(point(x, y, z)),
+                [x, y, z, r, g, b] => {
+                    positions.push(point(x, y, z));
+                    colors.push(Color::new(r, g, b));
+                }
+                _ => return invalid(format!("line {}: expected 3, 4 or 6 numbers", n + 1)),
+            },
+            "vt" => match numbers(words)?[..] {
+                [u, v, ..] => uvs.push([u, v]),
+                _ => return invalid(format!("line {}: expected 2 numbers", n + 1)),
+            },
+            "vn" => match numbers(words)?[..] {
+                [x, y, z] => normals.push(normal(x, y, z)),
+                _ => return invalid(format!("line {}: expected 3 numbers", n + 1)),
+            },
+            "f" => {
+                let counts = [positions.len(), uvs.len(), normals.len()];
+                let corners = words.map(|w| corner(w, counts)).collect::<Option<Vec<_>>>();
+                match corners {
+                    Some(corners) if corners.len() >= 3 => faces.push((corners, material)),
+                    _ => return invalid(format!("line {}: invalid face", n + 1)),
+                }
+            }
+            "usemtl" => {
+                let name = words.next().unwrap_or_default();
+                let next = material_names.len() as u16;
+                material = Some(match name.parse() {
+                    Ok(id) => MaterialId::Number(id),
+                    Err(_) => {
+                        MaterialId::Name(*material_names.entry(name.to_string()).or_insert(next))
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // vertex colors only count if every vertex has one, and the same goes
+    // for the uvs and normals of face corners
+    let corners = || faces.iter().flat_map(|(c, _)| c.iter());
+    let with_uvs = corners().all(|c| c.1.is_some());
+    let with_normals = corners().all(|c| c.2.is_some());
+    if colors.len() != positions.len() {
+        colors.clear();
+    }
+
+    // files whose corners use one index for everything, as `write` makes
+    // them, keep their vertices as they are
+    let shared = corners()
+        .all(|&(v, t, n)| (!with_uvs || t == Some(v)) && (!with_normals || n == Some(v)))
+        && (!with_uvs || uvs.len() == positions.len())
+        && (!with_normals || normals.len() == positions.len());
+
+    let mut mesh = Mesh::default();
+    let mut remap: HashMap<Corner, u32> = HashMap::new();
+    let mut vertex = |(v, t, n): Corner| -> u32 {
+        if shared {
+            return v;
+        }
+        let t = t.filter(|_| with_uvs);
+        let n = n.filter(|_| with_normals);
+        *remap.entry((v, t, n)).or_insert_with(|| {
+            mesh.positions.push(positions[v as usize]);
+            if !colors.is_empty() {
+                mesh.colors.push(colors[v as usize]);
+            }
+            if let Some(t) = t {
+                mesh.uvs.push(uvs[t as usize]);
+            }
+            if let Some(n) = n {
+                mesh.normals.push(normals[n as usize]);
+            }
+            mesh.positions.len() as u32 - 1
+        })
+    };
+
+    // named materials come after the numbered ones, so the two can't clash
+    let first_name = faces
+        .iter()
+        .filter_map(|(_, m)| match m {
+            Some(MaterialId::Number(id)) => Some(*id as usize + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    if first_name + material_names.len() > u16::MAX as usize + 1 {
+        return invalid("too many materials".into());
+    }
+
+    let with_materials = faces.iter().any(|(_, m)| m.is_some());
+    let mut triangles = vec![];
+    let mut materials = vec![];
+    for (corners, material) in &faces {
+        let first = vertex(corners[0]);
+        for pair in corners[1..].windows(2) {
+            triangles.push([first, vertex(pair[0]), vertex(pair[1])]);
+            if with_materials {
+                // faces before the first `usemtl` get material 0
+                materials.push(match material {
+                    Some(MaterialId::Number(id)) => *id,
+                    Some(MaterialId::Name(i)) => (first_name + *i as usize) as u16,
+                    None => 0,
+                });
+            }
+        }
+    }
+
+    if shared {
+        mesh.positions = positions;
+        mesh.colors = colors;
+        if with_uvs {
+            mesh.uvs = uvs;
+        }
+        if with_normals {
+            mesh.normals = normals;
+        }
+    }
+    mesh.triangles = triangles;
+    mesh.materials = materials;
+
+    Ok(mesh)
+}
+
+// Parses `v`, `v/t`, `v//n` or `v/t/n`, where negative indices count back
+// from the latest of their kind
+fn corner(word: &str, counts: [usize; 3]) -> Option<Corner> {
+    let mut parts = word.split('/');
+    let mut index = |count: usize, required: bool| -> Option<Option<u32>> {
+        match parts.next() {
+            None | Some("") if !required => Some(None),
+            Some(text) => {
+                let i: i64 = text.parse().ok()?;
+                let i = if i < 0 { count as i64 + i } else { i - 1 };
+                if (0..count as i64).contains(&i) {
+                    Some(Some(i as u32))
+                } else {
+                    None
+                }
+            }
+            None => None,
+        }
+    };
+
+    let v = index(counts[0], true)??;
+    let t = index(counts[1], false)?;
+    let n = index(counts[2], false)?;
+    Some((v, t, n))
+}
+
+/// The mesh as OBJ text, with per-face material ids as numbered `usemtl`
+/// names
+pub fn write(mesh: &Mesh) -> String {
+    let mut writer = Writer::default();
+    writer.mesh(mesh, None);
+    writer.out
+}
+
+/// How shapes without triangles of their own are turned into some
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tessellation {
+    /// Sphere slices around the axis; there are half as many from pole to
+    /// pole
+    pub sphere_segments: u32,
+    /// Planes become squares reaching this far from their origin
+    pub plane_extent: f64,
+}
+
+impl Default for Tessellation {
+    fn default() -> Self {
+        Tessellation {
+            sphere_segments: 32,
+            plane_extent: 100.,
+        }
+    }
+}
+
+/// Writes the scene's objects to an OBJ file at `path` and their materials
+/// to an MTL file beside it. OBJ files the scene refers to are read and
+/// included.
+pub fn export<P: AsRef<Path>>(
+    scene: &SceneFile,
+    path: P,
+    tessellation: &Tessellation,
+) -> Result<(), MeshError> {
+    let path = path.as_ref();
+    let library = path.with_extension("mtl");
+
+    let mut writer = Writer::default();
+    let library_name = library.file_name().unwrap_or_default().to_string_lossy();
+    writeln!(writer.out, "mtllib {}", library_name).unwrap();
+    for object in &scene.objects {
+        writer.object(object, Matrix4x4::identity(), tessellation)?;
+    }
+
+    let save = |path: &Path, text: &str| {
+        fs::write(path, text).map_err(|error| MeshError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    };
+    save(path, &writer.out)?;
+    save(&library, &mtl(&writer.materials))
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    // vertex counts so far, which the next mesh's indices start after
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+    objects: usize,
+    materials: Vec<Material>,
+}
+
+impl Writer {
+    fn object(
+        &mut self,
+        object: &Object,
+        parent: Matrix4x4,
+        tessellation: &Tessellation,
+    ) -> Result<(), MeshError> {
+        let world = parent * object.transform;
+        let mesh = match &object.shape {
+            Shape::Group(children) => {
+                for child in children {
+                    self.object(child, world, tessellation)?;
+                }
+                return Ok(());
+            }
+            Shape::Sphere => sphere(tessellation.sphere_segments),
+            Shape::Plane => plane(tessellation.plane_extent as f32),
+            Shape::Cube => cube(),
+            Shape::Obj(path) => load(path)?,
+            Shape::Mesh(mesh) => mesh.mesh().clone(),
+        };
+
+        let material = match self.materials.iter().position(|m| *m == object.material) {
+            Some(i) => i,
+            None => {
+                self.materials.push(object.material.clone());
+                self.materials.len() - 1
+            }
+        };
+
+        self.objects += 1;
+        let kind = match object.shape {
+            Shape::Sphere => "sphere",
+            Shape::Plane => "plane",
+            Shape::Cube => "cube",
+            _ => "mesh",
+        };
+        let name = format!("{}-{}", kind, self.objects);
+        let mesh = match transformed(mesh, &world) {
+            Some(mesh) => mesh,
+            None => return invalid(format!("{} has a singular transform", name)),
+        };
+        writeln!(self.out, "o {}", name).unwrap();
+        self.mesh(&mesh, Some(material));
+        Ok(())
+    }
+
+    // Appends the mesh, all in one material if one is given
+    fn mesh(&mut self, mesh: &Mesh, material: Option<usize>) {
+        let out = &mut self.out;
+
+        for (i, p) in mesh.positions.iter().enumerate() {
+            match mesh.colors.get(i) {
+                Some(c) => writeln!(out, "v {} {} {} {} {} {}", p.x, p.y, p.z, c.r, c.g, c.b),
+                None => writeln!(out, "v {} {} {}", p.x, p.y, p.z),
+            }
+            .unwrap();
+        }
+        for [u, v] in &mesh.uvs {
+            writeln!(out, "vt {} {}", u, v).unwrap();
+        }
+        for n in &mesh.normals {
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z).unwrap();
+        }
+
+        if let Some(m) = material {
+            writeln!(out, "usemtl material-{}", m).unwrap();
+        }
+
+        let mut current = None;
+        for (i, triangle) in mesh.triangles.iter().enumerate() {
+            if material.is_none() && mesh.materials.get(i) != current.as_ref() {
+                current = mesh.materials.get(i).copied();
+                writeln!(out, "usemtl {}", current.unwrap_or_default()).unwrap();
+            }
+
+            out.push('f');
+            for &v in triangle {
+                let v = v as usize;
+                let (p, t, n) = (v + self.positions, v + self.uvs, v + self.normals);
+                match (mesh.uvs.is_empty(), mesh.normals.is_empty()) {
+                    (true, true) => write!(out, " {}", p + 1),
+                    (false, true) => write!(out, " {}/{}", p + 1, t + 1),
+                    (true, false) => write!(out, " {}//{}", p + 1, n + 1),
+                    (false, false) => write!(out, " {}/{}/{}", p + 1, t + 1, n + 1),
+                }
+                .unwrap();
+            }
+            out.push('\n');
+        }
+
+        self.positions += mesh.positions.len();
+        self.uvs += mesh.uvs.len();
+        self.normals += mesh.normals.len();
+    }
+}
+
+// Phong materials in MTL terms. Reflection and refraction only switch on
+// the matching illumination models, since MTL has no strength for them.
+fn mtl(materials: &[Material]) -> String {
+    let mut out = String::new();
+
+    for (i, m) in materials.iter().enumerate() {
+        let c = |k: f64| [m.color.r * k, m.color.g * k, m.color.b * k];
+        let [ar, ag, ab] = c(m.ambient);
+        let [dr, dg, db] = c(m.diffuse);
+        let illum = if m.transparency > 0. {
+            7
+        } else if m.reflective > 0. {
+            3
+        } else {
+            2
+        };
+
+        if i > 0 {
+            out.push('\n');
+        }
+        writeln!(out, "newmtl material-{}", i).unwrap();
+        writeln!(out, "Ka {} {} {}", ar, ag, ab).unwrap();
+        writeln!(out, "Kd {} {} {}", dr, dg, db).unwrap();
+        writeln!(out, "Ks {0} {0} {0}", m.specular).unwrap();
+        writeln!(out, "Ns {}", m.shininess).unwrap();
+        writeln!(out, "d {}", 1. - m.transparency).unwrap();
+        writeln!(out, "Ni {}", m.refractive_index).unwrap();
+        writeln!(out, "illum {}", illum).unwrap();
+    }
+
+    out
+}
+
+// The mesh moved into world space, `None` if the transform flattens it
+fn transformed(mut mesh: Mesh, m: &Matrix4x4) -> Option<Mesh> {
+    for p in &mut mesh.positions {
+        *p = (*m * p.cast::<f64>()).cast();
+    }
+
+    // normals go by the inverse transpose, and mirroring turns faces inside
+    // out unless their corners are reversed
+    let normal_matrix = m.inverse()?.transpose();
+    for n in &mut mesh.normals {
+        let v = Vector3::from(normal_matrix * n.cast::<f64>());
+        *n = Normal3::from(v.normalize()).cast();
+    }
+    if m.determinant() < 0. {
+        for t in &mut mesh.triangles {
+            t.swap(1, 2);
+        }
+    }
+
+    Some(mesh)
+}
+
+// A unit sphere of rings from the top (+y) down, with uvs wrapping once
+// around it
+fn sphere(segments: u32) -> Mesh {
+    use std::f32::consts::PI;
+
+    let segments = segments.max(3);
+    let rings = (segments / 2).max(2);
+    let mut mesh = Mesh::default();
+
+    for ring in 0..=rings {
+        let phi = PI * ring as f32 / rings as f32;
+        for segment in 0..=segments {
+            let theta = 2. * PI * segment as f32 / segments as f32;
+            let (x, y, z) = (phi.sin() * theta.cos(), phi.cos(), -phi.sin() * theta.sin());
+            mesh.positions.push(point(x, y, z));
+            mesh.normals.push(normal(x, y, z));
+            mesh.uvs.push([
+                segment as f32 / segments as f32,
+                1. - ring as f32 / rings as f32,
+            ]);
+        }
+    }
+
+    let row = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = ring * row + segment;
+            let (b, c, d) = (a + 1, a + row, a + row + 1);
+            // the quads at the poles are triangles
+            if ring > 0 {
+                mesh.triangles.push([a, c, b]);
+            }
+            if ring < rings - 1 {
+                mesh.triangles.push([b, c, d]);
+            }
+        }
+    }
+
+    mesh
+}
+
+// The square from -extent to extent in x and z, facing +y
+fn plane(extent: f32) -> Mesh {
+    let e = extent;
+    let mut mesh = Mesh::new(
+        vec![
+            point(-e, 0., -e),
+            point(-e, 0., e),
+            point(e, 0., e),
+            point(e, 0., -e),
+        ],
+        vec![[0, 1, 2], [0, 2, 3]],
+    );
+    mesh.normals = vec![normal(0., 1., 0.); 4];
+    mesh.uvs = vec![[0., 0.], [0., 1.], [1., 1.], [1., 0.]];
+    mesh
+}
+
+// The cube from -1 to 1, with separate corners for each face so they stay
+// flat
+fn cube() -> Mesh {
+    let mut mesh = Mesh::default();
+    let x = vector(1_f32, 0., 0.);
+    let y = vector(0_f32, 1., 0.);
+    let z = vector(0_f32, 0., 1.);
+
+    // each face's normal and two axes across it, with u cross v = n
+    for &(n, u, v) in &[
+        (x, y, z),
+        (y, z, x),
+        (z, x, y),
+        (-x, z, y),
+        (-y, x, z),
+        (-z, y, x),
+    ] {
+        let first = mesh.positions.len() as u32;
+        for &(su, sv) in &[(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)] {
+            mesh.positions.push(point(0., 0., 0.) + n + u * su + v * sv);
+            mesh.normals.push(n.into());
+            mesh.uvs.push([(su + 1.) / 2., (sv + 1.) / 2.]);
+        }
+        mesh.triangles.push([first, first + 1, first + 2]);
+        mesh.triangles.push([first, first + 2, first + 3]);
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::transformations::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("rays-obj-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reading_polygons_and_separate_indices() {
+        let text = "\
+# a unit square and a triangle over it
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 -1
+usemtl tiles
+f 1/1/1 2/1/1 3/2/1 4/2/1
+usemtl glass
+f -4/-2/1 -3/-2/-1 -1/-2/1
+";
+        let mesh = read(text).unwrap();
+
+        // corners are shared where all their indices agree
+        assert_eq!(mesh.positions.len(), 5);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3], [0, 1, 4]]);
+        assert_eq!(mesh.positions[4], point(0., 1., 0.));
+        assert_eq!(mesh.uvs[4], [0., 0.]);
+        assert_eq!(mesh.normals, vec![normal(0., 0., -1.); 5]);
+        assert_eq!(mesh.materials, vec![0, 0, 1]);
+        assert!(mesh.colors.is_empty());
+
+        // names are numbered after the ids, and weights are ignored
+        let mesh = read(
+            &text
+                .replace("usemtl glass", "usemtl 2")
+                .replace("v 0 1 0", "v 0 1 0 0.5"),
+        )
+        .unwrap();
+        assert_eq!(mesh.materials, vec![3, 3, 2]);
+        assert_eq!(mesh.positions[4], point(0., 1., 0.));
+
+        let error = |text: &str| match read(text) {
+            Err(MeshError::Invalid(message)) => message,
+            r => panic!("expected an error, found {:?}", r),
+        };
+        assert_eq!(error("v 1 2\n"), "line 1: expected 3, 4 or 6 numbers");
+        assert_eq!(error("v 1 2 3\nf 1 2 4\n"), "line 2: invalid face");
+        assert_eq!(error("vt 0 zero\n"), "line 1: expected numbers");
+    }
+
+    #[test]
+    fn writing_and_reading_back_meshes() {
+        let mut mesh = cube();
+        mesh.colors = (0..24)
+            .map(|i| Color::new(i as f32 / 24., 0.5, 0.1))
+            .collect();
+        mesh.materials = vec![0, 0, 2, 2, 2, 5, 5, 5, 5, 5, 5, 0];
+        mesh.positions[3] = point(0.1, 1e-7, -3.3e8);
+
+        assert_eq!(read(&write(&mesh)).unwrap(), mesh);
+
+        let bare = Mesh::new(mesh.positions.clone(), mesh.triangles.clone());
+        let text = write(&bare);
+        assert!(text.contains("\nf 1 2 3\n"));
+        assert_eq!(read(&text).unwrap(), bare);
+    }
+
+    #[test]
+    fn tessellated_shapes_face_outwards() {
+        for mesh in &[sphere(12), cube(), plane(2.)] {
+            for i in 0..mesh.triangle_count() {
+                let [a, b, c] = mesh.triangle(i);
+                let face = (b - a).cross(c - a);
+                assert!(face.magnitude() > 0.);
+
+                // flat faces agree with their vertex normals; the sphere's
+                // are all outwards from its center
+                let n = Vector3::from(mesh.normals[mesh.triangles[i][0] as usize]);
+                assert!(face.dot(n) > 0.);
+            }
+        }
+
+        assert_eq!(sphere(12).triangle_count(), 12 * 6 * 2 - 2 * 12);
+        assert_eq!(cube().triangle_count(), 12);
+    }
+
+    #[test]
+    fn exporting_scenes() {
+        let dir = temp_dir("export");
+        fs::write(
+            dir.join("wedge.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let red = Material {
+            color: Color::new(1., 0., 0.),
+            reflective: 0.5,
+            ..Material::default()
+        };
+        let object = |shape, transform, material: &Material| Object {
+            shape,
+            transform,
+            material: material.clone(),
+            shadow: true,
+        };
+        let scene = SceneFile {
+            objects: vec![
+                object(Shape::Plane, Matrix4x4::identity(), &Material::default()),
+                object(
+                    Shape::Group(vec![
+                        object(Shape::Cube, scaling(-1., 1., 1.), &red),
+                        object(
+                            Shape::Obj(dir.join("wedge.obj")),
+                            translation(0., 0., 1.),
+                            &red,
+                        ),
+                    ]),
+                    translation(5., 0., 0.),
+                    &Material::default(),
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let tessellation = Tessellation {
+            plane_extent: 10.,
+            ..Default::default()
+        };
+        export(&scene, dir.join("scene.obj"), &tessellation).unwrap();
+
+        let text = fs::read_to_string(dir.join("scene.obj")).unwrap();
+        assert!(text.starts_with("mtllib scene.mtl\no plane-1\n"));
+        assert!(text.contains("\no cube-2\n"));
+        assert!(
+            text.contains("\no mesh-3\nv 5 0 1\nv 6 0 1\nv 5 1 1\nusemtl material-1\nf 29 30 31\n")
+        );
+
+        // everything is in world space, and mirroring keeps faces outwards
+        let world = read(&text).unwrap();
+        assert_eq!(world.triangle_count(), 2 + 12 + 1);
+        assert_eq!(world.positions[0], point(-10., 0., -10.));
+        for i in 2..14 {
+            let [a, b, c] = world.triangle(i);
+            let center = point(5_f32, 0., 0.);
+            assert!((b - a).cross(c - a).dot(a - center) > 0.);
+        }
+
+        let mtl = fs::read_to_string(dir.join("scene.mtl")).unwrap();
+        assert!(mtl.starts_with("newmtl material-0\nKa 0.1 0.1 0.1\nKd 0.9 0.9 0.9\n"));
+        assert!(mtl.contains("\nnewmtl material-1\nKa 0.1 0 0\nKd 0.9 0 0\nKs 0.9 0.9 0.9\nNs 200\nd 1\nNi 1\nillum 3\n"));
+
+        let missing = SceneFile {
+            objects: vec![object(
+                Shape::Obj(dir.join("missing.obj")),
+                Matrix4x4::identity(),
+                &red,
+            )],
+            ..Default::default()
+        };
+        assert!(matches!(
+            export(&missing, dir.join("missing-scene.obj"), &tessellation),
+            Err(MeshError::Io { .. })
+        ));
+
+        let flat = SceneFile {
+            objects: vec![object(Shape::Cube, scaling(1., 0., 1.), &red)],
+            ..Default::default()
+        };
+        assert_eq!(
+            export(&flat, dir.join("flat.obj"), &tessellation)
+                .unwrap_err()
+                .to_string(),
+            "invalid mesh: cube-1 has a singular transform"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,530 @@
+//! Stanford PLY meshes, ASCII or binary in either byte order.
+//!
+//! Vertex positions, normals (`nx`, `ny`, `nz`), texture coordinates
+//! (`u`/`v`, `s`/`t` or `texture_u`/`texture_v`) and colors (`red`,
+//! `green`, `blue`, taken to be sRGB) are read; polygonal faces are split
+//! into triangle fans. Other elements and properties are skipped. Meshes
+//! without normals get smooth ones.
+
+use std::fs;
+use std::path::Path;
+
+use rays_core::tuples::{normal, point};
+use rays_core::Color;
+
+use crate::mesh::{Mesh, MeshError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // The largest value of integer types, which colors are fractions of
+    fn full_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.,
+            Scalar::U16 => 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Scalar,
+    /// The type of the count, for list properties
+    list: Option<Scalar>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid<T>(message: String) -> Result<T, MeshError> {
+    Err(MeshError::Invalid(message))
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|error| MeshError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    read(&bytes)
+}
+
+pub fn read(bytes: &[u8]) -> Result<Mesh, MeshError> {
+    let (format, elements, body) = header(bytes)?;
+
+    let mut values = match format {
+        Format::Ascii => Values::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| MeshError::Invalid("ASCII data is not valid text".into()))?
+                .split_ascii_whitespace(),
+        ),
+        _ => Values::Binary {
+            data: body,
+            at: 0,
+            big_endian: format == Format::BigEndian,
+        },
+    };
+
+    let mut mesh = Mesh::default();
+    let mut has_vertices = false;
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                has_vertices = true;
+                vertices(element, &mut values, &mut mesh)?;
+            }
+            "face" => faces(element, &mut values, &mut mesh)?,
+            _ => {
+                let mut skipped = vec![];
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        values.property(element, property, &mut skipped)?;
+                    }
+                }
+            }
+        }
+    }
+
+    if !has_vertices {
+        return invalid("no vertex element".into());
+    }
+    if let Some(&i) = mesh
+        .triangles
+        .iter()
+        .flatten()
+        .find(|&&i| i as usize >= mesh.positions.len())
+    {
+        return invalid(format!(
+            "face refers to vertex {} of {}",
+            i,
+            mesh.positions.len()
+        ));
+    }
+
+    if mesh.normals.is_empty() {
+        mesh.compute_normals();
+    }
+
+    Ok(mesh)
+}
+
+// The format, the elements and where their data starts
+fn header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), MeshError> {
+    if !bytes.starts_with(b"ply") {
+        return invalid("not a PLY file".into());
+    }
+
+    let end = match bytes.windows(10).position(|w| w == b"end_header") {
+        Some(end) => end,
+        None => return invalid("header has no end_header".into()),
+    };
+    // the data starts after the end_header line break
+    let body = match bytes[end..].iter().position(|&b| b == b'\n') {
+        Some(newline) => &bytes[end + newline + 1..],
+        None => &[],
+    };
+
+    let text = String::from_utf8_lossy(&bytes[..end]);
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+
+    for (n, line) in text.lines().enumerate().skip(1) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad = || {
+            invalid(format!(
+                "header line {}: can't read `{}`",
+                n + 1,
+                line.trim()
+            ))
+        };
+
+        match words.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", kind, "1.0"] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return bad(),
+                })
+            }
+            ["element", name, count] => match count.parse() {
+                Ok(count) => elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                }),
+                Err(_) => return bad(),
+            },
+            ["property", "list", count, kind, name] => {
+                match (
+                    elements.last_mut(),
+                    Scalar::parse(count),
+                    Scalar::parse(kind),
+                ) {
+                    (Some(element), Some(count), Some(kind)) => element.properties.push(Property {
+                        name: name.to_string(),
+                        kind,
+                        list: Some(count),
+                    }),
+                    _ => return bad(),
+                }
+            }
+            ["property", kind, name] => match (elements.last_mut(), Scalar::parse(kind)) {
+                (Some(element), Some(kind)) => element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                    list: None,
+                }),
+                _ => return bad(),
+            },
+            _ => return bad(),
+        }
+    }
+
+    match format {
+        Some(format) => Ok((format, elements, body)),
+        None => invalid("header has no format line".into()),
+    }
+}
+
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        at: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Values<'a> {
+    fn next(&mut self, kind: Scalar, element: &Element) -> Result<f64, MeshError> {
+        let truncated = || invalid(format!("data ends inside the {} element", element.name));
+
+        match self {
+            Values::Ascii(words) => match words.next().map(str::parse::<f64>) {
+                Some(Ok(v)) => Ok(v),
+                Some(Err(_)) => {
+                    invalid(format!("non-numeric value in the {} element", element.name))
+                }
+                None => truncated(),
+            },
+            Values::Binary {
+                data,
+                at,
+                big_endian,
+            } => {
+                let size = kind.size();
+                let mut b = [0; 8];
+                match data.get(*at..*at + size) {
+                    Some(bytes) => b[..size].copy_from_slice(bytes),
+                    None => return truncated(),
+                }
+                *at += size;
+                if *big_endian {
+                    b[..size].reverse();
+                }
+
+                Ok(match kind {
+                    Scalar::I8 => b[0] as i8 as f64,
+                    Scalar::U8 => b[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Scalar::F64 => {
+                        f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+                    }
+                })
+            }
+        }
+    }
+
+    // One property of an element instance into `out`, which is reused so
+    // lists don't allocate each time; scalars are single values
+    fn property(
+        &mut self,
+        element: &Element,
+        property: &Property,
+        out: &mut Vec<f64>,
+    ) -> Result<(), MeshError> {
+        let count = match property.list {
+            Some(count) => self.next(count, element)? as usize,
+            None => 1,
+        };
+
+        out.clear();
+        for _ in 0..count {
+            out.push(self.next(property.kind, element)?);
+        }
+        Ok(())
+    }
+}
+
+fn vertices(element: &Element, values: &mut Values, mesh: &mut Mesh) -> Result<(), MeshError> {
+    let slot = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    };
+    let position = [slot(&["x"]), slot(&["y"]), slot(&["z"])];
+    let normal_slots = [slot(&["nx"]), slot(&["ny"]), slot(&["nz"])];
+    let uv_slots = [
+        slot(&["u", "s", "texture_u", "texture_s"]),
+        slot(&["v", "t", "texture_v", "texture_t"]),
+    ];
+    let color_slots = [slot(&["red"]), slot(&["green"]), slot(&["blue"])];
+
+    let [x, y, z] = match position {
+        [Some(x), Some(y), Some(z)] => [x, y, z],
+        _ => return invalid("vertices have no x, y and z".into()),
+    };
+    let normals = match normal_slots {
+        [Some(x), Some(y), Some(z)] => Some([x, y, z]),
+        _ => None,
+    };
+    let uvs = match uv_slots {
+        [Some(u), Some(v)] => Some([u, v]),
+        _ => None,
+    };
+    let colors = match color_slots {
+        [Some(r), Some(g), Some(b)] => Some([r, g, b]),
+        _ => None,
+    };
+
+    let mut row = vec![0_f64; element.properties.len()];
+    let mut list = vec![];
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            row[i] = match property.list {
+                None => values.next(property.kind, element)?,
+                // lists are unusual on vertices; their first value stands in
+                Some(_) => {
+                    values.property(element, property, &mut list)?;
+                    list.first().copied().unwrap_or(0.)
+                }
+            };
+        }
+
+        let f = |i: usize| row[i] as f32;
+        mesh.positions.push(point(f(x), f(y), f(z)));
+        if let Some([x, y, z]) = normals {
+            mesh.normals.push(normal(f(x), f(y), f(z)));
+        }
+        if let Some([u, v]) = uvs {
+            mesh.uvs.push([f(u), f(v)]);
+        }
+        if let Some(channels) = colors {
+            let [r, g, b] =
+                channels.map(|i| (row[i] / element.properties[i].kind.full_scale()) as f32);
+            mesh.colors.push(Color::new(r, g, b).to_linear());
+        }
+    }
+
+    Ok(())
+}
+
+fn faces(element: &Element, values: &mut Values, mesh: &mut Mesh) -> Result<(), MeshError> {
+    let indices = element
+        .properties
+        .iter()
+        .position(|p| p.list.is_some() && (p.name == "vertex_indices" || p.name == "vertex_index"));
+    let indices = match indices {
+        Some(i) => i,
+        None => return invalid("faces have no vertex_indices list".into()),
+    };
+
+    let mut list = vec![];
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            values.property(element, property, &mut list)?;
+            if i != indices {
+                continue;
+            }
+
+            let corner = |k: usize| -> Result<u32, MeshError> {
+                match list[k] {
+                    v if v >= 0. && v <= u32::MAX as f64 => Ok(v as u32),
+                    v => invalid(format!("face refers to vertex {}", v)),
+                }
+            };
+            for k in 1..list.len().saturating_sub(1) {
+                mesh.triangles
+                    .push([corner(0)?, corner(k)?, corner(k + 1)?]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::tuples::Normal3;
+
+    const ASCII: &str = "\
+ply
+format ascii 1.0
+comment a square with one color per corner
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+0 2
+";
+
+    // The same square in binary, with normals instead of colors
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let order = if big_endian { "big" } else { "little" };
+        let mut out = format!(
+            "ply\nformat binary_{}_endian 1.0\n\
+             element vertex 4\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\n\
+             property double u\nproperty double v\n\
+             element face 1\n\
+             property uchar flags\n\
+             property list uchar uint vertex_indices\n\
+             end_header\n",
+            order
+        )
+        .into_bytes();
+
+        let mut put = |bytes: &[u8]| {
+            let mut bytes = bytes.to_vec();
+            if big_endian {
+                bytes.reverse();
+            }
+            out.extend_from_slice(&bytes);
+        };
+        for (x, y) in [(0_f64, 0_f64), (1., 0.), (1., 1.), (0., 1.)] {
+            for v in [x, y, 0., 0., 0., -1.] {
+                put(&(v as f32).to_le_bytes());
+            }
+            put(&x.to_le_bytes());
+            put(&y.to_le_bytes());
+        }
+        put(&[7]);
+        put(&[4]);
+        for i in [0_u32, 1, 2, 3] {
+            put(&i.to_le_bytes());
+        }
+
+        out
+    }
+
+    #[test]
+    fn reading_ascii_files_with_colors() {
+        let mesh = read(ASCII.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors[0], Color::new(1., 0., 0.));
+        assert_eq!(mesh.colors[3], Color::new(1., 1., 1.));
+        // no normals in the file, so they're computed
+        assert_eq!(mesh.normals, vec![normal(0., 0., 1.); 4]);
+        assert!(mesh.uvs.is_empty());
+    }
+
+    #[test]
+    fn reading_binary_files_in_either_byte_order() {
+        for big_endian in [false, true] {
+            let mesh = read(&binary(big_endian)).unwrap();
+
+            assert_eq!(mesh.positions[2], point(1., 1., 0.));
+            assert_eq!(mesh.normals, vec![Normal3::new(0., 0., -1.); 4]);
+            assert_eq!(mesh.uvs[1], [1., 0.]);
+            assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+            assert!(mesh.colors.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejecting_broken_files() {
+        let error = |bytes: &[u8]| match read(bytes) {
+            Err(MeshError::Invalid(message)) => message,
+            r => panic!("expected an error, found {:?}", r),
+        };
+
+        assert_eq!(error(b"solid cube\n"), "not a PLY file");
+        assert_eq!(
+            error(ASCII.replace("uchar red", "colour red").as_bytes()),
+            "header line 8: can't read `property colour red`"
+        );
+        assert_eq!(
+            error(ASCII.replace("4 0 1 2 3", "4 0 1 2 9").as_bytes()),
+            "face refers to vertex 9 of 4"
+        );
+        assert_eq!(
+            error(ASCII.replace("\n0 2\n", "\n0\n").as_bytes()),
+            "data ends inside the edge element"
+        );
+
+        let mut truncated = binary(false);
+        truncated.truncate(truncated.len() - 2);
+        assert_eq!(error(&truncated), "data ends inside the face element");
+    }
+}
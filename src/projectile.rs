@@ -1,7 +1,13 @@
+use std::f64::consts::PI;
+
 use rays_core::canvas::*;
 use rays_core::color::*;
+use rays_core::matrices::Matrix4x4;
+use rays_core::transformations::{scaling, translation};
 use rays_core::tuples::{point, vector, Point, Vector};
 
+use crate::scene_file::{Camera, Light, Material, Object, SceneFile, Shape};
+
 struct Projectile {
     pos: Point,
     vel: Vector,
@@ -12,7 +18,8 @@ struct Env {
     wind: Vector,
 }
 
-pub fn simulate_projectile(canvas: &mut Canvas) {
+/// Where the projectile is at each tick until it lands
+pub fn trajectory() -> Vec<Point> {
     let mut p = Projectile {
         pos: point(0., 1., 0.),
         vel: vector(1., 1.8, 0.).normalize() * 11.25,
@@ -23,12 +30,78 @@ pub fn simulate_projectile(canvas: &mut Canvas) {
         wind: vector(-0.01, 0., 0.),
     };
 
-    let color = Color::new(1., 0., 0.);
-
+    let mut positions = Vec::new();
     while p.pos.y >= 0. {
-        canvas.write_pixel(p.pos.x.floor() as u32, p.pos.y.floor() as u32, color);
+        positions.push(p.pos);
         p = tick(&env, p);
     }
+
+    positions
+}
+
+pub fn simulate_projectile(canvas: &mut Canvas) {
+    let color = Color::new(1., 0., 0.);
+    // canvas rows grow downwards, so flip y to plot the arc upright
+    let trajectory: Vec<(f64, f64)> = trajectory()
+        .iter()
+        .map(|p| (p.x, canvas.height as f64 - 1. - p.y))
+        .collect();
+
+    for segment in trajectory.windows(2) {
+        let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+        canvas.draw_line_aa(x0, y0, x1, y1, color);
+    }
+
+    canvas.draw_text(10, 10, "projectile", 2, Color::new(1., 1., 1.));
+}
+
+/// The trajectory as a scene: a red ball at every tick over a floor, seen
+/// side on like the plot
+pub fn trajectory_scene() -> SceneFile {
+    let positions = trajectory();
+    let (width, height) = positions
+        .iter()
+        .fold((0_f64, 0_f64), |(w, h), p| (w.max(p.x), h.max(p.y)));
+    let center = point(width / 2., height / 2., 0.);
+
+    let ball = Material {
+        color: Color::new(1., 0., 0.),
+        specular: 0.3,
+        ..Material::default()
+    };
+    let mut objects = vec![Object {
+        shape: Shape::Plane,
+        transform: Matrix4x4::identity(),
+        material: Material {
+            color: Color::new(0.5, 0.5, 0.5),
+            specular: 0.,
+            ..Material::default()
+        },
+        shadow: true,
+    }];
+    objects.extend(positions.iter().map(|p| Object {
+        shape: Shape::Sphere,
+        transform: translation(p.x, p.y, p.z) * scaling(2., 2., 2.),
+        material: ball.clone(),
+        shadow: true,
+    }));
+
+    SceneFile {
+        camera: Some(Camera {
+            width: 900,
+            height: 500,
+            field_of_view: PI / 3.,
+            // far enough back for the whole arc to fit the view
+            from: center + vector(0., 0., -width),
+            to: center,
+            up: vector(0., 1., 0.),
+        }),
+        lights: vec![Light {
+            position: point(width / 2., height * 3., -width),
+            intensity: Color::new(1., 1., 1.),
+        }],
+        objects,
+    }
 }
 
 fn tick(env: &Env, p: Projectile) -> Projectile {
@@ -38,3 +111,33 @@ fn tick(env: &Env, p: Projectile) -> Projectile {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_trajectory_scene_has_a_ball_per_tick() {
+        let positions = trajectory();
+        assert_eq!(positions[0], point(0., 1., 0.));
+        assert!(positions.iter().all(|p| p.y >= 0.));
+
+        let scene = trajectory_scene();
+        assert_eq!(scene.objects.len(), positions.len() + 1);
+        assert_eq!(
+            scene.objects[1].transform,
+            translation(0., 1., 0.) * scaling(2., 2., 2.)
+        );
+
+        // the camera faces the middle of the arc from the front
+        let camera = scene.camera.unwrap();
+        assert_eq!(camera.to.z, 0.);
+        assert!(camera.from.z < 0.);
+    }
+
+    #[test]
+    fn plotting_on_an_empty_canvas() {
+        let mut canvas = Canvas::new(900, 0, None);
+        simulate_projectile(&mut canvas);
+        assert_eq!(canvas.height, 0);
+    }
+}
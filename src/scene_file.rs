@@ -0,0 +1,1524 @@
+//! Scene files in the YAML format of The Ray Tracer Challenge.
+//!
+//! A file is a list of items. `add:` items create the camera, lights and
+//! shapes (`sphere`, `plane`, `cube`, `group` with `children:`, and `obj`
+//! or `mesh` with a `file:`), and `define:` items name a material, a transform list
+//! or a whole shape for later use. A define can `extend:` an earlier one,
+//! which merges materials and appends transform lists.
+//!
+//! ```yaml
+//! - define: white-material
+//!   value: { color: [1, 1, 1], diffuse: 0.7 }
+//! - add: sphere
+//!   material: white-material
+//!   transform:
+//!     - [scale, 0.5, 0.5, 0.5]
+//!     - [rotate-y, 0.5]
+//! ```
+//!
+//! Transform lists apply in the order they are written; besides the book's
+//! steps, `[matrix, ...]` applies a whole matrix given as 16 numbers row by
+//! row. Names must be defined before they are used.
+//!
+//! Loading doesn't stop at the first mistake: every problem in the file is
+//! collected and reported together, each pointing at its line and column.
+//!
+//! Scenes can be saved back to this format, so ones built in code can be
+//! edited by hand and loaded again unchanged.
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rays_core::matrices::Matrix4x4;
+use rays_core::transformations::{look_at, Transform};
+use rays_core::tuples::{point, vector, Point3, Vector3};
+use rays_core::Color;
+
+use crate::diagnostic::{suggestion, Diagnostic};
+use crate::obj;
+use crate::triangle_mesh::TriangleMesh;
+use crate::yaml::{self, Node, Value};
+
+// Transform lists that name other transform lists can't loop, since names
+// must be defined before use, but they could nest very deeply
+const MAX_NESTING: usize = 32;
+
+const SHAPES: [&str; 6] = ["sphere", "plane", "cube", "group", "obj", "mesh"];
+const SHAPE_KEYS: [&str; 4] = ["add", "material", "transform", "shadow"];
+const MATERIAL_KEYS: [&str; 8] = [
+    "color",
+    "ambient",
+    "diffuse",
+    "specular",
+    "shininess",
+    "reflective",
+    "transparency",
+    "refractive-index",
+];
+const TRANSFORMATIONS: [&str; 7] = [
+    "translate",
+    "scale",
+    "rotate-x",
+    "rotate-y",
+    "rotate-z",
+    "shear",
+    "matrix",
+];
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Saving the scene, or one of its meshes, failed
+    Write {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// Everything wrong with the file, in source order
+    Invalid {
+        path: Option<PathBuf>,
+        src: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            SceneError::Write { path, error } => {
+                write!(f, "could not write {}: {}", path.display(), error)
+            }
+            SceneError::Invalid {
+                path,
+                src,
+                diagnostics,
+            } => {
+                let name = match path {
+                    Some(path) => path.display().to_string(),
+                    None => "<scene>".to_string(),
+                };
+
+                for (i, d) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f, "\n")?;
+                    }
+                    write!(f, "{}", d.render(src, &name))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+fn invalid<T>(node: &Node, message: String) -> Result<T, Diagnostic> {
+    Err(Diagnostic::new(node.span, message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub width: u32,
+    pub height: u32,
+    /// Horizontal or vertical, whichever is larger, in radians
+    pub field_of_view: f64,
+    pub from: Point3,
+    pub to: Point3,
+    pub up: Vector3,
+}
+
+impl Camera {
+    /// The view transformation
+    pub fn transform(&self) -> Matrix4x4 {
+        look_at(self.from, self.to, self.up)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    pub position: Point3,
+    pub intensity: Color,
+}
+
+/// Phong material; the defaults are the book's
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub color: Color,
+    pub ambient: f64,
+    pub diffuse: f64,
+    pub specular: f64,
+    pub shininess: f64,
+    pub reflective: f64,
+    pub transparency: f64,
+    pub refractive_index: f64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            color: Color::new(1., 1., 1.),
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.9,
+            shininess: 200.,
+            reflective: 0.,
+            transparency: 0.,
+            refractive_index: 1.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Sphere,
+    Plane,
+    Cube,
+    Group(Vec<Object>),
+    /// A Wavefront OBJ mesh; relative paths are resolved against the scene
+    /// file's directory
+    Obj(PathBuf),
+    /// A mesh already in memory, shared by every object using it. Scene
+    /// files load `mesh` items as these, reading the OBJ file right away
+    Mesh(Arc<TriangleMesh>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub shape: Shape,
+    pub transform: Matrix4x4,
+    pub material: Material,
+    pub shadow: bool,
+}
+
+/// Everything a scene file describes
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SceneFile {
+    pub camera: Option<Camera>,
+    pub lights: Vec<Light>,
+    pub objects: Vec<Object>,
+}
+
+impl SceneFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneFile, SceneError> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path).map_err(|error| SceneError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        Self::parse_in(
+            src,
+            path.parent().unwrap_or_else(|| Path::new("")),
+            Some(path),
+        )
+    }
+
+    /// Parses a scene, resolving file references against the working
+    /// directory
+    pub fn parse(src: &str) -> Result<SceneFile, SceneError> {
+        Self::parse_in(src.to_string(), Path::new(""), None)
+    }
+
+    /// Writes the scene to `path`. Transforms are saved as whole matrices,
+    /// and meshes held in memory as OBJ files beside the scene, named after
+    /// it, which load back as meshes shared the same way.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let mut saver = Saver {
+            dir: path.parent().unwrap_or_else(|| Path::new("")),
+            stem: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            out: String::new(),
+            meshes: vec![],
+        };
+
+        saver.scene(self)?;
+        save_file(path, &saver.out)
+    }
+
+    fn parse_in(
+        src: String,
+        base_dir: &Path,
+        path: Option<&Path>,
+    ) -> Result<SceneFile, SceneError> {
+        let mut loader = Loader {
+            base_dir,
+            defines: HashMap::new(),
+            expanding: vec![],
+            meshes: HashMap::new(),
+            scene: SceneFile::default(),
+            diagnostics: vec![],
+        };
+
+        match yaml::parse(&src) {
+            Ok(doc) => match &doc.value {
+                Value::Null => {}
+                Value::Seq(items) => {
+                    for item in items {
+                        loader.item(item);
+                    }
+                }
+                _ => {
+                    loader.error(&doc, "expected a list of `add` and `define` items".into());
+                }
+            },
+            Err(e) => loader.diagnostics.push(Diagnostic::new(e.span, e.message)),
+        }
+
+        if loader.diagnostics.is_empty() {
+            return Ok(loader.scene);
+        }
+
+        // shapes are checked again wherever a define is used, so the same
+        // problem can come up more than once
+        let mut diagnostics = loader.diagnostics;
+        diagnostics.sort_by_key(|d| d.span.start);
+        diagnostics.dedup();
+
+        Err(SceneError::Invalid {
+            path: path.map(Path::to_path_buf),
+            src,
+            diagnostics,
+        })
+    }
+}
+
+struct Loader<'a> {
+    base_dir: &'a Path,
+    defines: HashMap<String, Node>,
+    // Shape defines being expanded. They are looked up when used, so a
+    // define can name itself, directly or through a redefinition
+    expanding: Vec<String>,
+    // `mesh` files already read, so objects using the same one share it
+    meshes: HashMap<PathBuf, Arc<TriangleMesh>>,
+    scene: SceneFile,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Loader<'a> {
+    /// Records the error, if any, so loading can carry on
+    fn report<T>(&mut self, result: Result<T, Diagnostic>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(d) => {
+                self.diagnostics.push(d);
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, node: &Node, message: String) {
+        self.diagnostics.push(Diagnostic::new(node.span, message));
+    }
+
+    fn item(&mut self, item: &Node) {
+        if item.get("define").is_some() {
+            return self.define(item);
+        }
+
+        if item.get("add").is_none() {
+            return self.error(item, "expected an `add` or `define` item".into());
+        }
+
+        match item.get("add").and_then(Node::as_str) {
+            Some("camera") => {
+                if let Some(camera) = self.camera(item) {
+                    self.scene.camera = Some(camera);
+                }
+            }
+            Some("light") => {
+                if let Some(light) = self.light(item) {
+                    self.scene.lights.push(light);
+                }
+            }
+            _ => {
+                if let Some(object) = self.object(item, &Material::default()) {
+                    self.scene.objects.push(object);
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, item: &Node) {
+        self.check_keys(item, &["define", "extend", "value"]);
+        let name = self.report(field(item, "define").and_then(scalar));
+        let value = self.report(field(item, "value").cloned());
+        let (name, mut value) = match (name, value) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return,
+        };
+
+        // an unusable `extend` is reported, and the define kept without it
+        // so its uses don't raise more errors
+        if let Some(extend) = item.get("extend") {
+            if let Some(base) = self.lookup(extend) {
+                let base_kind = base.kind();
+                match (base.value, &mut value.value) {
+                    (Value::Map(base_entries), Value::Map(entries)) => {
+                        let mut merged: Vec<(Node, Node)> = base_entries
+                            .into_iter()
+                            .filter(|(k, _)| !entries.iter().any(|(e, _)| e.value == k.value))
+                            .collect();
+                        merged.append(entries);
+                        *entries = merged;
+                    }
+                    (Value::Seq(mut base_items), Value::Seq(items)) => {
+                        base_items.append(items);
+                        *items = base_items;
+                    }
+                    _ => {
+                        self.error(
+                            extend,
+                            format!(
+                                "`{}` is {} and can't be extended by {}",
+                                extend.as_str().unwrap_or_default(),
+                                base_kind,
+                                value.kind()
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        self.defines.insert(name.to_string(), value);
+    }
+
+    fn lookup(&mut self, name: &Node) -> Option<Node> {
+        let key = self.report(scalar(name))?;
+
+        match self.defines.get(key) {
+            Some(value) => Some(value.clone()),
+            None => {
+                let help = suggestion(key, self.defines.keys().map(String::as_str));
+                let mut d = Diagnostic::new(name.span, format!("`{}` is not defined", key));
+                if let Some(help) = help {
+                    d = d.with_help(help);
+                }
+                self.diagnostics.push(d);
+                None
+            }
+        }
+    }
+
+    // Reports keys outside `allowed`, which catches typos that would
+    // otherwise be silently ignored
+    fn check_keys(&mut self, item: &Node, allowed: &[&str]) {
+        for (key, _) in item.as_map().unwrap_or(&[]) {
+            if let Some(name) = self.report(scalar(key)) {
+                if !allowed.contains(&name) {
+                    let mut d = Diagnostic::new(key.span, format!("unknown key `{}`", name));
+                    if let Some(help) = suggestion(name, allowed.iter().copied()) {
+                        d = d.with_help(help);
+                    }
+                    self.diagnostics.push(d);
+                }
+            }
+        }
+    }
+
+    fn mesh(&mut self, file: &Node, path: PathBuf) -> Option<Arc<TriangleMesh>> {
+        if let Some(mesh) = self.meshes.get(&path) {
+            return Some(mesh.clone());
+        }
+
+        let mesh = match obj::load(&path) {
+            Ok(mesh) => Arc::new(TriangleMesh::new(mesh)),
+            Err(e) => return self.report(invalid(file, e.to_string())),
+        };
+        self.meshes.insert(path, mesh.clone());
+        Some(mesh)
+    }
+
+    fn camera(&mut self, item: &Node) -> Option<Camera> {
+        self.check_keys(
+            item,
+            &[
+                "add",
+                "width",
+                "height",
+                "field-of-view",
+                "from",
+                "to",
+                "up",
+            ],
+        );
+
+        let width = self.report(field(item, "width").and_then(integer));
+        let height = self.report(field(item, "height").and_then(integer));
+        let field_of_view = self.report(field(item, "field-of-view").and_then(number));
+        let from = self.report(field(item, "from").and_then(to_point));
+        let to = self.report(field(item, "to").and_then(to_point));
+        let up = self.report(field(item, "up").and_then(to_vector));
+
+        Some(Camera {
+            width: width?,
+            height: height?,
+            field_of_view: field_of_view?,
+            from: from?,
+            to: to?,
+            up: up?,
+        })
+    }
+
+    fn light(&mut self, item: &Node) -> Option<Light> {
+        self.check_keys(item, &["add", "at", "intensity"]);
+
+        let position = self.report(field(item, "at").and_then(to_point));
+        let intensity = self.report(field(item, "intensity").and_then(color));
+
+        Some(Light {
+            position: position?,
+            intensity: intensity?,
+        })
+    }
+
+    fn object(&mut self, item: &Node, inherited: &Material) -> Option<Object> {
+        let add = self.report(field(item, "add"))?;
+        let name = self.report(scalar(add))?;
+
+        // a defined shape: its settings, overridden by the item's, with the
+        // item's transform applied after the definition's
+        if let Some(definition) = self.defines.get(name).cloned() {
+            if definition.get("add").is_none() {
+                return self.report(invalid(add, format!("`{}` is not a shape", name)));
+            }
+            if self.expanding.iter().any(|n| n == name) {
+                return self.report(invalid(add, format!("`{}` refers to itself", name)));
+            }
+
+            self.check_keys(item, &SHAPE_KEYS);
+            self.expanding.push(name.to_string());
+            let object = self.object(&definition, inherited);
+            self.expanding.pop();
+            let mut object = object?;
+
+            if let Some(material) = item.get("material") {
+                object.material = self.material(material, &object.material)?;
+            }
+            if let Some(transform) = item.get("transform") {
+                object.transform = self.transform(transform, object.transform, 0)?;
+                self.check_invertible(transform, &object.transform);
+            }
+            if let Some(shadow) = item.get("shadow") {
+                object.shadow = self.report(boolean(shadow))?;
+            }
+
+            return Some(object);
+        }
+
+        let material = match item.get("material") {
+            Some(m) => self.material(m, inherited),
+            None => Some(inherited.clone()),
+        };
+
+        let shape = match name {
+            "sphere" => Some(Shape::Sphere),
+            "plane" => Some(Shape::Plane),
+            "cube" => Some(Shape::Cube),
+            "group" => {
+                self.check_keys(
+                    item,
+                    &["add", "material", "transform", "shadow", "children"],
+                );
+
+                // children take the group's material unless they set their
+                // own; all of them are checked even if some are broken
+                let group_material = material.clone().unwrap_or_default();
+                let mut children = vec![];
+                let mut valid = true;
+                if let Some(list) = item.get("children") {
+                    for child in self.report(seq(list)).unwrap_or_else(|| {
+                        valid = false;
+                        &[]
+                    }) {
+                        match self.object(child, &group_material) {
+                            Some(child) => children.push(child),
+                            None => valid = false,
+                        }
+                    }
+                }
+
+                if valid {
+                    Some(Shape::Group(children))
+                } else {
+                    None
+                }
+            }
+            "obj" => {
+                self.check_keys(item, &["add", "material", "transform", "shadow", "file"]);
+                self.report(field(item, "file").and_then(scalar))
+                    .map(|file| Shape::Obj(self.base_dir.join(file)))
+            }
+            "mesh" => {
+                self.check_keys(item, &["add", "material", "transform", "shadow", "file"]);
+                let file = self.report(field(item, "file"))?;
+                let path = self.base_dir.join(self.report(scalar(file))?);
+                self.mesh(file, path).map(Shape::Mesh)
+            }
+            other => {
+                let mut d = Diagnostic::new(add.span, format!("unknown item `{}`", other));
+                let shape_defines = self
+                    .defines
+                    .iter()
+                    .filter(|(_, v)| v.get("add").is_some())
+                    .map(|(k, _)| k.as_str());
+                let candidates = ["camera", "light"]
+                    .iter()
+                    .chain(SHAPES.iter())
+                    .copied()
+                    .chain(shape_defines);
+                if let Some(help) = suggestion(other, candidates) {
+                    d = d.with_help(help);
+                }
+                self.diagnostics.push(d);
+                return None;
+            }
+        };
+
+        if let Some(Shape::Sphere | Shape::Plane | Shape::Cube) = shape {
+            self.check_keys(item, &SHAPE_KEYS);
+        }
+
+        let transform = match item.get("transform") {
+            Some(t) => {
+                let transform = self.transform(t, Matrix4x4::identity(), 0);
+                if let Some(m) = &transform {
+                    self.check_invertible(t, m);
+                }
+                transform
+            }
+            None => Some(Matrix4x4::identity()),
+        };
+
+        let shadow = match item.get("shadow") {
+            Some(s) => self.report(boolean(s)),
+            None => Some(true),
+        };
+
+        Some(Object {
+            shape: shape?,
+            transform: transform?,
+            material: material?,
+            shadow: shadow?,
+        })
+    }
+
+    // Rendering needs the inverse of every object's transform
+    fn check_invertible(&mut self, node: &Node, m: &Matrix4x4) {
+        if !m.is_invertible() {
+            let d = Diagnostic::new(node.span, "this transform can't be inverted".into())
+                .with_help("a scale of 0 flattens the shape; use a small value instead".into());
+            self.diagnostics.push(d);
+        }
+    }
+
+    fn material(&mut self, node: &Node, base: &Material) -> Option<Material> {
+        let node = match node.value {
+            Value::Scalar(_) => self.lookup(node)?,
+            _ => node.clone(),
+        };
+
+        let entries = match node.as_map() {
+            Some(entries) => entries,
+            None => {
+                return self.report(invalid(
+                    &node,
+                    format!("expected a material, found {}", node.kind()),
+                ))
+            }
+        };
+
+        let mut m = base.clone();
+        let mut valid = true;
+        for (key, value) in entries {
+            let set = match self.report(scalar(key))? {
+                "color" => color(value).map(|v| m.color = v),
+                "ambient" => number(value).map(|v| m.ambient = v),
+                "diffuse" => number(value).map(|v| m.diffuse = v),
+                "specular" => number(value).map(|v| m.specular = v),
+                "shininess" => number(value).map(|v| m.shininess = v),
+                "reflective" => number(value).map(|v| m.reflective = v),
+                "transparency" => number(value).map(|v| m.transparency = v),
+                "refractive-index" => number(value).and_then(|v| {
+                    if v >= 1. && v.is_finite() {
+                        m.refractive_index = v;
+                        Ok(())
+                    } else {
+                        Err(Diagnostic::new(
+                            value.span,
+                            format!("refractive index must be at least 1, found {}", v),
+                        )
+                        .with_help("vacuum and air are 1, water 1.33 and glass 1.5".into()))
+                    }
+                }),
+                other => {
+                    let d =
+                        Diagnostic::new(key.span, format!("unknown material property `{}`", other));
+                    Err(match suggestion(other, MATERIAL_KEYS.iter().copied()) {
+                        Some(help) => d.with_help(help),
+                        None => d,
+                    })
+                }
+            };
+            valid &= self.report(set).is_some();
+        }
+
+        if valid {
+            Some(m)
+        } else {
+            None
+        }
+    }
+
+    // Applies the steps of a transform list after `base`, reporting every
+    // broken step
+    fn transform(&mut self, node: &Node, base: Matrix4x4, depth: usize) -> Option<Matrix4x4> {
+        if depth > MAX_NESTING {
+            return self.report(invalid(
+                node,
+                "transform definitions nest too deeply".into(),
+            ));
+        }
+
+        let mut t = Transform::identity().then(base);
+        let mut valid = true;
+        for step in self.report(seq(node))? {
+            if let Value::Scalar(_) = step.value {
+                let named = self.lookup(step).and_then(|named| {
+                    if named.as_seq().is_some() {
+                        Some(named)
+                    } else {
+                        self.report(invalid(
+                            step,
+                            format!("`{}` is not a transform list", text(step)),
+                        ))
+                    }
+                });
+
+                match named.and_then(|n| self.transform(&n, t.matrix(), depth + 1)) {
+                    Some(m) => t = Transform::identity().then(m),
+                    None => valid = false,
+                }
+                continue;
+            }
+
+            match self.report(step_transform(step, t)) {
+                Some(next) => t = next,
+                None => valid = false,
+            }
+        }
+
+        if valid {
+            Some(t.matrix())
+        } else {
+            None
+        }
+    }
+}
+
+fn text(node: &Node) -> &str {
+    node.as_str().unwrap_or_default()
+}
+
+// One `[op, args..]` step of a transform list
+fn step_transform(step: &Node, t: Transform) -> Result<Transform, Diagnostic> {
+    let parts = seq(step)?;
+    let (op, args) = match parts.split_first() {
+        Some((op, args)) => (op, args),
+        None => {
+            return invalid(
+                step,
+                "expected a transformation like `[scale, 2, 2, 2]`".into(),
+            )
+        }
+    };
+
+    let op_name = scalar(op)?;
+    let arity = match op_name {
+        "translate" | "scale" => 3,
+        "rotate-x" | "rotate-y" | "rotate-z" => 1,
+        "shear" => 6,
+        "matrix" => 16,
+        other => {
+            let d = Diagnostic::new(op.span, format!("unknown transformation `{}`", other));
+            return Err(match suggestion(other, TRANSFORMATIONS.iter().copied()) {
+                Some(help) => d.with_help(help),
+                None => d,
+            });
+        }
+    };
+
+    if args.len() != arity {
+        let d = Diagnostic::new(
+            step.span,
+            format!(
+                "`{}` takes {} {}, found {}",
+                op_name,
+                arity,
+                if arity == 1 { "value" } else { "values" },
+                args.len()
+            ),
+        );
+        return Err(match op_name {
+            "scale" if args.len() == 1 => d.with_help(format!(
+                "scale all axes with `[scale, {0}, {0}, {0}]`",
+                text(&args[0])
+            )),
+            _ => d,
+        });
+    }
+
+    let a = args.iter().map(number).collect::<Result<Vec<_>, _>>()?;
+    Ok(match op_name {
+        "translate" => t.translate(a[0], a[1], a[2]),
+        "scale" => t.scale(a[0], a[1], a[2]),
+        "rotate-x" => t.rotate_x(a[0]),
+        "rotate-y" => t.rotate_y(a[0]),
+        "rotate-z" => t.rotate_z(a[0]),
+        "shear" => t.shear(a[0], a[1], a[2], a[3], a[4], a[5]),
+        _ => t.then(Matrix4x4::new([
+            [a[0], a[1], a[2], a[3]],
+            [a[4], a[5], a[6], a[7]],
+            [a[8], a[9], a[10], a[11]],
+            [a[12], a[13], a[14], a[15]],
+        ])),
+    })
+}
+
+fn field<'n>(item: &'n Node, key: &str) -> Result<&'n Node, Diagnostic> {
+    match item.get(key) {
+        Some(value) => Ok(value),
+        None => invalid(item, format!("missing `{}`", key)),
+    }
+}
+
+fn scalar(node: &Node) -> Result<&str, Diagnostic> {
+    match node.as_str() {
+        Some(s) => Ok(s),
+        None => invalid(node, format!("expected a name, found {}", node.kind())),
+    }
+}
+
+fn seq(node: &Node) -> Result<&[Node], Diagnostic> {
+    match node.as_seq() {
+        Some(items) => Ok(items),
+        None => invalid(node, format!("expected a list, found {}", node.kind())),
+    }
+}
+
+fn number(node: &Node) -> Result<f64, Diagnostic> {
+    match node.as_str().map(str::parse::<f64>) {
+        Some(Ok(v)) => Ok(v),
+        Some(Err(_)) => invalid(node, format!("expected a number, found `{}`", text(node))),
+        None => invalid(node, format!("expected a number, found {}", node.kind())),
+    }
+}
+
+fn integer(node: &Node) -> Result<u32, Diagnostic> {
+    match node.as_str().map(str::parse::<u32>) {
+        Some(Ok(v)) => Ok(v),
+        _ => invalid(node, "expected a whole number".into()),
+    }
+}
+
+fn boolean(node: &Node) -> Result<bool, Diagnostic> {
+    match node.as_str() {
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        _ => invalid(node, "expected `true` or `false`".into()),
+    }
+}
+
+fn triple(node: &Node) -> Result<[f64; 3], Diagnostic> {
+    let items = seq(node)?;
+    if items.len() != 3 {
+        return invalid(node, format!("expected 3 numbers, found {}", items.len()));
+    }
+
+    Ok([number(&items[0])?, number(&items[1])?, number(&items[2])?])
+}
+
+fn to_point(node: &Node) -> Result<Point3, Diagnostic> {
+    let [x, y, z] = triple(node)?;
+    Ok(point(x, y, z))
+}
+
+fn to_vector(node: &Node) -> Result<Vector3, Diagnostic> {
+    let [x, y, z] = triple(node)?;
+    Ok(vector(x, y, z))
+}
+
+/// Linear `[r, g, b]`, or an sRGB hex string
+fn color(node: &Node) -> Result<Color, Diagnostic> {
+    if let Some(hex) = node.as_str() {
+        return match hex.parse() {
+            Ok(c) => Ok(c),
+            Err(e) => invalid(node, format!("{}", e)),
+        };
+    }
+
+    let [r, g, b] = triple(node)?;
+    Ok(Color::new(r, g, b))
+}
+
+struct Saver<'a> {
+    dir: &'a Path,
+    stem: String,
+    out: String,
+    /// Meshes already saved, and their file names
+    meshes: Vec<(Arc<TriangleMesh>, String)>,
+}
+
+impl<'a> Saver<'a> {
+    fn scene(&mut self, scene: &SceneFile) -> Result<(), SceneError> {
+        if let Some(c) = &scene.camera {
+            let out = &mut self.out;
+            writeln!(out, "- add: camera").unwrap();
+            writeln!(out, "  width: {}", c.width).unwrap();
+            writeln!(out, "  height: {}", c.height).unwrap();
+            writeln!(out, "  field-of-view: {:?}", c.field_of_view).unwrap();
+            writeln!(out, "  from: {}", list(&[c.from.x, c.from.y, c.from.z])).unwrap();
+            writeln!(out, "  to: {}", list(&[c.to.x, c.to.y, c.to.z])).unwrap();
+            writeln!(out, "  up: {}", list(&[c.up.x, c.up.y, c.up.z])).unwrap();
+        }
+
+        for light in &scene.lights {
+            let (p, i) = (light.position, light.intensity);
+            writeln!(self.out, "\n- add: light").unwrap();
+            writeln!(self.out, "  at: {}", list(&[p.x, p.y, p.z])).unwrap();
+            writeln!(self.out, "  intensity: {}", list(&[i.r, i.g, i.b])).unwrap();
+        }
+
+        for object in &scene.objects {
+            self.out.push('\n');
+            self.object(object, "")?;
+        }
+
+        Ok(())
+    }
+
+    // Every setting is written out, so nothing depends on the defaults or
+    // on what a group passes down
+    fn object(&mut self, object: &Object, indent: &str) -> Result<(), SceneError> {
+        let kind = match &object.shape {
+            Shape::Sphere => "sphere",
+            Shape::Plane => "plane",
+            Shape::Cube => "cube",
+            Shape::Group(_) => "group",
+            Shape::Obj(_) => "obj",
+            Shape::Mesh(_) => "mesh",
+        };
+        writeln!(self.out, "{}- add: {}", indent, kind).unwrap();
+        let pad = format!("{}  ", indent);
+
+        let file = match &object.shape {
+            Shape::Obj(path) => Some(relative_to(path, self.dir)),
+            Shape::Mesh(mesh) => Some(PathBuf::from(self.mesh(mesh)?)),
+            _ => None,
+        };
+        if let Some(file) = file {
+            writeln!(self.out, "{}file: {}", pad, quote(&file.to_string_lossy())).unwrap();
+        }
+
+        let m = &object.material;
+        let out = &mut self.out;
+        writeln!(out, "{}material:", pad).unwrap();
+        writeln!(
+            out,
+            "{}  color: {}",
+            pad,
+            list(&[m.color.r, m.color.g, m.color.b])
+        )
+        .unwrap();
+        for (key, value) in MATERIAL_KEYS[1..].iter().zip(&[
+            m.ambient,
+            m.diffuse,
+            m.specular,
+            m.shininess,
+            m.reflective,
+            m.transparency,
+            m.refractive_index,
+        ]) {
+            writeln!(out, "{}  {}: {:?}", pad, key, value).unwrap();
+        }
+
+        // matrices compare within a tolerance, which would drop transforms
+        // close to the identity
+        if object.transform.rows() != Matrix4x4::<f64>::identity().rows() {
+            let rows = object.transform.rows();
+            let numbers = list(&rows.concat());
+            writeln!(out, "{}transform:", pad).unwrap();
+            writeln!(out, "{}  - [matrix, {}", pad, &numbers[1..]).unwrap();
+        }
+        if !object.shadow {
+            writeln!(out, "{}shadow: false", pad).unwrap();
+        }
+
+        if let Shape::Group(children) = &object.shape {
+            if children.is_empty() {
+                writeln!(out, "{}children: []", pad).unwrap();
+            } else {
+                writeln!(out, "{}children:", pad).unwrap();
+            }
+            for child in children {
+                self.object(child, &format!("{}  ", pad))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Saves the mesh the first time it comes up, returning its file name
+    fn mesh(&mut self, mesh: &Arc<TriangleMesh>) -> Result<String, SceneError> {
+        if let Some((_, name)) = self.meshes.iter().find(|(m, _)| Arc::ptr_eq(m, mesh)) {
+            return Ok(name.clone());
+        }
+
+        let name = format!("{}-mesh-{}.obj", self.stem, self.meshes.len() + 1);
+        save_file(&self.dir.join(&name), &obj::write(mesh.mesh()))?;
+        self.meshes.push((mesh.clone(), name.clone()));
+        Ok(name)
+    }
+}
+
+fn save_file(path: &Path, text: &str) -> Result<(), SceneError> {
+    fs::write(path, text).map_err(|error| SceneError::Write {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+// `{:?}` writes the shortest text that reads back as the same number
+fn list(numbers: &[f64]) -> String {
+    let numbers: Vec<String> = numbers.iter().map(|n| format!("{:?}", n)).collect();
+    format!("[{}]", numbers.join(", "))
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Loading resolves paths against the scene's directory, so they're written
+// relative to it where possible
+fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let absolute = |p: &Path| match env::current_dir() {
+        Ok(cwd) => cwd.join(p),
+        Err(_) => p.to_path_buf(),
+    };
+
+    let path = absolute(path);
+    match path.strip_prefix(absolute(dir)) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+    use rays_core::transformations::*;
+    use std::f64::consts::PI;
+
+    fn diagnostics(src: &str) -> Vec<Diagnostic> {
+        match SceneFile::parse(src).unwrap_err() {
+            SceneError::Invalid { diagnostics, .. } => diagnostics,
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    fn error_at(src: &str) -> (String, usize, usize) {
+        let d = &diagnostics(src)[0];
+        (d.message.clone(), d.span.line, d.span.column)
+    }
+
+    #[test]
+    fn loading_camera_lights_and_shapes() {
+        let src = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [ -6, 6, -10 ]
+  to: [ 6, 0, 6 ]
+  up: [ -0.45, 1, 0 ]
+
+- add: light
+  at: [ 50, 100, -50 ]
+  intensity: [ 1, 1, 1 ]
+
+- add: plane
+  material:
+    color: '#ff8000'
+    reflective: 0.25
+  shadow: false
+
+- add: sphere
+  transform:
+    - [ scale, 0.5, 0.5, 0.5 ]
+    - [ translate, 0, 1, 0 ]
+";
+        let scene = SceneFile::parse(src).unwrap();
+
+        let camera = scene.camera.unwrap();
+        assert_eq!((camera.width, camera.height), (100, 50));
+        assert_eq!(camera.field_of_view, 0.785);
+        assert_eq!(
+            camera.transform(),
+            look_at(
+                point(-6., 6., -10.),
+                point(6., 0., 6.),
+                vector(-0.45, 1., 0.)
+            )
+        );
+
+        assert_eq!(
+            scene.lights,
+            vec![Light {
+                position: point(50., 100., -50.),
+                intensity: Color::new(1., 1., 1.),
+            }]
+        );
+
+        let plane = &scene.objects[0];
+        assert_eq!(plane.shape, Shape::Plane);
+        assert_eq!(
+            plane.material.color,
+            Color::<f64>::from_hex("#ff8000").unwrap()
+        );
+        assert_eq!(plane.material.reflective, 0.25);
+        assert_eq!(plane.material.diffuse, Material::default().diffuse);
+        assert!(!plane.shadow);
+
+        let sphere = &scene.objects[1];
+        assert_eq!(sphere.shape, Shape::Sphere);
+        // applied in reading order
+        assert_eq!(
+            sphere.transform,
+            translation(0., 1., 0.) * scaling(0.5, 0.5, 0.5)
+        );
+        assert!(sphere.shadow);
+    }
+
+    #[test]
+    fn defines_and_extends() {
+        let src = "
+- define: white-material
+  value:
+    color: [ 1, 1, 1 ]
+    diffuse: 0.7
+    ambient: 0.1
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [ 0.537, 0.831, 0.914 ]
+
+- define: standard-transform
+  value:
+    - [ translate, 1, -1, 1 ]
+    - [ scale, 0.5, 0.5, 0.5 ]
+
+- define: large-object
+  extend: standard-transform
+  value:
+    - [ scale, 3.5, 3.5, 3.5 ]
+
+- add: cube
+  material: blue-material
+  transform:
+    - large-object
+    - [ rotate-y, 1.5707963 ]
+";
+        let scene = SceneFile::parse(src).unwrap();
+        let cube = &scene.objects[0];
+
+        assert_eq!(cube.material.color, Color::new(0.537, 0.831, 0.914));
+        assert_eq!(cube.material.diffuse, 0.7);
+        assert_eq!(
+            cube.transform,
+            rotation_y(PI / 2.)
+                * scaling(3.5, 3.5, 3.5)
+                * scaling(0.5, 0.5, 0.5)
+                * translation(1., -1., 1.)
+        );
+    }
+
+    #[test]
+    fn groups_obj_files_and_defined_shapes() {
+        let src = "
+- define: leg
+  value:
+    add: group
+    material: { color: [1, 0, 0] }
+    children:
+      - add: sphere
+        transform: [ [ translate, 0, 0, -1 ] ]
+      - add: cube
+        material: { color: [0, 0, 1] }
+
+- add: leg
+  transform: [ [ rotate-y, 0.5 ] ]
+
+- add: obj
+  file: models/teapot.obj
+";
+        let scene = SceneFile::parse(src).unwrap();
+
+        let leg = &scene.objects[0];
+        assert_eq!(leg.transform, rotation_y(0.5));
+        match &leg.shape {
+            Shape::Group(children) => {
+                assert_eq!(children.len(), 2);
+                assert_eq!(children[0].material.color, Color::new(1., 0., 0.));
+                assert_eq!(children[0].transform, translation(0., 0., -1.));
+                assert_eq!(children[1].material.color, Color::new(0., 0., 1.));
+            }
+            s => panic!("expected a group, found {:?}", s),
+        }
+
+        assert_eq!(
+            scene.objects[1].shape,
+            Shape::Obj(PathBuf::from("models/teapot.obj"))
+        );
+    }
+
+    #[test]
+    fn loading_files_resolves_paths_next_to_them() {
+        let dir = std::env::temp_dir().join(format!("rays-scene-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scene.yml");
+        fs::write(&path, "- add: obj\n  file: teapot.obj\n").unwrap();
+
+        let scene = SceneFile::load(&path).unwrap();
+        assert_eq!(scene.objects[0].shape, Shape::Obj(dir.join("teapot.obj")));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(SceneFile::load(&path), Err(SceneError::Io { .. })));
+    }
+
+    #[test]
+    fn reporting_invalid_scenes() {
+        assert_eq!(
+            error_at("- add: sphere\n  material: shiny\n"),
+            ("`shiny` is not defined".to_string(), 2, 13)
+        );
+        assert_eq!(
+            error_at("- add: sphere\n  transform: [[rotate-y, 1, 2]]\n"),
+            ("`rotate-y` takes 1 value, found 2".to_string(), 2, 15)
+        );
+        assert_eq!(
+            error_at("- add: sphere\n  transform: [[spin, 1]]\n"),
+            ("unknown transformation `spin`".to_string(), 2, 16)
+        );
+        assert_eq!(
+            error_at("- add: cone\n"),
+            ("unknown item `cone`".to_string(), 1, 8)
+        );
+        assert_eq!(
+            error_at("- add: sphere\n  material:\n    difuse: 0.5\n"),
+            ("unknown material property `difuse`".to_string(), 3, 5)
+        );
+        assert_eq!(
+            error_at("- add: light\n  at: [1, 2]\n  intensity: [1, 1, 1]\n"),
+            ("expected 3 numbers, found 2".to_string(), 2, 7)
+        );
+        assert_eq!(
+            error_at("- add: camera\n  width: 10\n"),
+            ("missing `height`".to_string(), 1, 3)
+        );
+        assert_eq!(
+            error_at("- define: t\n  value: [[scale, 1, 1, 1]]\n- define: m\n  extend: t\n  value: { color: [1, 1, 1] }\n"),
+            ("`t` is a list and can't be extended by a mapping".to_string(), 4, 11)
+        );
+
+        let (message, line, column) = error_at("- add: mesh\n  file: missing.obj\n");
+        assert!(message.starts_with("could not read missing.obj: "));
+        assert_eq!((line, column), (2, 9));
+    }
+
+    #[test]
+    fn reporting_self_referencing_shapes() {
+        assert_eq!(
+            error_at("- define: a\n  value: { add: a }\n- add: a\n"),
+            ("`a` refers to itself".to_string(), 2, 17)
+        );
+        assert_eq!(
+            error_at("- define: sphere\n  value: { add: sphere }\n- add: sphere\n"),
+            ("`sphere` refers to itself".to_string(), 2, 17)
+        );
+
+        let src = "\
+- define: leg
+  value:
+    add: group
+    children:
+      - add: cube
+      - add: leg
+- add: leg
+";
+        assert_eq!(error_at(src), ("`leg` refers to itself".to_string(), 6, 14));
+
+        let src = "\
+- define: a
+  value: { add: sphere }
+- define: b
+  value: { add: a }
+- define: a
+  value: { add: b }
+- add: a
+";
+        assert_eq!(error_at(src), ("`a` refers to itself".to_string(), 4, 17));
+    }
+
+    #[test]
+    fn reporting_every_problem_at_once() {
+        let src = "\
+- define: glass
+  extend: clear
+  value: { transparency: 0.9, refractive-index: 0.5 }
+
+- add: sphere
+  materal: glass
+  transform:
+    - [ scale, 0 ]
+    - [ rotate-z ]
+    - [ tranlsate, 1, 2, 3 ]
+
+- add: cube
+  transform: [ [ scale, 1, 0, 1 ] ]
+
+- add: sphear
+";
+        let found: Vec<_> = diagnostics(src)
+            .into_iter()
+            .map(|d| (d.span.line, d.message, d.help))
+            .collect();
+        let help = |h: &str| Some(h.to_string());
+
+        assert_eq!(
+            found,
+            vec![
+                (2, "`clear` is not defined".into(), None),
+                (
+                    6,
+                    "unknown key `materal`".into(),
+                    help("did you mean `material`?")
+                ),
+                (
+                    8,
+                    "`scale` takes 3 values, found 1".into(),
+                    help("scale all axes with `[scale, 0, 0, 0]`")
+                ),
+                (9, "`rotate-z` takes 1 value, found 0".into(), None),
+                (
+                    10,
+                    "unknown transformation `tranlsate`".into(),
+                    help("did you mean `translate`?")
+                ),
+                (
+                    13,
+                    "this transform can't be inverted".into(),
+                    help("a scale of 0 flattens the shape; use a small value instead")
+                ),
+                (
+                    15,
+                    "unknown item `sphear`".into(),
+                    help("did you mean `sphere`?")
+                ),
+            ]
+        );
+
+        // uses of a broken definition report it once, where it's written
+        let src = "\
+- define: glass
+  value: { refractive-index: 0.5 }
+- add: sphere
+  material: glass
+- add: cube
+  material: galss
+";
+        let found = diagnostics(src);
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[0].message,
+            "refractive index must be at least 1, found 0.5"
+        );
+        assert_eq!(
+            (found[1].span.line, found[1].help.as_deref()),
+            (6, Some("did you mean `glass`?"))
+        );
+    }
+
+    #[test]
+    fn errors_show_the_file_and_source_line() {
+        let dir = std::env::temp_dir().join(format!("rays-diagnostics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scene.yml");
+        fs::write(&path, "- add: plane\n  shadow: maybe\n").unwrap();
+
+        let message = SceneFile::load(&path).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            message,
+            format!(
+                "error: expected `true` or `false`\n \
+                 --> {}:2:11\n  \
+                 |\n\
+                 2 |   shadow: maybe\n  \
+                 |           ^^^^^",
+                path.display()
+            )
+        );
+
+        // syntax errors come through the same way
+        let message = SceneFile::parse("- add: [sphere\n")
+            .unwrap_err()
+            .to_string();
+        assert!(message.starts_with("error: unclosed `[`\n --> <scene>:1:8\n"));
+
+        for src in &["[[]é", "{a: ''é"] {
+            let message = SceneFile::parse(src).unwrap_err().to_string();
+            assert!(message.ends_with(" ^"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn transform_lists_take_whole_matrices() {
+        let src = "
+- add: cube
+  transform:
+    - [ scale, 2, 2, 2 ]
+    - [ matrix, 1, 0, 0, 5, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1 ]
+";
+        let scene = SceneFile::parse(src).unwrap();
+        assert_eq!(
+            scene.objects[0].transform,
+            translation(5., 0., 0.) * scaling(2., 2., 2.)
+        );
+
+        assert_eq!(
+            error_at("- add: cube\n  transform: [ [ matrix, 1, 0, 0 ] ]\n").0,
+            "`matrix` takes 16 values, found 3"
+        );
+    }
+
+    #[test]
+    fn saving_and_loading_back_scenes() {
+        let dir = std::env::temp_dir().join(format!("rays-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let glass = Material {
+            color: Color::new(0.1, 1. / 3., 0.),
+            transparency: 0.9,
+            refractive_index: 1.52,
+            ..Material::default()
+        };
+        let object = |shape, transform, material: &Material| Object {
+            shape,
+            transform,
+            material: material.clone(),
+            shadow: true,
+        };
+        let triangle = Mesh::new(
+            vec![point(0., 0., 0.), point(1., 0., 0.), point(0., 0.1, 0.)],
+            vec![[0, 1, 2]],
+        );
+        let mesh = Arc::new(TriangleMesh::new(triangle.clone()));
+
+        let mut scene = SceneFile {
+            camera: Some(Camera {
+                width: 320,
+                height: 200,
+                field_of_view: PI / 3.,
+                from: point(0., 1.5, -5.),
+                to: point(0., 1., 0.),
+                up: vector(0., 1., 0.),
+            }),
+            lights: vec![Light {
+                position: point(-10., 10., -10.),
+                intensity: Color::new(1., 1., 1.),
+            }],
+            objects: vec![
+                // close enough to the identity to compare equal to it
+                object(
+                    Shape::Plane,
+                    translation(0., 0., 5e-6) * shearing(0., 1e-7, 0., 0., 0., 0.),
+                    &Material::default(),
+                ),
+                object(
+                    Shape::Group(vec![
+                        object(
+                            Shape::Sphere,
+                            rotation_x(0.1) * shearing(1., 0., 0., 0., 0., 1e-9),
+                            &glass,
+                        ),
+                        object(Shape::Group(vec![]), Matrix4x4::identity(), &glass),
+                        object(Shape::Mesh(mesh.clone()), scaling(1., 2., 3.), &glass),
+                        object(Shape::Mesh(mesh), translation(0., 1., 0.), &glass),
+                    ]),
+                    rotation_y(PI / 7.),
+                    &Material::default(),
+                ),
+                object(
+                    Shape::Obj(dir.join("models/it's \"quoted\".obj")),
+                    Matrix4x4::identity(),
+                    &glass,
+                ),
+            ],
+        };
+        scene.objects[0].shadow = false;
+
+        let path = dir.join("scene.yml");
+        scene.save(&path).unwrap();
+        let loaded = SceneFile::load(&path).unwrap();
+
+        // meshes are saved once for both objects using them, and shared
+        // again once loaded
+        assert!(!dir.join("scene-mesh-2.obj").exists());
+        assert_eq!(obj::load(dir.join("scene-mesh-1.obj")).unwrap(), triangle);
+        assert_eq!(loaded, scene);
+        match &loaded.objects[1].shape {
+            Shape::Group(children) => match (&children[2].shape, &children[3].shape) {
+                (Shape::Mesh(a), Shape::Mesh(b)) => assert!(Arc::ptr_eq(a, b)),
+                s => panic!("expected meshes, found {:?}", s),
+            },
+            s => panic!("expected a group, found {:?}", s),
+        }
+
+        // matrices compare within a tolerance, but the numbers survive exactly
+        fn rows(objects: &[Object], out: &mut Vec<[[f64; 4]; 4]>) {
+            for object in objects {
+                out.push(*object.transform.rows());
+                if let Shape::Group(children) = &object.shape {
+                    rows(children, out);
+                }
+            }
+        }
+        let (mut saved, mut read) = (vec![], vec![]);
+        rows(&scene.objects, &mut saved);
+        rows(&loaded.objects, &mut read);
+        assert_eq!(read, saved);
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\n  file: \"models/it's \\\"quoted\\\".obj\"\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
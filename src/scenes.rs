@@ -0,0 +1,88 @@
+use std::f64::consts::PI;
+
+use rays_core::canvas::*;
+use rays_core::color::*;
+use rays_core::imageops::ResizeFilter;
+use rays_core::transformations::rotation_z;
+use rays_core::tuples::point;
+
+use crate::projectile;
+
+pub struct Scene {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    render: fn(u32, u32) -> Canvas,
+}
+
+impl Scene {
+    pub fn render(&self) -> Canvas {
+        (self.render)(self.width, self.height)
+    }
+
+    pub fn render_at(&self, width: u32, height: u32) -> Canvas {
+        (self.render)(width, height)
+    }
+}
+
+pub const SCENES: &[Scene] = &[
+    Scene {
+        name: "projectile",
+        width: 900,
+        height: 500,
+        render: render_projectile,
+    },
+    Scene {
+        name: "clock",
+        width: 200,
+        height: 200,
+        render: render_clock,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Scene> {
+    SCENES.iter().find(|s| s.name == name)
+}
+
+/// The projectile arc only fits its native 900x500 canvas, other sizes are
+/// resampled from it
+fn render_projectile(width: u32, height: u32) -> Canvas {
+    let mut c = Canvas::new(900, 500, None);
+    projectile::simulate_projectile(&mut c);
+
+    if (width, height) == (c.width, c.height) {
+        c
+    } else {
+        c.resize(width, height, ResizeFilter::Lanczos3)
+    }
+}
+
+/// Twelve hour marks, placed by rotating noon around the z axis
+fn render_clock(width: u32, height: u32) -> Canvas {
+    let mut c = Canvas::new(width, height, None);
+
+    let radius = width.min(height) as f64 * 3. / 8.;
+    let (cx, cy) = (width as f64 / 2., height as f64 / 2.);
+    let noon = point(0., radius, 0.);
+
+    c.draw_circle(
+        cx as i64,
+        cy as i64,
+        (radius * 1.15) as i64,
+        Color::new(0.2, 0.2, 0.2),
+    );
+
+    for hour in 0..12 {
+        // clockwise on screen is a negative rotation around z
+        let mark = rotation_z(-(hour as f64) * PI / 6.) * noon;
+        let size = if hour % 3 == 0 {
+            radius / 12.
+        } else {
+            radius / 20.
+        };
+
+        c.fill_circle(cx + mark.x, cy - mark.y, size, Color::new(1., 1., 1.));
+    }
+
+    c
+}
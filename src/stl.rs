@@ -0,0 +1,228 @@
+//! STL meshes, ASCII or binary.
+//!
+//! STL stores every triangle with its own corners, so shared corners are
+//! welded back together and given smooth normals, except across edges
+//! sharper than `CREASE_ANGLE`, which stay hard. The facet normals in the
+//! file are ignored.
+
+use std::fs;
+use std::path::Path;
+
+use rays_core::tuples::point;
+
+use crate::mesh::{Mesh, MeshError};
+
+const HEADER_SIZE: usize = 84;
+const FACET_SIZE: usize = 50;
+
+// Corners this close, relative to the size of the model, are the same
+const WELD_TOLERANCE: f32 = 1e-6;
+
+// Faces meeting at a steeper angle (in radians) don't share normals, which
+// keeps boxes and other machined parts flat shaded
+const CREASE_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+
+fn invalid<T>(message: String) -> Result<T, MeshError> {
+    Err(MeshError::Invalid(message))
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, MeshError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|error| MeshError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    read(&bytes)
+}
+
+pub fn read(bytes: &[u8]) -> Result<Mesh, MeshError> {
+    // binary files may start with "solid" too, but their size gives them
+    // away
+    let binary_size = bytes
+        .get(80..HEADER_SIZE)
+        .map(|b| HEADER_SIZE + FACET_SIZE * u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    let mut mesh = if bytes.starts_with(b"solid") && binary_size != Some(bytes.len()) {
+        ascii(bytes)?
+    } else {
+        binary(bytes)?
+    };
+
+    let (min, max) = mesh.positions.iter().fold(
+        ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+        |(min, max), p| {
+            (
+                [min[0].min(p.x), min[1].min(p.y), min[2].min(p.z)],
+                [max[0].max(p.x), max[1].max(p.y), max[2].max(p.z)],
+            )
+        },
+    );
+    let size = (0..3).map(|i| (max[i] - min[i]).max(0.)).fold(0., f32::max);
+
+    mesh.weld(size * WELD_TOLERANCE);
+    mesh.compute_creased_normals(CREASE_ANGLE);
+    Ok(mesh)
+}
+
+fn binary(bytes: &[u8]) -> Result<Mesh, MeshError> {
+    let count = match bytes.get(80..HEADER_SIZE) {
+        Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+        None => return invalid("binary STL header is truncated".into()),
+    };
+    let facets = match bytes.get(HEADER_SIZE..HEADER_SIZE + count * FACET_SIZE) {
+        Some(facets) => facets,
+        None => {
+            return invalid(format!(
+                "binary STL should hold {} triangles but is too short",
+                count
+            ))
+        }
+    };
+
+    let mut mesh = Mesh::default();
+    for (i, facet) in facets.chunks_exact(FACET_SIZE).enumerate() {
+        let f = |k: usize| {
+            let b = &facet[12 + 4 * k..16 + 4 * k];
+            f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        };
+
+        // skip the facet normal, then three corners
+        for corner in 0..3 {
+            mesh.positions
+                .push(point(f(3 * corner), f(3 * corner + 1), f(3 * corner + 2)));
+        }
+        let first = 3 * i as u32;
+        mesh.triangles.push([first, first + 1, first + 2]);
+    }
+
+    Ok(mesh)
+}
+
+fn ascii(bytes: &[u8]) -> Result<Mesh, MeshError> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut mesh = Mesh::default();
+
+    for (n, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+
+        let coordinates: Vec<f32> = words.filter_map(|w| w.parse().ok()).collect();
+        match coordinates.as_slice() {
+            &[x, y, z] => mesh.positions.push(point(x, y, z)),
+            _ => {
+                return invalid(format!(
+                    "line {}: expected three coordinates after `vertex`",
+                    n + 1
+                ))
+            }
+        }
+    }
+
+    if !mesh.positions.len().is_multiple_of(3) {
+        return invalid("a facet doesn't have three vertices".into());
+    }
+
+    let count = mesh.positions.len() as u32 / 3;
+    mesh.triangles = (0..count).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::tuples::normal;
+
+    // A folded square: two facets sharing an edge along y
+    const ASCII: &str = "\
+solid fold
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 -1 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 0 1
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid fold
+";
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut out = header.to_vec();
+        out.resize(80, b' ');
+        out.extend_from_slice(&2_u32.to_le_bytes());
+        let facets: [[f32; 12]; 2] = [
+            [0., 0., -1., 0., 0., 0., 0., 1., 0., 1., 0., 0.],
+            [-1., 0., 0., 0., 0., 0., 0., 0., 1., 0., 1., 0.],
+        ];
+        for facet in &facets {
+            for v in facet {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&[0, 0]);
+        }
+        out
+    }
+
+    fn check_fold(mesh: &Mesh) {
+        // the fold is a right angle, so the shared edge stays hard
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(mesh.positions[0], mesh.positions[3]);
+        assert_eq!(mesh.normals[0], normal(0., 0., -1.));
+        assert_eq!(mesh.normals[2], normal(0., 0., -1.));
+        assert_eq!(mesh.normals[3], normal(-1., 0., 0.));
+        assert_eq!(mesh.normals[5], normal(-1., 0., 0.));
+    }
+
+    #[test]
+    fn reading_and_welding_ascii_files() {
+        check_fold(&read(ASCII.as_bytes()).unwrap());
+
+        // a shallow fold is welded and smoothed across the edge
+        let shallow = read(ASCII.replace("vertex 0 0 1", "vertex -1 0 0.3").as_bytes()).unwrap();
+        assert_eq!(shallow.positions.len(), 4);
+        assert_eq!(shallow.triangles, vec![[0, 1, 2], [0, 3, 1]]);
+        assert_eq!(shallow.normals[0], shallow.normals[1]);
+        assert_eq!(shallow.normals[0], normal(-0.3, 0., -2.).normalize());
+        assert_eq!(shallow.normals[2], normal(0., 0., -1.));
+    }
+
+    #[test]
+    fn reading_binary_files_even_if_they_start_with_solid() {
+        check_fold(&read(&binary(b"binary fold")).unwrap());
+        check_fold(&read(&binary(b"solid fold, but binary")).unwrap());
+    }
+
+    #[test]
+    fn rejecting_broken_files() {
+        let error = |bytes: &[u8]| match read(bytes) {
+            Err(MeshError::Invalid(message)) => message,
+            r => panic!("expected an error, found {:?}", r),
+        };
+
+        assert_eq!(
+            error(ASCII.replace("vertex 0 0 1", "vertex 0 0").as_bytes()),
+            "line 12: expected three coordinates after `vertex`"
+        );
+        assert_eq!(
+            error(ASCII.replace("      vertex 1 0 0\n", "").as_bytes()),
+            "a facet doesn't have three vertices"
+        );
+
+        let mut short = binary(b"fold");
+        short.truncate(120);
+        assert_eq!(
+            error(&short),
+            "binary STL should hold 2 triangles but is too short"
+        );
+    }
+}
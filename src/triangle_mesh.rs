@@ -0,0 +1,611 @@
+//! Meshes ready for rendering: the shared vertex and index buffers of a
+//! `Mesh` plus a bounding volume hierarchy over its triangles.
+//!
+//! Everything lives in a handful of flat arrays, with no allocation per
+//! triangle: a triangle costs 12 bytes of indices, a vertex 12 bytes of
+//! position plus its optional normal, uv and color, and the hierarchy about
+//! 16 bytes per triangle.
+
+use rays_core::ray::Ray;
+use rays_core::tuples::{point, Normal3, Point3, Vector3};
+
+use crate::mesh::Mesh;
+
+// Nodes with this many triangles or fewer become leaves
+const MAX_LEAF: usize = 4;
+
+// Candidate split planes per axis for the surface area heuristic
+const BINS: usize = 12;
+
+// The deepest a leaf can be, which sizes the traversal stack. Nodes below
+// `SAH_DEPTH` split by the heuristic, deeper ones halve their triangles, so
+// even 2^32 of them fit
+const MAX_DEPTH: usize = 64;
+const SAH_DEPTH: usize = MAX_DEPTH - 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Bounds {
+    const EMPTY: Bounds = Bounds {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+
+    fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+        }
+    }
+
+    fn grow(self, p: [f32; 3]) -> Bounds {
+        self.union(Bounds { min: p, max: p })
+    }
+
+    // Half the surface area, which is all the heuristic needs
+    fn area(&self) -> f32 {
+        let [x, y, z] = [0, 1, 2].map(|i| (self.max[i] - self.min[i]).max(0.));
+        x * y + y * z + z * x
+    }
+
+    fn centroid(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) / 2.)
+    }
+
+    // Where the ray enters the box, if it does before `t_max`
+    fn entry(&self, origin: &[f64; 3], inv_direction: &[f64; 3], t_max: f64) -> Option<f64> {
+        let (mut near, mut far) = (0_f64, t_max);
+
+        for i in 0..3 {
+            let t0 = (self.min[i] as f64 - origin[i]) * inv_direction[i];
+            let t1 = (self.max[i] as f64 - origin[i]) * inv_direction[i];
+            // NaN from 0 * inf when the ray lies in a slab's plane falls
+            // through both comparisons and leaves the interval unchanged
+            let (t0, t1) = if t0 > t1 { (t1, t0) } else { (t0, t1) };
+            if t0 > near {
+                near = t0;
+            }
+            if t1 < far {
+                far = t1;
+            }
+        }
+
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Node {
+    bounds: Bounds,
+    /// A leaf's first triangle, or an interior node's second child; the
+    /// first child comes right after its parent
+    index: u32,
+    /// Triangles in a leaf, 0 for interior nodes
+    count: u32,
+}
+
+/// Where a ray meets a triangle mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHit {
+    pub t: f64,
+    /// Index into the mesh's triangles
+    pub triangle: usize,
+    /// Barycentric weights of the triangle's second and third corners
+    pub u: f64,
+    pub v: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TriangleMesh {
+    mesh: Mesh,
+    nodes: Vec<Node>,
+}
+
+impl From<Mesh> for TriangleMesh {
+    fn from(mesh: Mesh) -> Self {
+        TriangleMesh::new(mesh)
+    }
+}
+
+impl TriangleMesh {
+    /// Builds the hierarchy, reordering the triangles (and their materials)
+    /// so every leaf holds a contiguous run of them
+    pub fn new(mut mesh: Mesh) -> Self {
+        let boxes: Vec<Bounds> = (0..mesh.triangle_count())
+            .map(|i| {
+                mesh.triangle(i)
+                    .iter()
+                    .fold(Bounds::EMPTY, |b, p| b.grow([p.x, p.y, p.z]))
+            })
+            .collect();
+
+        let mut order: Vec<u32> = (0..boxes.len() as u32).collect();
+        let mut nodes = Vec::with_capacity(2 * boxes.len() / MAX_LEAF + 1);
+        if !boxes.is_empty() {
+            subdivide(&mut nodes, &mut order, 0, &boxes, 0);
+        }
+
+        mesh.triangles = order.iter().map(|&i| mesh.triangles[i as usize]).collect();
+        if !mesh.materials.is_empty() {
+            mesh.materials = order.iter().map(|&i| mesh.materials[i as usize]).collect();
+        }
+
+        TriangleMesh { mesh, nodes }
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        self.mesh
+    }
+
+    /// The corners of the box around the mesh, if it has any triangles
+    pub fn bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+        self.nodes.first().map(|root| {
+            let Bounds { min, max } = root.bounds;
+            (point(min[0], min[1], min[2]), point(max[0], max[1], max[2]))
+        })
+    }
+
+    pub fn material(&self, triangle: usize) -> Option<u16> {
+        self.mesh.materials.get(triangle).copied()
+    }
+
+    /// The closest intersection in front of the ray's origin
+    pub fn intersect(&self, ray: &Ray) -> Option<MeshHit> {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let inv_direction = [ray.direction.x, ray.direction.y, ray.direction.z].map(|d| 1. / d);
+
+        let mut closest: Option<MeshHit> = None;
+        let mut t_max = f64::INFINITY;
+
+        // nodes still to visit, with where the ray enters them. Each level
+        // of the tree leaves at most one behind, so this never overflows
+        let mut stack = [(0_usize, 0_f64); MAX_DEPTH + 1];
+        let root = self.nodes.first()?;
+        stack[0] = (0, root.bounds.entry(&origin, &inv_direction, t_max)?);
+        let mut len = 1;
+
+        while len > 0 {
+            len -= 1;
+            let (i, entry) = stack[len];
+            // a closer hit may have turned up since the node was queued
+            if entry > t_max {
+                continue;
+            }
+
+            let node = &self.nodes[i];
+            if node.count > 0 {
+                let first = node.index as usize;
+                for triangle in first..first + node.count as usize {
+                    if let Some(hit) = self.intersect_triangle(triangle, ray) {
+                        if hit.t < t_max {
+                            t_max = hit.t;
+                            closest = Some(hit);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            // visit the nearer child first by pushing it last
+            let children = [i + 1, node.index as usize].map(|c| {
+                (
+                    c,
+                    self.nodes[c].bounds.entry(&origin, &inv_direction, t_max),
+                )
+            });
+            let (near, far) = match children {
+                [(_, Some(a)), (_, Some(b))] if b < a => (children[1], children[0]),
+                _ => (children[0], children[1]),
+            };
+            for (c, entry) in [far, near] {
+                if let Some(t) = entry {
+                    stack[len] = (c, t);
+                    len += 1;
+                }
+            }
+        }
+
+        closest
+    }
+
+    // Möller-Trumbore, as in the book's triangles
+    fn intersect_triangle(&self, triangle: usize, ray: &Ray) -> Option<MeshHit> {
+        let [p1, p2, p3] = self.mesh.triangle(triangle).map(|p| p.cast::<f64>());
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+
+        let dir_cross_e2 = ray.direction.cross(e2);
+        let det = e1.dot(dir_cross_e2);
+        // parallel to the plane, to within rounding
+        if det.abs() <= f64::EPSILON * e1.magnitude() * dir_cross_e2.magnitude() {
+            return None;
+        }
+
+        let f = 1. / det;
+        let p1_to_origin = ray.origin - p1;
+        let u = f * p1_to_origin.dot(dir_cross_e2);
+        if !(0. ..=1.).contains(&u) {
+            return None;
+        }
+
+        let origin_cross_e1 = p1_to_origin.cross(e1);
+        let v = f * ray.direction.dot(origin_cross_e1);
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+
+        let t = f * e2.dot(origin_cross_e1);
+        if t <= 0. {
+            return None;
+        }
+
+        Some(MeshHit { t, triangle, u, v })
+    }
+
+    /// The surface normal at a hit, in the mesh's own space. It's
+    /// interpolated from the vertex normals if the mesh has them, otherwise
+    /// it's the triangle's, which follows `Mesh::compute_normals`.
+    pub fn normal_at(&self, hit: &MeshHit) -> Normal3 {
+        let [a, b, c] = self.mesh.triangles[hit.triangle].map(|i| i as usize);
+
+        if self.mesh.normals.is_empty() {
+            let [p1, p2, p3] = self.mesh.triangle(hit.triangle).map(|p| p.cast::<f64>());
+            return (p2 - p1).cross(p3 - p1).normalize().into();
+        }
+
+        let n = |i: usize| Vector3::from(self.mesh.normals[i].cast::<f64>());
+        (n(a) * (1. - hit.u - hit.v) + n(b) * hit.u + n(c) * hit.v)
+            .normalize()
+            .into()
+    }
+
+    /// Texture coordinates at a hit, if the mesh has them
+    pub fn uv_at(&self, hit: &MeshHit) -> Option<[f64; 2]> {
+        if self.mesh.uvs.is_empty() {
+            return None;
+        }
+
+        let [a, b, c] = self.mesh.triangles[hit.triangle].map(|i| self.mesh.uvs[i as usize]);
+        let w = [1. - hit.u - hit.v, hit.u, hit.v];
+        Some([0, 1].map(|k| w[0] * a[k] as f64 + w[1] * b[k] as f64 + w[2] * c[k] as f64))
+    }
+}
+
+// Adds the node for `triangles`, which start at `first` in the final
+// order, and everything below it
+fn subdivide(
+    nodes: &mut Vec<Node>,
+    triangles: &mut [u32],
+    first: usize,
+    boxes: &[Bounds],
+    depth: usize,
+) {
+    let here = nodes.len();
+    nodes.push(Node {
+        bounds: triangles
+            .iter()
+            .fold(Bounds::EMPTY, |b, &t| b.union(boxes[t as usize])),
+        index: first as u32,
+        count: triangles.len() as u32,
+    });
+
+    if triangles.len() <= MAX_LEAF {
+        return;
+    }
+
+    let heuristic = if depth < SAH_DEPTH {
+        split(triangles, boxes)
+    } else {
+        None
+    };
+    let mid = match heuristic {
+        Some(mid) => mid,
+        None => {
+            // no plane separates the centroids, none pays off, or the tree
+            // is already deep; halving along the widest axis keeps it
+            // shallow regardless
+            let centroids = triangles
+                .iter()
+                .fold(Bounds::EMPTY, |b, &t| b.grow(boxes[t as usize].centroid()));
+            let axis = widest_axis(&centroids);
+            let mid = triangles.len() / 2;
+            triangles.select_nth_unstable_by(mid, |&a, &b| {
+                let ca = boxes[a as usize].centroid()[axis];
+                let cb = boxes[b as usize].centroid()[axis];
+                ca.total_cmp(&cb)
+            });
+            mid
+        }
+    };
+
+    let (left, right) = triangles.split_at_mut(mid);
+    nodes[here].count = 0;
+    subdivide(nodes, left, first, boxes, depth + 1);
+    nodes[here].index = nodes.len() as u32;
+    subdivide(nodes, right, first + mid, boxes, depth + 1);
+}
+
+fn widest_axis(b: &Bounds) -> usize {
+    let extent = [0, 1, 2].map(|i| b.max[i] - b.min[i]);
+    (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap_or(0)
+}
+
+// Partitions the triangles at the cheapest plane by the surface area
+// heuristic, binning their centroids, and returns how many went left.
+// `None` if no plane beats keeping them together.
+fn split(triangles: &mut [u32], boxes: &[Bounds]) -> Option<usize> {
+    let centroids = triangles
+        .iter()
+        .fold(Bounds::EMPTY, |b, &t| b.grow(boxes[t as usize].centroid()));
+    let bounds = triangles
+        .iter()
+        .fold(Bounds::EMPTY, |b, &t| b.union(boxes[t as usize]));
+
+    let bin_of = |t: u32, axis: usize| -> usize {
+        let extent = centroids.max[axis] - centroids.min[axis];
+        let c = boxes[t as usize].centroid()[axis];
+        (((c - centroids.min[axis]) / extent * BINS as f32) as usize).min(BINS - 1)
+    };
+
+    // the cost of a leaf, in the same units as the split costs below
+    let mut best: Option<(usize, usize)> = None;
+    let mut best_cost = triangles.len() as f32 * bounds.area();
+
+    for axis in 0..3 {
+        if centroids.max[axis] <= centroids.min[axis] {
+            continue;
+        }
+
+        let mut counts = [0_usize; BINS];
+        let mut bin_bounds = [Bounds::EMPTY; BINS];
+        for &t in triangles.iter() {
+            let bin = bin_of(t, axis);
+            counts[bin] += 1;
+            bin_bounds[bin] = bin_bounds[bin].union(boxes[t as usize]);
+        }
+
+        // sweep from the right to know each plane's right side
+        let mut right_cost = [0_f32; BINS];
+        let (mut count, mut b) = (0, Bounds::EMPTY);
+        for plane in (1..BINS).rev() {
+            count += counts[plane];
+            b = b.union(bin_bounds[plane]);
+            right_cost[plane] = count as f32 * b.area();
+        }
+
+        let (mut count, mut b) = (0, Bounds::EMPTY);
+        for plane in 1..BINS {
+            count += counts[plane - 1];
+            b = b.union(bin_bounds[plane - 1]);
+
+            let cost = count as f32 * b.area() + right_cost[plane];
+            if count > 0 && count < triangles.len() && cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, plane));
+            }
+        }
+    }
+
+    let (axis, plane) = best?;
+    let mut mid = 0;
+    for i in 0..triangles.len() {
+        if bin_of(triangles[i], axis) < plane {
+            triangles.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    Some(mid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::tuples::{normal, vector};
+
+    fn book_triangle() -> Mesh {
+        Mesh::new(
+            vec![point(0., 1., 0.), point(-1., 0., 0.), point(1., 0., 0.)],
+            vec![[0, 1, 2]],
+        )
+    }
+
+    // A wavy grid of n by n quads, as triangles with material ids giving
+    // their position in the grid
+    fn terrain(n: u32) -> Mesh {
+        let height = |x: u32, z: u32| ((x as f32 * 0.7).sin() + (z as f32 * 0.4).cos()) * 0.5;
+        let mut mesh = Mesh::default();
+
+        for z in 0..=n {
+            for x in 0..=n {
+                mesh.positions.push(point(x as f32, height(x, z), z as f32));
+            }
+        }
+        for z in 0..n {
+            for x in 0..n {
+                let i = z * (n + 1) + x;
+                mesh.triangles.push([i, i + n + 1, i + 1]);
+                mesh.triangles.push([i + 1, i + n + 1, i + n + 2]);
+                mesh.materials.extend([(z * n + x) as u16; 2]);
+            }
+        }
+
+        mesh
+    }
+
+    // Checking every triangle, to compare the hierarchy with
+    fn brute_force(mesh: &TriangleMesh, ray: &Ray) -> Option<MeshHit> {
+        (0..mesh.mesh().triangle_count())
+            .filter_map(|i| mesh.intersect_triangle(i, ray))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    #[test]
+    fn intersecting_the_books_triangle() {
+        let mesh = TriangleMesh::new(book_triangle());
+
+        /* Intersecting a ray parallel to the triangle */
+        let r = Ray::new(point(0., -1., -2.), vector(0., 1., 0.));
+        assert_eq!(mesh.intersect(&r), None);
+
+        /* A ray misses the p1-p3 edge */
+        let r = Ray::new(point(1., 1., -2.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&r), None);
+
+        /* A ray misses the p1-p2 edge */
+        let r = Ray::new(point(-1., 1., -2.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&r), None);
+
+        /* A ray misses the p2-p3 edge */
+        let r = Ray::new(point(0., -1., -2.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&r), None);
+
+        /* A ray strikes a triangle */
+        let r = Ray::new(point(0., 0.5, -2.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&r).unwrap().t, 2.);
+
+        // nothing behind the origin
+        let r = Ray::new(point(0., 0.5, 2.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&r), None);
+    }
+
+    #[test]
+    fn interpolating_normals_and_uvs() {
+        let mut triangle = book_triangle();
+        triangle.normals = vec![normal(0., 1., 0.), normal(-1., 0., 0.), normal(1., 0., 0.)];
+        triangle.uvs = vec![[0.5, 1.], [0., 0.], [1., 0.]];
+        let mesh = TriangleMesh::new(triangle);
+
+        /* An intersection with a smooth triangle stores u/v */
+        let r = Ray::new(point(-0.2, 0.3, -2.), vector(0., 0., 1.));
+        let hit = mesh.intersect(&r).unwrap();
+        assert!((hit.u - 0.45).abs() < 1e-9);
+        assert!((hit.v - 0.25).abs() < 1e-9);
+
+        /* A smooth triangle uses u/v to interpolate the normal */
+        assert_eq!(mesh.normal_at(&hit), normal(-0.5547, 0.83205, 0.));
+        let [u, v] = mesh.uv_at(&hit).unwrap();
+        assert!((u - 0.4).abs() < 1e-9 && (v - 0.3).abs() < 1e-9);
+
+        // flat triangles use their face normal
+        let flat = TriangleMesh::new(book_triangle());
+        let hit = flat.intersect(&r).unwrap();
+        assert_eq!(flat.normal_at(&hit), normal(0., 0., 1.));
+        assert_eq!(flat.uv_at(&hit), None);
+    }
+
+    #[test]
+    fn the_hierarchy_finds_the_closest_hit() {
+        let mesh = TriangleMesh::new(terrain(40));
+        assert_eq!(mesh.mesh().triangle_count(), 3200);
+        assert!(mesh.nodes.len() > 3200 / MAX_LEAF);
+
+        let (min, max) = mesh.bounds().unwrap();
+        assert_eq!((min.x, min.z, max.x, max.z), (0., 0., 40., 40.));
+
+        // rays from all sides, some grazing the surface
+        let mut seed = 12345_u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f64 / (1 << 24) as f64
+        };
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = point(
+                random() * 60. - 10.,
+                random() * 6. - 3.,
+                random() * 60. - 10.,
+            );
+            let target = point(random() * 40., random() * 2. - 1., random() * 40.);
+            let ray = Ray::new(origin, target - origin);
+
+            let found = mesh.intersect(&ray);
+            assert_eq!(found.map(|h| h.t), brute_force(&mesh, &ray).map(|h| h.t));
+            hits += found.is_some() as usize;
+        }
+        assert!(hits > 250);
+
+        // materials follow their triangles through the reordering
+        let ray = Ray::new(point(12.25, 10., 7.5), vector(0., -1., 0.));
+        let hit = mesh.intersect(&ray).unwrap();
+        assert_eq!(mesh.material(hit.triangle), Some(7 * 40 + 12));
+    }
+
+    #[test]
+    fn degenerate_meshes() {
+        let empty = TriangleMesh::new(Mesh::default());
+        let r = Ray::new(point(0., 0., -5.), vector(0., 0., 1.));
+        assert_eq!(empty.intersect(&r), None);
+        assert_eq!(empty.bounds(), None);
+        assert_eq!(empty.material(0), None);
+
+        // many triangles on top of each other can't be split by the
+        // heuristic, but still make a shallow tree
+        let mut stack = book_triangle();
+        stack.triangles = vec![[0, 1, 2]; 1000];
+        let stack = TriangleMesh::new(stack);
+        assert_eq!(stack.intersect(&r).unwrap().t, 5.);
+        assert!(stack.nodes.len() < 1000);
+    }
+
+    #[test]
+    fn trees_stay_within_the_stack() {
+        fn depth(nodes: &[Node], i: usize) -> usize {
+            match nodes[i].count {
+                0 => 1 + depth(nodes, i + 1).max(depth(nodes, nodes[i].index as usize)),
+                _ => 0,
+            }
+        }
+
+        // triangles spaced further and further apart, which the
+        // heuristic splits off a few at a time
+        let mut mesh = Mesh::default();
+        for i in 0..126 {
+            let x = 2_f32.powi(i);
+            let first = mesh.positions.len() as u32;
+            mesh.positions
+                .extend([point(x, 0., 0.), point(x + 0.5, 0., 0.), point(x, 0.5, 0.)]);
+            mesh.triangles.push([first, first + 1, first + 2]);
+        }
+        let boxes: Vec<Bounds> = (0..mesh.triangle_count())
+            .map(|i| {
+                mesh.triangle(i)
+                    .iter()
+                    .fold(Bounds::EMPTY, |b, p| b.grow([p.x, p.y, p.z]))
+            })
+            .collect();
+
+        // past SAH_DEPTH the triangles are halved, so the rest of the tree
+        // is only logarithmic in their number
+        let mut order: Vec<u32> = (0..126).collect();
+        let mut nodes = vec![];
+        subdivide(&mut nodes, &mut order, 0, &boxes, SAH_DEPTH);
+        assert_eq!(depth(&nodes, 0), 5);
+
+        let mesh = TriangleMesh::new(mesh);
+        assert!(depth(&mesh.nodes, 0) <= MAX_DEPTH);
+
+        let ray = Ray::new(point(1.1, 0.1, -1.), vector(0., 0., 1.));
+        assert_eq!(mesh.intersect(&ray).map(|h| h.t), Some(1.));
+    }
+
+    #[test]
+    fn nodes_are_compact() {
+        assert_eq!(std::mem::size_of::<Node>(), 32);
+        assert_eq!(std::mem::size_of::<[u32; 3]>(), 12);
+    }
+}
@@ -0,0 +1,697 @@
+//! A small YAML subset, enough for scene files: block mappings and
+//! sequences, flow `[..]` and `{..}` collections, plain and quoted scalars
+//! and `#` comments. Every node keeps the span it was parsed from so
+//! errors can point at the offending text.
+
+use std::error::Error;
+use std::fmt;
+
+/// A byte range of the source, with the 1-based line and column it starts at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Scalar(String),
+    Seq(Vec<Node>),
+    /// Entries in source order, keys are scalars
+    Map(Vec<(Node, Node)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub value: Value,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_seq(&self) -> Option<&[Node]> {
+        match &self.value {
+            Value::Seq(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(Node, Node)]> {
+        match &self.value {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Value of `key` when this is a mapping that has it
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        self.as_map()?
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    /// What kind of value this is, for error messages
+    pub fn kind(&self) -> &'static str {
+        match self.value {
+            Value::Null => "nothing",
+            Value::Scalar(_) => "a value",
+            Value::Seq(_) => "a list",
+            Value::Map(_) => "a mapping",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+impl Error for YamlError {}
+
+/// Parses a document; an empty document is `Value::Null`
+pub fn parse(src: &str) -> Result<Node, YamlError> {
+    let mut parser = Parser::new(src)?;
+
+    if parser.lines.is_empty() {
+        return Ok(Node {
+            value: Value::Null,
+            span: parser.span(0, 0),
+        });
+    }
+
+    let indent = parser.lines[0].indent;
+    let node = parser.block(indent)?;
+
+    match parser.lines.get(parser.pos) {
+        None => Ok(node),
+        Some(line) => Err(parser.error(
+            line.start,
+            line.end,
+            if line.indent > indent {
+                "unexpected indentation"
+            } else {
+                "expected the document to end here"
+            },
+        )),
+    }
+}
+
+// A non-empty line with comments and surrounding spaces removed
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    indent: usize,
+    start: usize,
+    end: usize,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    line_starts: Vec<usize>,
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Result<Self, YamlError> {
+        let mut parser = Parser {
+            src,
+            line_starts: vec![0],
+            lines: vec![],
+            pos: 0,
+        };
+
+        let mut offset = 0;
+        for raw in src.split_inclusive('\n') {
+            let start = offset;
+            offset += raw.len();
+            parser.line_starts.push(offset);
+
+            let text = raw.trim_end_matches(['\n', '\r']);
+            let content = text.trim_start_matches(' ');
+            let indent = text.len() - content.len();
+
+            if content.starts_with('\t') {
+                let at = start + indent;
+                return Err(parser.error(at, at + 1, "tabs are not allowed for indentation"));
+            }
+
+            let content = strip_comment(content).trim_end();
+            if content.is_empty() || content == "---" || content == "..." {
+                continue;
+            }
+
+            parser.lines.push(Line {
+                indent,
+                start: start + indent,
+                end: start + indent + content.len(),
+            });
+        }
+
+        Ok(parser)
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        let line = match self.line_starts.binary_search(&start) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+
+        Span {
+            start,
+            end,
+            line: line + 1,
+            column: self.src[self.line_starts[line]..start].chars().count() + 1,
+        }
+    }
+
+    fn error(&self, start: usize, end: usize, message: &str) -> YamlError {
+        YamlError {
+            message: message.to_string(),
+            span: self.span(start, end),
+        }
+    }
+
+    /// The end of the character starting at `at`
+    fn char_end(&self, at: usize) -> usize {
+        at + self.src[at..].chars().next().map_or(1, char::len_utf8)
+    }
+
+    fn text(&self, line: &Line) -> &'a str {
+        &self.src[line.start..line.end]
+    }
+
+    fn is_item(&self, line: &Line) -> bool {
+        let text = self.text(line);
+        text == "-" || text.starts_with("- ")
+    }
+
+    // Parses the block starting at the current line, which is at `indent`
+    fn block(&mut self, indent: usize) -> Result<Node, YamlError> {
+        let line = self.lines[self.pos];
+
+        if self.is_item(&line) {
+            self.seq(indent)
+        } else if key_colon(self.text(&line)).is_some() {
+            self.map(indent)
+        } else {
+            self.pos += 1;
+            self.inline(line.start, line.end)
+        }
+    }
+
+    fn seq(&mut self, indent: usize) -> Result<Node, YamlError> {
+        let start = self.lines[self.pos].start;
+        let mut items = vec![];
+
+        while let Some(&line) = self.lines.get(self.pos) {
+            if line.indent != indent || !self.is_item(&line) {
+                break;
+            }
+
+            let rest = self.text(&line)[1..].trim_start();
+            let item = if rest.is_empty() {
+                self.pos += 1;
+                match self.lines.get(self.pos) {
+                    Some(next) if next.indent > indent => self.block(next.indent)?,
+                    _ => Node {
+                        value: Value::Null,
+                        span: self.span(line.start, line.start + 1),
+                    },
+                }
+            } else {
+                // continue with the item's content as if it were indented
+                // to where it starts, so `- key: value` opens a mapping
+                let offset = line.end - line.start - rest.len();
+                self.lines[self.pos] = Line {
+                    indent: indent + offset,
+                    start: line.start + offset,
+                    end: line.end,
+                };
+                self.block(indent + offset)?
+            };
+
+            items.push(item);
+        }
+
+        let end = items.last().map_or(start + 1, |item| item.span.end);
+        Ok(Node {
+            value: Value::Seq(items),
+            span: self.span(start, end),
+        })
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Node, YamlError> {
+        let start = self.lines[self.pos].start;
+        let mut entries: Vec<(Node, Node)> = vec![];
+
+        while let Some(&line) = self.lines.get(self.pos) {
+            if line.indent != indent || self.is_item(&line) {
+                break;
+            }
+
+            let text = self.text(&line);
+            let colon = match key_colon(text) {
+                Some(colon) => colon,
+                None => return Err(self.error(line.start, line.end, "expected `key: value`")),
+            };
+
+            let key_text = text[..colon].trim_end();
+            let key = Node {
+                value: Value::Scalar(unquote(key_text).to_string()),
+                span: self.span(line.start, line.start + key_text.len()),
+            };
+
+            if let Some((existing, _)) = entries.iter().find(|(k, _)| k.value == key.value) {
+                return Err(YamlError {
+                    message: format!(
+                        "duplicate key `{}`, first defined on line {}",
+                        key_text, existing.span.line
+                    ),
+                    span: key.span,
+                });
+            }
+
+            let rest = text[colon + 1..].trim_start();
+            let value = if rest.is_empty() {
+                self.pos += 1;
+                match self.lines.get(self.pos) {
+                    Some(next) if next.indent > indent => self.block(next.indent)?,
+                    // a list may sit at the same indentation as its key
+                    Some(next) if next.indent == indent && self.is_item(next) => {
+                        self.seq(indent)?
+                    }
+                    _ => Node {
+                        value: Value::Null,
+                        span: self.span(line.start + colon, line.start + colon + 1),
+                    },
+                }
+            } else {
+                self.pos += 1;
+                self.inline(line.end - rest.len(), line.end)?
+            };
+
+            entries.push((key, value));
+        }
+
+        let end = entries.last().map_or(start, |(_, v)| v.span.end);
+        Ok(Node {
+            value: Value::Map(entries),
+            span: self.span(start, end),
+        })
+    }
+
+    // A value written on one line, or a flow collection that may continue
+    // on the following lines
+    fn inline(&mut self, start: usize, end: usize) -> Result<Node, YamlError> {
+        let text = &self.src[start..end];
+
+        if !(text.starts_with('[') || text.starts_with('{')) {
+            return self.scalar(start, end);
+        }
+
+        let (node, after) = self.flow(start)?;
+
+        // skip the lines the collection spanned
+        while self.pos < self.lines.len() && self.lines[self.pos].start < after {
+            self.pos += 1;
+        }
+
+        let line_end = self.src[after..]
+            .find('\n')
+            .map_or(self.src.len(), |i| after + i);
+        let trailing = strip_comment(&self.src[after..line_end]).trim();
+        if !trailing.is_empty() {
+            let at = after + self.src[after..].len() - self.src[after..].trim_start().len();
+            return Err(self.error(at, at + trailing.len(), "unexpected text after the value"));
+        }
+
+        Ok(node)
+    }
+
+    fn scalar(&self, start: usize, end: usize) -> Result<Node, YamlError> {
+        let text = &self.src[start..end];
+
+        let value = match text.chars().next() {
+            Some(q @ '"') | Some(q @ '\'') => {
+                let (value, len) = self.quoted(start, q)?;
+                if len != text.len() {
+                    return Err(self.error(start + len, end, "unexpected text after the value"));
+                }
+                value
+            }
+            _ => text.to_string(),
+        };
+
+        Ok(Node {
+            value: Value::Scalar(value),
+            span: self.span(start, end),
+        })
+    }
+
+    // Reads a quoted string at `start`, returning it and its length in the
+    // source including the quotes
+    fn quoted(&self, start: usize, quote: char) -> Result<(String, usize), YamlError> {
+        let mut value = String::new();
+        let mut chars = self.src[start + 1..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => return Ok((value, i + 2)),
+                '\n' => break,
+                '\\' if quote == '"' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+
+        Err(self.error(start, start + 1, "unclosed quote"))
+    }
+
+    // Parses a flow value at `i`, returning it and the offset after it
+    fn flow(&self, i: usize) -> Result<(Node, usize), YamlError> {
+        let i = self.skip_space(i);
+        let bytes = self.src.as_bytes();
+
+        match bytes.get(i) {
+            Some(b'[') => {
+                let mut items = vec![];
+                let mut at = self.skip_space(i + 1);
+
+                loop {
+                    match bytes.get(at) {
+                        Some(b']') => break,
+                        None => return Err(self.error(i, i + 1, "unclosed `[`")),
+                        _ => {}
+                    }
+
+                    let (item, after) = self.flow(at)?;
+                    items.push(item);
+                    at = self.skip_space(after);
+
+                    match bytes.get(at) {
+                        Some(b',') => at = self.skip_space(at + 1),
+                        Some(b']') => {}
+                        // the line ended without closing the collection
+                        _ if self.src[after..at].contains('\n') => {
+                            return Err(self.error(i, i + 1, "unclosed `[`"))
+                        }
+                        None => return Err(self.error(i, i + 1, "unclosed `[`")),
+                        _ => return Err(self.error(at, self.char_end(at), "expected `,` or `]`")),
+                    }
+                }
+
+                Ok((
+                    Node {
+                        value: Value::Seq(items),
+                        span: self.span(i, at + 1),
+                    },
+                    at + 1,
+                ))
+            }
+            Some(b'{') => {
+                let mut entries = vec![];
+                let mut at = self.skip_space(i + 1);
+
+                loop {
+                    match bytes.get(at) {
+                        Some(b'}') => break,
+                        None => return Err(self.error(i, i + 1, "unclosed `{`")),
+                        _ => {}
+                    }
+
+                    let key_end = self.src[at..]
+                        .find([':', ',', '}', '\n'])
+                        .map_or(self.src.len(), |n| at + n);
+                    if bytes.get(key_end) != Some(&b':') {
+                        return Err(self.error(at, key_end, "expected `key: value`"));
+                    }
+
+                    let key_text = self.src[at..key_end].trim_end();
+                    let key = Node {
+                        value: Value::Scalar(unquote(key_text).to_string()),
+                        span: self.span(at, at + key_text.len()),
+                    };
+                    let (value, after) = self.flow(key_end + 1)?;
+                    entries.push((key, value));
+                    at = self.skip_space(after);
+
+                    match bytes.get(at) {
+                        Some(b',') => at = self.skip_space(at + 1),
+                        Some(b'}') => {}
+                        // the line ended without closing the collection
+                        _ if self.src[after..at].contains('\n') => {
+                            return Err(self.error(i, i + 1, "unclosed `{`"))
+                        }
+                        None => return Err(self.error(i, i + 1, "unclosed `{`")),
+                        _ => return Err(self.error(at, self.char_end(at), "expected `,` or `}`")),
+                    }
+                }
+
+                Ok((
+                    Node {
+                        value: Value::Map(entries),
+                        span: self.span(i, at + 1),
+                    },
+                    at + 1,
+                ))
+            }
+            Some(&q) if q == b'"' || q == b'\'' => {
+                let (value, len) = self.quoted(i, q as char)?;
+                Ok((
+                    Node {
+                        value: Value::Scalar(value),
+                        span: self.span(i, i + len),
+                    },
+                    i + len,
+                ))
+            }
+            _ => {
+                let end = self.src[i..]
+                    .find([',', ']', '}', '\n', '#'])
+                    .map_or(self.src.len(), |n| i + n);
+                let text = self.src[i..end].trim_end();
+
+                Ok((
+                    Node {
+                        value: Value::Scalar(text.to_string()),
+                        span: self.span(i, i + text.len()),
+                    },
+                    i + text.len(),
+                ))
+            }
+        }
+    }
+
+    // Skips whitespace, newlines and comments inside flow collections
+    fn skip_space(&self, mut i: usize) -> usize {
+        let bytes = self.src.as_bytes();
+
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b' ' | b'\t' | b'\r' | b'\n' => i += 1,
+                b'#' => {
+                    i = self.src[i..].find('\n').map_or(self.src.len(), |n| i + n);
+                }
+                _ => break,
+            }
+        }
+
+        i
+    }
+}
+
+// Removes a `#` comment, which starts a line or follows a space, unless it
+// is quoted
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && prev == ' ' => return &text[..i],
+            None => {}
+        }
+        prev = c;
+    }
+
+    text
+}
+
+// Position of the colon ending a mapping key, which is followed by a
+// space or ends the line
+fn key_colon(text: &str) -> Option<usize> {
+    if text.starts_with('[') || text.starts_with('{') {
+        return None;
+    }
+
+    let mut quote = None;
+    let bytes = text.as_bytes();
+
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if (c == '"' || c == '\'') && i == 0 => quote = Some(c),
+            None if c == ':' && matches!(bytes.get(i + 1), None | Some(b' ')) => return Some(i),
+            None => {}
+        }
+    }
+
+    None
+}
+
+fn unquote(text: &str) -> &str {
+    let quoted = text.len() >= 2
+        && ((text.starts_with('"') && text.ends_with('"'))
+            || (text.starts_with('\'') && text.ends_with('\'')));
+
+    if quoted {
+        &text[1..text.len() - 1]
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalars(node: &Node) -> Vec<&str> {
+        node.as_seq()
+            .unwrap()
+            .iter()
+            .map(|n| n.as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parsing_block_collections() {
+        let src = "\
+# a scene
+- add: camera
+  width: 100
+  from: [ -6, 6, -10 ]   # eye
+
+- define: std-transform
+  value:
+  - [ translate, 1, -1, 1 ]
+  - [ scale, 0.5, 0.5, 0.5 ]
+";
+        let doc = parse(src).unwrap();
+        let items = doc.as_seq().unwrap();
+        assert_eq!(items.len(), 2);
+
+        let camera = &items[0];
+        assert_eq!(camera.get("add").unwrap().as_str(), Some("camera"));
+        assert_eq!(camera.get("width").unwrap().as_str(), Some("100"));
+        assert_eq!(scalars(camera.get("from").unwrap()), vec!["-6", "6", "-10"]);
+
+        let value = items[1].get("value").unwrap().as_seq().unwrap();
+        assert_eq!(scalars(&value[0]), vec!["translate", "1", "-1", "1"]);
+        assert_eq!(scalars(&value[1]), vec!["scale", "0.5", "0.5", "0.5"]);
+    }
+
+    #[test]
+    fn parsing_flow_collections_and_quotes() {
+        let src = "\
+material: { color: [1, 0.5, 0], diffuse: 0.7 }
+name: \"a # not a comment\"
+other: 'single'
+nested:
+  - - 1
+    - 2
+  - [3, [4, 5]]
+multi: [ 1,
+         2 ]
+empty:
+";
+        let doc = parse(src).unwrap();
+
+        let material = doc.get("material").unwrap();
+        assert_eq!(
+            scalars(material.get("color").unwrap()),
+            vec!["1", "0.5", "0"]
+        );
+        assert_eq!(material.get("diffuse").unwrap().as_str(), Some("0.7"));
+        assert_eq!(doc.get("name").unwrap().as_str(), Some("a # not a comment"));
+        assert_eq!(doc.get("other").unwrap().as_str(), Some("single"));
+
+        let nested = doc.get("nested").unwrap().as_seq().unwrap();
+        assert_eq!(scalars(&nested[0]), vec!["1", "2"]);
+        assert_eq!(nested[1].as_seq().unwrap()[0].as_str(), Some("3"));
+
+        assert_eq!(scalars(doc.get("multi").unwrap()), vec!["1", "2"]);
+        assert_eq!(doc.get("empty").unwrap().value, Value::Null);
+    }
+
+    #[test]
+    fn nodes_remember_where_they_came_from() {
+        let src = "- add: sphere\n  material:\n    diffuse: 0.5\n";
+        let doc = parse(src).unwrap();
+        let diffuse = doc.as_seq().unwrap()[0]
+            .get("material")
+            .unwrap()
+            .get("diffuse")
+            .unwrap();
+
+        assert_eq!(diffuse.span.line, 3);
+        assert_eq!(diffuse.span.column, 14);
+        assert_eq!(&src[diffuse.span.start..diffuse.span.end], "0.5");
+    }
+
+    #[test]
+    fn reporting_syntax_errors() {
+        let err = parse("a: [1, 2\nb: 3\n").unwrap_err();
+        assert_eq!(err.message, "unclosed `[`");
+        assert_eq!((err.span.line, err.span.column), (1, 4));
+
+        let err = parse("a: 1\na: 2\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2:1: duplicate key `a`, first defined on line 1"
+        );
+
+        let err = parse("a: 1\n    b: 2\n").unwrap_err();
+        assert_eq!(err.message, "unexpected indentation");
+
+        let err = parse("a:\n\t- 1\n").unwrap_err();
+        assert_eq!(err.message, "tabs are not allowed for indentation");
+
+        let err = parse("a: \"open\n").unwrap_err();
+        assert_eq!(err.message, "unclosed quote");
+
+        assert_eq!(parse("# nothing\n").unwrap().value, Value::Null);
+
+        let err = parse("a: [[]é\n").unwrap_err();
+        assert_eq!(err.message, "expected `,` or `]`");
+        assert_eq!((err.span.start, err.span.end), (6, 8));
+    }
+}
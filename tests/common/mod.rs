@@ -0,0 +1,289 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rays_core::canvas::*;
+use rays_core::diff::ImageDiff;
+use rays_core::image::{ExportOptions, ImageError, Transfer};
+
+/// Set to a non-empty value other than "0" to overwrite references with the
+/// rendered images instead of comparing against them
+pub const BLESS_VAR: &str = "RAYS_BLESS";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Largest allowed channel difference of any pixel
+    pub max_error: f64,
+    pub rmse: f64,
+    pub min_ssim: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            max_error: 0.02,
+            rmse: 0.002,
+            min_ssim: 0.99,
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn exact() -> Self {
+        Tolerance {
+            max_error: 0.0,
+            rmse: 0.0,
+            min_ssim: 1.0,
+        }
+    }
+
+    pub fn accepts(&self, diff: &ImageDiff) -> bool {
+        diff.max_error <= self.max_error && diff.rmse <= self.rmse && diff.ssim >= self.min_ssim
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    MissingReference(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    Mismatch {
+        diff: Box<ImageDiff>,
+        output: PathBuf,
+    },
+    Io(io::Error),
+    Image(ImageError),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::MissingReference(path) => write!(
+                f,
+                "no reference image at {}, run with {}=1 to create it",
+                path.display(),
+                BLESS_VAR
+            ),
+            GoldenError::SizeMismatch { expected, found } => write!(
+                f,
+                "expected a {}x{} image, rendered {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            GoldenError::Mismatch { diff, output } => write!(
+                f,
+                "image differs from reference (max error {:.5}, rmse {:.5}, \
+                 psnr {:.2} dB, ssim {:.5}, {} pixels differ); \
+                 actual, expected and diff images written to {}",
+                diff.max_error,
+                diff.rmse,
+                diff.psnr,
+                diff.ssim,
+                diff.pixels_above(0.0),
+                output.display()
+            ),
+            GoldenError::Io(e) => write!(f, "{}", e),
+            GoldenError::Image(e) => write!(f, "could not read reference: {}", e),
+        }
+    }
+}
+
+impl Error for GoldenError {}
+
+impl From<io::Error> for GoldenError {
+    fn from(e: io::Error) -> Self {
+        GoldenError::Io(e)
+    }
+}
+
+impl From<ImageError> for GoldenError {
+    fn from(e: ImageError) -> Self {
+        GoldenError::Image(e)
+    }
+}
+
+/// Compares rendered canvases against reference images stored as binary
+/// PPMs named `<name>.ppm`
+#[derive(Debug, Clone)]
+pub struct Golden {
+    pub reference_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub tolerance: Tolerance,
+    pub bless: bool,
+}
+
+impl Default for Golden {
+    /// References in `tests/golden`, failures written to `target/golden`
+    fn default() -> Self {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let target = env::var_os("CARGO_TARGET_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| root.join("target"));
+
+        Golden {
+            reference_dir: root.join("tests").join("golden"),
+            output_dir: target.join("golden"),
+            tolerance: Tolerance::default(),
+            bless: env::var(BLESS_VAR).is_ok_and(|v| !v.is_empty() && v != "0"),
+        }
+    }
+}
+
+impl Golden {
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn reference_path(&self, name: &str) -> PathBuf {
+        self.reference_dir.join(format!("{}.ppm", name))
+    }
+
+    pub fn check(&self, name: &str, actual: &Canvas) -> Result<(), GoldenError> {
+        let options = ExportOptions::default();
+        let encoded = actual.to_ppm_binary(&options);
+        let reference = self.reference_path(name);
+
+        if self.bless {
+            fs::create_dir_all(&self.reference_dir)?;
+            fs::write(&reference, &encoded)?;
+            return Ok(());
+        }
+
+        let expected = match fs::read(&reference) {
+            Ok(data) => Canvas::from_ppm(&data, Transfer::Srgb)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                self.write_output(name, "actual", &encoded)?;
+                return Err(GoldenError::MissingReference(reference));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // compare what would be stored, so an unchanged render matches exactly
+        let actual = Canvas::from_ppm(&encoded, Transfer::Srgb)?;
+
+        let diff = match actual.diff(&expected) {
+            Ok(diff) => diff,
+            Err(_) => {
+                self.write_output(name, "actual", &encoded)?;
+                return Err(GoldenError::SizeMismatch {
+                    expected: (expected.width, expected.height),
+                    found: (actual.width, actual.height),
+                });
+            }
+        };
+
+        if self.tolerance.accepts(&diff) {
+            return Ok(());
+        }
+
+        self.write_output(name, "actual", &encoded)?;
+        self.write_output(name, "expected", &expected.to_ppm_binary(&options))?;
+        self.write_output(name, "diff", &diff.heatmap(None).to_ppm_binary(&options))?;
+
+        Err(GoldenError::Mismatch {
+            diff: Box::new(diff),
+            output: self.output_dir.clone(),
+        })
+    }
+
+    fn write_output(&self, name: &str, kind: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.output_dir)?;
+        fs::write(self.output_dir.join(format!("{}.{}.ppm", name, kind)), data)
+    }
+}
+
+/// Checks `actual` against the reference named `name` with the default
+/// settings, panicking with a description of any difference
+pub fn assert_golden(name: &str, actual: &Canvas) {
+    if let Err(e) = Golden::default().check(name, actual) {
+        panic!("golden image '{}': {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rays_core::color::Color;
+
+    fn golden(dir: &str) -> Golden {
+        let tmp = env::temp_dir().join(format!("rays-golden-{}-{}", dir, std::process::id()));
+        let _ = fs::remove_dir_all(&tmp);
+
+        Golden {
+            reference_dir: tmp.join("refs"),
+            output_dir: tmp.join("out"),
+            tolerance: Tolerance::default(),
+            bless: false,
+        }
+    }
+
+    fn gradient(shift: f64) -> Canvas {
+        Canvas::from_fn(32, 16, |x, y| {
+            Color::new(x as f64 / 32. + shift, y as f64 / 16., 0.25)
+        })
+    }
+
+    #[test]
+    fn missing_reference_fails_and_writes_actual() {
+        let g = golden("missing");
+
+        match g.check("scene", &gradient(0.)) {
+            Err(GoldenError::MissingReference(path)) => {
+                assert_eq!(path, g.reference_path("scene"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(g.output_dir.join("scene.actual.ppm").exists());
+    }
+
+    #[test]
+    fn blessing_then_comparing() {
+        let mut g = golden("bless");
+
+        g.bless = true;
+        g.check("scene", &gradient(0.)).unwrap();
+        assert!(g.reference_path("scene").exists());
+
+        g.bless = false;
+        g.with_tolerance(Tolerance::exact())
+            .check("scene", &gradient(0.))
+            .unwrap();
+    }
+
+    #[test]
+    fn differences_are_reported_with_images() {
+        let mut g = golden("mismatch");
+
+        g.bless = true;
+        g.check("scene", &gradient(0.)).unwrap();
+        g.bless = false;
+
+        // tiny shifts are tolerated, large ones are not
+        g.check("scene", &gradient(0.001)).unwrap();
+
+        match g.check("scene", &gradient(0.2)) {
+            Err(GoldenError::Mismatch { diff, output }) => {
+                assert!(diff.max_error > 0.1);
+                assert_eq!(output, g.output_dir);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        for kind in &["actual", "expected", "diff"] {
+            assert!(g.output_dir.join(format!("scene.{}.ppm", kind)).exists());
+        }
+
+        match g.check("scene", &Canvas::new(4, 4, None)) {
+            Err(GoldenError::SizeMismatch { expected, found }) => {
+                assert_eq!(expected, (32, 16));
+                assert_eq!(found, (4, 4));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use common::assert_golden;
+use rays::scenes::{self, SCENES};
+
+#[test]
+fn scenes_match_reference_images() {
+    for scene in SCENES {
+        // keep the checked in references small
+        let scale = (200. / scene.width.max(scene.height) as f64).min(1.);
+        let width = (scene.width as f64 * scale).round() as u32;
+        let height = (scene.height as f64 * scale).round() as u32;
+
+        assert_golden(scene.name, &scene.render_at(width, height));
+    }
+}
+
+#[test]
+fn finding_scenes_by_name() {
+    assert_eq!(scenes::find("clock").map(|s| s.name), Some("clock"));
+    assert!(scenes::find("teapot").is_none());
+}